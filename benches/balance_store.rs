@@ -0,0 +1,48 @@
+//! Compares the default cached store with a plain `HashMap` on bursty (same client in
+//! a row) and interleaved feeds.
+
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tx_engine_example::{
+    balance_store::BalanceStore,
+    domain::types::ClientId,
+    io::input::Transaction,
+    test_utils::client_feed,
+    tx_engine::{ClientData, TxEngine},
+};
+
+fn process<S: BalanceStore>(mut engine: TxEngine<S>, txs: &[Transaction]) -> TxEngine<S> {
+    for tx in txs {
+        engine
+            .process_transaction(tx)
+            .expect("every feed row applies");
+    }
+    engine
+}
+
+fn bursty_vs_interleaved(c: &mut Criterion) {
+    for (label, bursty) in [("bursty", true), ("interleaved", false)] {
+        let txs = client_feed(1_000, 200, bursty);
+        let mut group = c.benchmark_group(label);
+        group.sample_size(10);
+        group.bench_function("cached", |b| {
+            b.iter_batched(
+                TxEngine::new,
+                |engine| process(engine, &txs),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("hashmap", |b| {
+            b.iter_batched(
+                || TxEngine::builder().build_with_store(HashMap::<ClientId, ClientData>::new()),
+                |engine| process(engine, &txs),
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bursty_vs_interleaved);
+criterion_main!(benches);
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::{domain::types::ClientId, tx_engine::ClientData};
+
+/// Storage for per-client engine state. The engine only talks to clients through this
+/// trait, so the default in-memory `HashMap` can be swapped for another backend
+/// (e.g. a disk-backed store) without touching the transaction logic.
+pub trait BalanceStore<M = Decimal> {
+    type Iter<'a>: Iterator<Item = (&'a ClientId, &'a ClientData<M>)>
+    where
+        Self: 'a,
+        M: 'a;
+
+    fn get(&self, client: &ClientId) -> Option<&ClientData<M>>;
+
+    fn get_mut(&mut self, client: &ClientId) -> Option<&mut ClientData<M>>;
+
+    fn insert(&mut self, client: ClientId, data: ClientData<M>);
+
+    fn remove(&mut self, client: &ClientId) -> Option<ClientData<M>>;
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    fn get_or_insert_with<F>(&mut self, client: ClientId, init: F) -> &mut ClientData<M>
+    where
+        F: FnOnce() -> ClientData<M>,
+    {
+        if self.get(&client).is_none() {
+            self.insert(client, init());
+        }
+        self.get_mut(&client)
+            .expect("client data must exist right after insertion")
+    }
+}
+
+impl<M> BalanceStore<M> for HashMap<ClientId, ClientData<M>> {
+    type Iter<'a>
+        = std::collections::hash_map::Iter<'a, ClientId, ClientData<M>>
+    where
+        M: 'a;
+
+    fn get(&self, client: &ClientId) -> Option<&ClientData<M>> {
+        HashMap::get(self, client)
+    }
+
+    fn get_mut(&mut self, client: &ClientId) -> Option<&mut ClientData<M>> {
+        HashMap::get_mut(self, client)
+    }
+
+    fn insert(&mut self, client: ClientId, data: ClientData<M>) {
+        HashMap::insert(self, client, data);
+    }
+
+    fn remove(&mut self, client: &ClientId) -> Option<ClientData<M>> {
+        HashMap::remove(self, client)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        HashMap::iter(self)
+    }
+
+    fn get_or_insert_with<F>(&mut self, client: ClientId, init: F) -> &mut ClientData<M>
+    where
+        F: FnOnce() -> ClientData<M>,
+    {
+        self.entry(client).or_insert_with(init)
+    }
+}
+
+/// Default store. Clients live in a `Vec` indexed by a `HashMap`, and the slot of the
+/// last client accessed through `get_mut` or `insert` is remembered, so a burst of
+/// transactions for the same client skips the hash lookup. The cache is only written
+/// through `&mut self`, which keeps the store `Sync`. Removing a client moves the last
+/// one into its slot and forgets the cached slot.
+pub struct CachedClientStore<M = Decimal> {
+    index: HashMap<ClientId, usize>,
+    clients: Vec<(ClientId, ClientData<M>)>,
+    last: Option<(ClientId, usize)>,
+}
+
+impl<M> Default for CachedClientStore<M> {
+    fn default() -> Self {
+        CachedClientStore {
+            index: HashMap::new(),
+            clients: Vec::new(),
+            last: None,
+        }
+    }
+}
+
+impl<M> CachedClientStore<M> {
+    fn slot(&self, client: &ClientId) -> Option<usize> {
+        match self.last {
+            Some((last_client, slot)) if last_client == *client => Some(slot),
+            _ => self.index.get(client).copied(),
+        }
+    }
+
+    /// Like `slot`, and remembers the slot found for the next lookup.
+    fn cache_slot(&mut self, client: &ClientId) -> Option<usize> {
+        let slot = self.slot(client)?;
+        self.last = Some((*client, slot));
+        Some(slot)
+    }
+}
+
+impl<M> BalanceStore<M> for CachedClientStore<M> {
+    type Iter<'a>
+        = std::iter::Map<
+        std::slice::Iter<'a, (ClientId, ClientData<M>)>,
+        fn(&'a (ClientId, ClientData<M>)) -> (&'a ClientId, &'a ClientData<M>),
+    >
+    where
+        M: 'a;
+
+    fn get(&self, client: &ClientId) -> Option<&ClientData<M>> {
+        self.slot(client).map(|slot| &self.clients[slot].1)
+    }
+
+    fn get_mut(&mut self, client: &ClientId) -> Option<&mut ClientData<M>> {
+        self.cache_slot(client)
+            .map(|slot| &mut self.clients[slot].1)
+    }
+
+    fn insert(&mut self, client: ClientId, data: ClientData<M>) {
+        match self.cache_slot(&client) {
+            Some(slot) => self.clients[slot].1 = data,
+            None => {
+                let slot = self.clients.len();
+                self.clients.push((client, data));
+                self.index.insert(client, slot);
+                self.last = Some((client, slot));
+            }
+        }
+    }
+
+    fn remove(&mut self, client: &ClientId) -> Option<ClientData<M>> {
+        let slot = self.index.remove(client)?;
+        self.last = None;
+        let (_, data) = self.clients.swap_remove(slot);
+        if let Some((moved, _)) = self.clients.get(slot) {
+            self.index.insert(*moved, slot);
+        }
+        Some(data)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.clients.iter().map(|(client, data)| (client, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, TransactionType};
+    use crate::test_utils::{client_feed, make_tx};
+    use crate::tx_engine::TxEngine;
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct BTreeStore(BTreeMap<u16, (ClientId, ClientData)>);
+
+    impl BalanceStore for BTreeStore {
+        type Iter<'a> = std::iter::Map<
+            std::collections::btree_map::Values<'a, u16, (ClientId, ClientData)>,
+            fn(&'a (ClientId, ClientData)) -> (&'a ClientId, &'a ClientData),
+        >;
+
+        fn get(&self, client: &ClientId) -> Option<&ClientData> {
+            self.0.get(&client.0).map(|(_, data)| data)
+        }
+
+        fn get_mut(&mut self, client: &ClientId) -> Option<&mut ClientData> {
+            self.0.get_mut(&client.0).map(|(_, data)| data)
+        }
+
+        fn insert(&mut self, client: ClientId, data: ClientData) {
+            self.0.insert(client.0, (client, data));
+        }
+
+        fn remove(&mut self, client: &ClientId) -> Option<ClientData> {
+            self.0.remove(&client.0).map(|(_, data)| data)
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            self.0.values().map(|(client, data)| (client, data))
+        }
+    }
+
+    #[test]
+    fn custom_store_produces_identical_snapshots() {
+        let workload = [
+            make_tx(TransactionType::Deposit, 2, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(3.0)))),
+            make_tx(
+                TransactionType::Withdrawal,
+                2,
+                3,
+                Some(Amount::new(dec!(1.5))),
+            ),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(TransactionType::Deposit, 3, 4, Some(Amount::new(dec!(4.0)))),
+            make_tx(TransactionType::Dispute, 3, 4, None),
+            make_tx(TransactionType::Chargeback, 3, 4, None),
+            make_tx(
+                TransactionType::Withdrawal,
+                9,
+                5,
+                Some(Amount::new(dec!(1.0))),
+            ),
+        ];
+        let mut default_engine = TxEngine::new();
+        let mut btree_engine = TxEngine::builder().build_with_store(BTreeStore::default());
+
+        for tx in &workload {
+            let default_result = default_engine.process_transaction(tx);
+            let btree_result = btree_engine.process_transaction(tx);
+            assert_eq!(default_result.is_ok(), btree_result.is_ok());
+        }
+
+        assert_eq!(
+            default_engine.clients_snapshot(),
+            btree_engine.clients_snapshot()
+        );
+        assert_eq!(default_engine.totals(), btree_engine.totals());
+    }
+
+    #[test]
+    fn cached_store_matches_hashmap_on_bursty_and_interleaved_feeds() {
+        for bursty in [true, false] {
+            let mut cached_engine = TxEngine::new();
+            let mut map_engine =
+                TxEngine::builder().build_with_store(HashMap::<ClientId, ClientData>::new());
+
+            for tx in &client_feed(5, 12, bursty) {
+                cached_engine.process_transaction(tx).unwrap();
+                map_engine.process_transaction(tx).unwrap();
+            }
+
+            assert_eq!(
+                cached_engine.clients_snapshot(),
+                map_engine.clients_snapshot()
+            );
+            assert_eq!(cached_engine.held_breakdown(), map_engine.held_breakdown());
+        }
+    }
+
+    #[test]
+    fn default_engine_is_sync() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<TxEngine>();
+    }
+}
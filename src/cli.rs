@@ -0,0 +1,526 @@
+use crate::{
+    domain::errors::AppError,
+    io::{
+        generate::GenerateOptions,
+        input::{InputFormat, TrimMode},
+        output::{JsonAmounts, OutputFormat},
+    },
+    tx_engine::{
+        ChargebackPolicy, DedupScope, DuplicateTxPolicy, IdenticalRowScope, LifecycleAmountPolicy,
+        ResolveScope,
+    },
+};
+
+const USAGE: &str = "\
+Usage: cargo run -- [OPTIONS] <transactions.csv>
+       cargo run -- generate [GENERATE OPTIONS] > transactions.csv
+       cargo run -- merge-output <snapshot.csv>... > merged.csv
+       cargo run -- compare [OPTIONS] <first.csv> <second.csv>
+       cargo run -- batches [OPTIONS] <transactions.csv>...
+
+Options:
+  --config <FILE>              Load settings from a TOML file; flags override it
+  --input-format <csv|bin>     Input file format (default: csv)
+  --no-header                  The CSV input has no header row
+  --encode-bin <FILE>          Convert the CSV input to the binary format and exit
+  --format <FORMAT>            Snapshot as csv (default), a json array or jsonl, one object
+                               per line
+  --no-total                   Omit the total column from the output
+  --auto-scale                 Print amounts with the most decimal places found in the input
+  --integer-cents              Print amounts as integer cents, e.g. 350 for 3.50; fails if a
+                               balance has a fraction of a cent
+  --print-schema               Print the output columns and format version, then exit
+  --held-detail                Also print the open disputes making up each held amount
+  --out <FILE>                 Write the snapshot to FILE instead of stdout
+  --error-on-rounding-loss     Fail if printed amounts are rounded away from exact values
+  --self-check                 Fail if the final balances violate an internal invariant
+  --partial-on-read-error      If reading the input fails, print the snapshot so far, then fail
+  --require-increasing-tx      Fail if deposit/withdrawal tx ids are not strictly increasing
+  --strict                     Fail on the first transaction that would be skipped
+  --two-pass                   Apply all deposits/withdrawals before disputes, resolves, chargebacks
+  --lossy-utf8                 Replace invalid UTF-8 in rows instead of failing
+  --max-field-len <N>          Fail on any CSV field longer than N bytes
+  --delimiter <CHAR>           CSV field delimiter (default: ,)
+  --decimal-comma              Read a comma in amounts as the decimal separator, e.g. 5,50
+  --pad-short-records          Read a row that lacks the amount column as having an empty amount
+  --trim <MODE>                Strip whitespace from all (default), headers, fields or none
+  --seed-snapshot <FILE>       Open the accounts of a snapshot CSV from an earlier run first
+  --allow-withdrawal-disputes  Allow disputes on withdrawals
+  --duplicate-tx <POLICY>      reject (default), ignore-second or error
+  --dedup-scope <SCOPE>        Tx ids are unique across all clients (global, default) or
+                               within each client (per-client)
+  --chargeback <POLICY>        remove-funds (default) or return-to-available
+  --resolve-scope <SCOPE>      A resolve closes all partial disputes of a tx (transaction,
+                               default) or only the oldest one (dispute)
+  --lifecycle-amount <POLICY>  ignore (default) or strict: reject a resolve/chargeback whose
+                               amount differs from what it releases
+  --max-open-disputes <N>      Reject disputes beyond N open per client
+  --dedup-identical <SCOPE>    Skip rows identical to the previous row or to any earlier row
+  --negatives-are-withdrawals  Treat negative-amount deposits as withdrawals
+  --amount-scale <N>           Cut every amount to N decimal places before applying it
+  --system-account <ID>        Client that may go negative and is never locked by a chargeback
+  --hide-system-account        Leave the system account out of the snapshot
+  --panic-safe                 Fail with an error if a transaction panics instead of aborting
+  --metrics-file <FILE>        Write Prometheus metrics to FILE (requires the `metrics` feature)
+  --warnings-json <FILE>       Write each skipped transaction to FILE as a JSON line
+  --annotate <FILE>            Copy the CSV input to FILE with a status column per row
+  --report-json <FILE>         Write a JSON summary of the run to FILE, or to stderr for -
+  --disputes-out <FILE>        Write the open disputes to FILE as client,tx,held_amount CSV
+  --expect <FILE>              Fail unless the snapshot matches FILE in the columns it lists
+  --print-run-config           Print the effective configuration to stderr after processing
+  --http <ADDR>                Serve POST requests with a CSV body, returning JSON balances
+                               (requires the `server` feature)
+  --queue-size <N>             Requests --http reads ahead of the engine worker (default: 16)
+  --json-amounts <MODE>        Write JSON amounts as string (default, exact) or number
+
+Generate options (write a random but valid feed to stdout):
+  --rows <N>                   Number of transactions (default: 1000)
+  --clients <N>                Number of clients, at least 1 (default: 10)
+  --dispute-rate <P>           Chance from 0 to 1 of a dispute-lifecycle row (default: 0.05)
+  --seed <N>                   Random seed; the same seed gives the same file (default: 0)";
+
+/// Command-line arguments. Settings are `None` unless given explicitly so they can
+/// override values loaded from `--config`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliOptions {
+    pub input_path: String,
+    /// Set for the `generate` subcommand, which takes none of the other options.
+    pub generate: Option<GenerateOptions>,
+    /// Snapshot files for the `merge-output` subcommand, which takes no options.
+    pub merge_output: Option<Vec<String>>,
+    /// Set by the `compare` subcommand: the feed whose final state is compared with
+    /// that of `input_path`.
+    pub compare_path: Option<String>,
+    /// Input files of the `batches` subcommand, processed in order on one engine with
+    /// each file's rows counted under its path.
+    pub batches: Option<Vec<String>>,
+    pub config_file: Option<String>,
+    pub input_format: Option<InputFormat>,
+    pub encode_bin: Option<String>,
+    pub print_schema: bool,
+    pub print_run_config: bool,
+    pub http: Option<String>,
+    /// Bound on requests waiting for the `--http` worker.
+    pub queue_size: Option<usize>,
+    pub has_headers: Option<bool>,
+    pub output_format: Option<OutputFormat>,
+    pub include_total: Option<bool>,
+    pub auto_scale: Option<bool>,
+    pub integer_cents: Option<bool>,
+    pub json_amounts: Option<JsonAmounts>,
+    pub error_on_rounding_loss: Option<bool>,
+    pub self_check: Option<bool>,
+    pub partial_on_read_error: Option<bool>,
+    pub held_detail: Option<bool>,
+    pub out_file: Option<String>,
+    pub require_increasing_tx: Option<bool>,
+    pub lossy_utf8: Option<bool>,
+    pub strict: Option<bool>,
+    pub two_pass: Option<bool>,
+    pub max_field_len: Option<usize>,
+    pub trim: Option<TrimMode>,
+    pub delimiter: Option<char>,
+    pub decimal_comma: Option<bool>,
+    pub pad_short_records: Option<bool>,
+    pub seed_snapshot: Option<String>,
+    pub allow_withdrawal_disputes: Option<bool>,
+    pub negatives_are_withdrawals: Option<bool>,
+    pub duplicate_tx_policy: Option<DuplicateTxPolicy>,
+    pub dedup_scope: Option<DedupScope>,
+    pub chargeback_policy: Option<ChargebackPolicy>,
+    pub resolve_scope: Option<ResolveScope>,
+    pub lifecycle_amount: Option<LifecycleAmountPolicy>,
+    pub max_open_disputes: Option<usize>,
+    pub dedup_identical: Option<IdenticalRowScope>,
+    pub panic_safe: Option<bool>,
+    pub amount_scale: Option<u32>,
+    pub system_account: Option<u16>,
+    pub hide_system_account: Option<bool>,
+    pub metrics_file: Option<String>,
+    pub warnings_json: Option<String>,
+    pub annotate: Option<String>,
+    pub report_json: Option<String>,
+    pub disputes_out: Option<String>,
+    pub expect: Option<String>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliOptions, AppError> {
+    if args.get(1).is_some_and(|arg| arg == "generate") {
+        return Ok(CliOptions {
+            generate: Some(parse_generate_args(&args[2..])?),
+            ..CliOptions::default()
+        });
+    }
+    if args.get(1).is_some_and(|arg| arg == "merge-output") {
+        let paths = &args[2..];
+        if paths.is_empty() || paths.iter().any(|path| path.starts_with("--")) {
+            return Err(AppError::TxProcessing(USAGE.to_string()));
+        }
+        return Ok(CliOptions {
+            merge_output: Some(paths.to_vec()),
+            ..CliOptions::default()
+        });
+    }
+
+    let compare = args.get(1).is_some_and(|arg| arg == "compare");
+    let batches = args.get(1).is_some_and(|arg| arg == "batches");
+    let mut options = CliOptions::default();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter().skip(if compare || batches { 2 } else { 1 });
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => options.config_file = Some(option_value(arg, args.next())?),
+            "--input-format" => {
+                options.input_format = Some(match option_value(arg, args.next())?.as_str() {
+                    "csv" => InputFormat::Csv,
+                    "bin" => InputFormat::Bin,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown input format {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--no-header" => options.has_headers = Some(false),
+            "--encode-bin" => options.encode_bin = Some(option_value(arg, args.next())?),
+            "--format" => {
+                options.output_format = Some(match option_value(arg, args.next())?.as_str() {
+                    "csv" => OutputFormat::Csv,
+                    "json" => OutputFormat::Json,
+                    "jsonl" => OutputFormat::Jsonl,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown output format {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--no-total" => options.include_total = Some(false),
+            "--auto-scale" => options.auto_scale = Some(true),
+            "--integer-cents" => options.integer_cents = Some(true),
+            "--print-schema" => options.print_schema = true,
+            "--print-run-config" => options.print_run_config = true,
+            "--http" => options.http = Some(option_value(arg, args.next())?),
+            "--queue-size" => options.queue_size = Some(parse_value(arg, args.next())?),
+            "--json-amounts" => {
+                options.json_amounts = Some(match option_value(arg, args.next())?.as_str() {
+                    "string" => JsonAmounts::String,
+                    "number" => JsonAmounts::Number,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown JSON amount mode {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--held-detail" => options.held_detail = Some(true),
+            "--out" => options.out_file = Some(option_value(arg, args.next())?),
+            "--error-on-rounding-loss" => options.error_on_rounding_loss = Some(true),
+            "--self-check" => options.self_check = Some(true),
+            "--partial-on-read-error" => options.partial_on_read_error = Some(true),
+            "--require-increasing-tx" => options.require_increasing_tx = Some(true),
+            "--strict" => options.strict = Some(true),
+            "--two-pass" => options.two_pass = Some(true),
+            "--lossy-utf8" => options.lossy_utf8 = Some(true),
+            "--allow-withdrawal-disputes" => options.allow_withdrawal_disputes = Some(true),
+            "--negatives-are-withdrawals" => options.negatives_are_withdrawals = Some(true),
+            "--dedup-identical" => {
+                options.dedup_identical = Some(match option_value(arg, args.next())?.as_str() {
+                    "previous" => IdenticalRowScope::Previous,
+                    "any" => IdenticalRowScope::Any,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown dedup scope {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--panic-safe" => options.panic_safe = Some(true),
+            "--system-account" => {
+                options.system_account = Some(parse_value(arg, args.next())?);
+            }
+            "--hide-system-account" => options.hide_system_account = Some(true),
+            "--amount-scale" => options.amount_scale = Some(parse_value(arg, args.next())?),
+            "--duplicate-tx" => {
+                options.duplicate_tx_policy =
+                    Some(match option_value(arg, args.next())?.as_str() {
+                        "reject" => DuplicateTxPolicy::Reject,
+                        "ignore-second" => DuplicateTxPolicy::IgnoreSecond,
+                        "error" => DuplicateTxPolicy::Error,
+                        other => {
+                            return Err(AppError::TxProcessing(format!(
+                                "Unknown duplicate tx policy {other}\n{USAGE}"
+                            )));
+                        }
+                    });
+            }
+            "--dedup-scope" => {
+                options.dedup_scope = Some(match option_value(arg, args.next())?.as_str() {
+                    "global" => DedupScope::Global,
+                    "per-client" => DedupScope::PerClient,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown dedup scope {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--chargeback" => {
+                options.chargeback_policy = Some(match option_value(arg, args.next())?.as_str() {
+                    "remove-funds" => ChargebackPolicy::RemoveFunds,
+                    "return-to-available" => ChargebackPolicy::ReturnToAvailable,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown chargeback policy {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--resolve-scope" => {
+                options.resolve_scope = Some(match option_value(arg, args.next())?.as_str() {
+                    "transaction" => ResolveScope::Transaction,
+                    "dispute" => ResolveScope::Dispute,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown resolve scope {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--lifecycle-amount" => {
+                options.lifecycle_amount = Some(match option_value(arg, args.next())?.as_str() {
+                    "ignore" => LifecycleAmountPolicy::Ignore,
+                    "strict" => LifecycleAmountPolicy::Strict,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown lifecycle amount policy {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--max-open-disputes" => {
+                options.max_open_disputes = Some(parse_value(arg, args.next())?);
+            }
+            "--max-field-len" => options.max_field_len = Some(parse_value(arg, args.next())?),
+            "--delimiter" => {
+                let value = option_value(arg, args.next())?;
+                let mut chars = value.chars();
+                options.delimiter = match (chars.next(), chars.next()) {
+                    (Some(delimiter), None) => Some(delimiter),
+                    _ => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Invalid value {value} for {arg}\n{USAGE}"
+                        )));
+                    }
+                };
+            }
+            "--decimal-comma" => options.decimal_comma = Some(true),
+            "--pad-short-records" => options.pad_short_records = Some(true),
+            "--seed-snapshot" => options.seed_snapshot = Some(option_value(arg, args.next())?),
+            "--trim" => {
+                options.trim = Some(match option_value(arg, args.next())?.as_str() {
+                    "all" => TrimMode::All,
+                    "headers" => TrimMode::Headers,
+                    "fields" => TrimMode::Fields,
+                    "none" => TrimMode::None,
+                    other => {
+                        return Err(AppError::TxProcessing(format!(
+                            "Unknown trim mode {other}\n{USAGE}"
+                        )));
+                    }
+                });
+            }
+            "--metrics-file" => options.metrics_file = Some(option_value(arg, args.next())?),
+            "--warnings-json" => options.warnings_json = Some(option_value(arg, args.next())?),
+            "--annotate" => options.annotate = Some(option_value(arg, args.next())?),
+            "--report-json" => options.report_json = Some(option_value(arg, args.next())?),
+            "--disputes-out" => options.disputes_out = Some(option_value(arg, args.next())?),
+            "--expect" => options.expect = Some(option_value(arg, args.next())?),
+            flag if flag.starts_with("--") => {
+                return Err(AppError::TxProcessing(format!(
+                    "Unknown option {flag}\n{USAGE}"
+                )));
+            }
+            path => paths.push(path.to_string()),
+        }
+    }
+
+    if compare {
+        let [first, second]: [String; 2] = paths
+            .try_into()
+            .map_err(|_| AppError::TxProcessing(USAGE.to_string()))?;
+        options.input_path = first;
+        options.compare_path = Some(second);
+        return Ok(options);
+    }
+    if batches {
+        if paths.is_empty() {
+            return Err(AppError::TxProcessing(USAGE.to_string()));
+        }
+        options.batches = Some(paths);
+        return Ok(options);
+    }
+    if paths.len() > 1 {
+        return Err(AppError::TxProcessing(USAGE.to_string()));
+    }
+    options.input_path = match paths.pop() {
+        Some(path) => path,
+        None if options.print_schema || options.http.is_some() => String::new(),
+        None => return Err(AppError::TxProcessing(USAGE.to_string())),
+    };
+    Ok(options)
+}
+
+fn parse_generate_args(args: &[String]) -> Result<GenerateOptions, AppError> {
+    let mut options = GenerateOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rows" => options.rows = parse_value(arg, args.next())?,
+            "--clients" => options.clients = parse_value(arg, args.next())?,
+            "--dispute-rate" => options.dispute_rate = parse_value(arg, args.next())?,
+            "--seed" => options.seed = parse_value(arg, args.next())?,
+            other => {
+                return Err(AppError::TxProcessing(format!(
+                    "Unknown generate option {other}\n{USAGE}"
+                )));
+            }
+        }
+    }
+    if options.clients == 0 || !(0.0..=1.0).contains(&options.dispute_rate) {
+        return Err(AppError::TxProcessing(format!(
+            "--clients must be at least 1 and --dispute-rate between 0 and 1\n{USAGE}"
+        )));
+    }
+    Ok(options)
+}
+
+fn option_value(flag: &str, value: Option<&String>) -> Result<String, AppError> {
+    value
+        .cloned()
+        .ok_or_else(|| AppError::TxProcessing(format!("Missing value for {flag}\n{USAGE}")))
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, AppError> {
+    let value = option_value(flag, value)?;
+    value
+        .parse()
+        .map_err(|_| AppError::TxProcessing(format!("Invalid value {value} for {flag}\n{USAGE}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        std::iter::once("tx-engine-example")
+            .chain(values.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn parses_path_without_overrides() {
+        let options = parse_args(&args(&["input.csv"])).unwrap();
+
+        assert_eq!(options.input_path, "input.csv");
+        assert_eq!(options.include_total, None);
+        assert_eq!(options.require_increasing_tx, None);
+        assert_eq!(options.config_file, None);
+    }
+
+    #[test]
+    fn parses_no_total_flag_in_any_position() {
+        let before = parse_args(&args(&["--no-total", "input.csv"])).unwrap();
+        let after = parse_args(&args(&["input.csv", "--no-total"])).unwrap();
+
+        assert_eq!(before.include_total, Some(false));
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn parses_options_with_values() {
+        let options = parse_args(&args(&["--config", "engine.toml", "input.csv"])).unwrap();
+
+        assert_eq!(options.config_file.as_deref(), Some("engine.toml"));
+        assert!(parse_args(&args(&["input.csv", "--config"])).is_err());
+
+        let options = parse_args(&args(&["--input-format", "bin", "input.bin"])).unwrap();
+        assert_eq!(options.input_format, Some(InputFormat::Bin));
+        assert!(parse_args(&args(&["--input-format", "xml", "input.xml"])).is_err());
+    }
+
+    #[test]
+    fn parses_generate_subcommand() {
+        let options = parse_args(&args(&["generate", "--rows", "5", "--seed", "9"])).unwrap();
+
+        assert_eq!(
+            options.generate,
+            Some(GenerateOptions {
+                rows: 5,
+                seed: 9,
+                ..GenerateOptions::default()
+            })
+        );
+        assert!(parse_args(&args(&["generate", "--clients", "0"])).is_err());
+        assert!(parse_args(&args(&["generate", "--dispute-rate", "2"])).is_err());
+        assert!(parse_args(&args(&["generate", "input.csv"])).is_err());
+    }
+
+    #[test]
+    fn parses_merge_output_subcommand() {
+        let options = parse_args(&args(&["merge-output", "a.csv", "b.csv"])).unwrap();
+
+        assert_eq!(
+            options.merge_output,
+            Some(vec!["a.csv".to_string(), "b.csv".to_string()])
+        );
+        assert!(parse_args(&args(&["merge-output"])).is_err());
+        assert!(parse_args(&args(&["merge-output", "--no-total", "a.csv"])).is_err());
+    }
+
+    #[test]
+    fn parses_compare_subcommand_with_run_options() {
+        let options = parse_args(&args(&["compare", "--two-pass", "a.csv", "b.csv"])).unwrap();
+
+        assert_eq!(options.input_path, "a.csv");
+        assert_eq!(options.compare_path.as_deref(), Some("b.csv"));
+        assert_eq!(options.two_pass, Some(true));
+        assert!(parse_args(&args(&["compare", "a.csv"])).is_err());
+        assert!(parse_args(&args(&["compare", "a.csv", "b.csv", "c.csv"])).is_err());
+    }
+
+    #[test]
+    fn parses_batches_subcommand_with_run_options() {
+        let options =
+            parse_args(&args(&["batches", "a.csv", "--no-total", "b.csv", "a.csv"])).unwrap();
+
+        assert_eq!(
+            options.batches,
+            Some(vec![
+                "a.csv".to_string(),
+                "b.csv".to_string(),
+                "a.csv".to_string()
+            ])
+        );
+        assert_eq!(options.include_total, Some(false));
+        assert!(parse_args(&args(&["batches", "--no-total"])).is_err());
+    }
+
+    #[test]
+    fn print_schema_does_not_need_a_path() {
+        let options = parse_args(&args(&["--print-schema", "--no-total"])).unwrap();
+
+        assert!(options.print_schema);
+        assert_eq!(options.include_total, Some(false));
+        assert!(options.input_path.is_empty());
+    }
+
+    #[test]
+    fn rejects_missing_path_and_unknown_flags() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["--bogus", "input.csv"])).is_err());
+        assert!(parse_args(&args(&["a.csv", "b.csv"])).is_err());
+    }
+}
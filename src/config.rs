@@ -0,0 +1,490 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::CliOptions,
+    domain::{errors::AppError, types::ClientId},
+    io::{
+        input::{CsvOptions, InputFormat, TrimMode},
+        output::{JsonAmounts, OutputFormat, OutputOptions, MINOR_UNIT_SCALE},
+    },
+    tx_engine::{
+        ChargebackPolicy, DedupScope, DuplicateTxPolicy, IdenticalRowScope, LifecycleAmountPolicy,
+        ResolveScope, TxEngineBuilder,
+    },
+};
+
+/// Settings loaded from a `--config` TOML file. Every section and key is optional.
+///
+/// ```toml
+/// [input]
+/// format = "csv"
+/// require_increasing_tx = true
+///
+/// [engine]
+/// allow_withdrawal_disputes = true
+/// duplicate_tx_policy = "ignore_second"
+///
+/// [output]
+/// include_total = false
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub engine: EngineConfig,
+    pub output: OutputConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub format: InputFormat,
+    pub has_headers: bool,
+    pub require_increasing_tx: bool,
+    pub lossy_utf8: bool,
+    pub strict: bool,
+    pub two_pass: bool,
+    pub max_field_len: Option<usize>,
+    pub trim: TrimMode,
+    pub delimiter: char,
+    pub decimal_comma: bool,
+    pub pad_short_records: bool,
+    /// Snapshot CSV whose accounts are opened before the feed is read, see
+    /// `--seed-snapshot`.
+    pub seed_snapshot: Option<String>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            format: InputFormat::default(),
+            has_headers: true,
+            require_increasing_tx: false,
+            lossy_utf8: false,
+            strict: false,
+            two_pass: false,
+            max_field_len: None,
+            trim: TrimMode::default(),
+            delimiter: ',',
+            decimal_comma: false,
+            pad_short_records: false,
+            seed_snapshot: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub allow_withdrawal_disputes: bool,
+    pub reject_withdrawals_for_unknown_clients: bool,
+    pub negatives_are_withdrawals: bool,
+    pub duplicate_tx_policy: DuplicateTxPolicy,
+    pub dedup_scope: DedupScope,
+    pub chargeback_policy: ChargebackPolicy,
+    pub resolve_scope: ResolveScope,
+    pub lifecycle_amount: LifecycleAmountPolicy,
+    pub max_open_disputes: Option<usize>,
+    pub dedup_identical: Option<IdenticalRowScope>,
+    pub panic_safe: bool,
+    pub amount_scale: Option<u32>,
+    pub system_account: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    pub include_total: bool,
+    /// Prints amounts with as many decimal places as the most precise input amount, up
+    /// to `MAX_AUTO_SCALE`, instead of `OUTPUT_SCALE`.
+    pub auto_scale: bool,
+    /// Prints amounts as integer numbers of cents, failing if a balance has a sub-cent
+    /// part.
+    pub integer_cents: bool,
+    /// Leaves the engine's system account out of the snapshot.
+    pub hide_system_account: bool,
+    pub json_amounts: JsonAmounts,
+    pub error_on_rounding_loss: bool,
+    pub self_check: bool,
+    /// On a failure reading the input, prints the snapshot of the rows read so far
+    /// before failing.
+    pub partial_on_read_error: bool,
+    pub held_detail: bool,
+    pub out_file: Option<String>,
+    pub metrics_file: Option<String>,
+    pub warnings_json: Option<String>,
+    pub annotate: Option<String>,
+    pub report_json: Option<String>,
+    pub disputes_out: Option<String>,
+    /// Snapshot CSV the final balances are checked against, see `--expect`.
+    pub expect: Option<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            format: OutputFormat::default(),
+            include_total: true,
+            auto_scale: false,
+            integer_cents: false,
+            hide_system_account: false,
+            json_amounts: JsonAmounts::default(),
+            error_on_rounding_loss: false,
+            self_check: false,
+            partial_on_read_error: false,
+            held_detail: false,
+            out_file: None,
+            metrics_file: None,
+            warnings_json: None,
+            annotate: None,
+            report_json: None,
+            disputes_out: None,
+            expect: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self, AppError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| AppError::Config(format!("Cannot read config {path}: {err}")))?;
+        Self::from_toml(&content)
+            .map_err(|err| AppError::Config(format!("Invalid config {path}: {err}")))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// The effective settings in the `--config` format, so a run can be repeated.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("config must serialize to TOML")
+    }
+
+    /// Rejects combinations of settings that cannot work together.
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !self.input.delimiter.is_ascii() {
+            return Err(AppError::Config(format!(
+                "Delimiter {:?} must be a single ASCII character",
+                self.input.delimiter
+            )));
+        }
+        if self.input.decimal_comma && self.input.delimiter == ',' {
+            return Err(AppError::Config(
+                "decimal_comma needs a delimiter other than ','".to_string(),
+            ));
+        }
+        if self.output.integer_cents && self.output.auto_scale {
+            return Err(AppError::Config(
+                "integer_cents and auto_scale cannot be combined".to_string(),
+            ));
+        }
+        if self.input.require_increasing_tx && self.engine.dedup_scope == DedupScope::PerClient {
+            return Err(AppError::Config(
+                "require_increasing_tx checks tx ids across all clients and cannot be combined with dedup_scope = \"per_client\"".to_string(),
+            ));
+        }
+        if self.output.held_detail && self.output.format != OutputFormat::Csv {
+            return Err(AppError::Config(
+                "held_detail is only supported with CSV output".to_string(),
+            ));
+        }
+        if self
+            .engine
+            .amount_scale
+            .is_some_and(|scale| scale > Decimal::MAX_SCALE)
+        {
+            return Err(AppError::Config(format!(
+                "amount_scale must be at most {}",
+                Decimal::MAX_SCALE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Overrides file values with the ones given explicitly on the command line.
+    pub fn apply_cli(&mut self, cli: &CliOptions) {
+        if let Some(format) = cli.input_format {
+            self.input.format = format;
+        }
+        if let Some(has_headers) = cli.has_headers {
+            self.input.has_headers = has_headers;
+        }
+        if let Some(require_increasing_tx) = cli.require_increasing_tx {
+            self.input.require_increasing_tx = require_increasing_tx;
+        }
+        if let Some(lossy_utf8) = cli.lossy_utf8 {
+            self.input.lossy_utf8 = lossy_utf8;
+        }
+        if let Some(strict) = cli.strict {
+            self.input.strict = strict;
+        }
+        if let Some(two_pass) = cli.two_pass {
+            self.input.two_pass = two_pass;
+        }
+        if let Some(max_field_len) = cli.max_field_len {
+            self.input.max_field_len = Some(max_field_len);
+        }
+        if let Some(trim) = cli.trim {
+            self.input.trim = trim;
+        }
+        if let Some(delimiter) = cli.delimiter {
+            self.input.delimiter = delimiter;
+        }
+        if let Some(decimal_comma) = cli.decimal_comma {
+            self.input.decimal_comma = decimal_comma;
+        }
+        if let Some(pad_short_records) = cli.pad_short_records {
+            self.input.pad_short_records = pad_short_records;
+        }
+        if let Some(seed_snapshot) = &cli.seed_snapshot {
+            self.input.seed_snapshot = Some(seed_snapshot.clone());
+        }
+        if let Some(allow_withdrawal_disputes) = cli.allow_withdrawal_disputes {
+            self.engine.allow_withdrawal_disputes = allow_withdrawal_disputes;
+        }
+        if let Some(negatives_are_withdrawals) = cli.negatives_are_withdrawals {
+            self.engine.negatives_are_withdrawals = negatives_are_withdrawals;
+        }
+        if let Some(duplicate_tx_policy) = cli.duplicate_tx_policy {
+            self.engine.duplicate_tx_policy = duplicate_tx_policy;
+        }
+        if let Some(dedup_scope) = cli.dedup_scope {
+            self.engine.dedup_scope = dedup_scope;
+        }
+        if let Some(chargeback_policy) = cli.chargeback_policy {
+            self.engine.chargeback_policy = chargeback_policy;
+        }
+        if let Some(resolve_scope) = cli.resolve_scope {
+            self.engine.resolve_scope = resolve_scope;
+        }
+        if let Some(lifecycle_amount) = cli.lifecycle_amount {
+            self.engine.lifecycle_amount = lifecycle_amount;
+        }
+        if let Some(max_open_disputes) = cli.max_open_disputes {
+            self.engine.max_open_disputes = Some(max_open_disputes);
+        }
+        if let Some(dedup_identical) = cli.dedup_identical {
+            self.engine.dedup_identical = Some(dedup_identical);
+        }
+        if let Some(panic_safe) = cli.panic_safe {
+            self.engine.panic_safe = panic_safe;
+        }
+        if let Some(amount_scale) = cli.amount_scale {
+            self.engine.amount_scale = Some(amount_scale);
+        }
+        if let Some(system_account) = cli.system_account {
+            self.engine.system_account = Some(system_account);
+        }
+        if let Some(hide_system_account) = cli.hide_system_account {
+            self.output.hide_system_account = hide_system_account;
+        }
+        if let Some(format) = cli.output_format {
+            self.output.format = format;
+        }
+        if let Some(auto_scale) = cli.auto_scale {
+            self.output.auto_scale = auto_scale;
+        }
+        if let Some(integer_cents) = cli.integer_cents {
+            self.output.integer_cents = integer_cents;
+        }
+        if let Some(include_total) = cli.include_total {
+            self.output.include_total = include_total;
+        }
+        if let Some(json_amounts) = cli.json_amounts {
+            self.output.json_amounts = json_amounts;
+        }
+        if let Some(error_on_rounding_loss) = cli.error_on_rounding_loss {
+            self.output.error_on_rounding_loss = error_on_rounding_loss;
+        }
+        if let Some(self_check) = cli.self_check {
+            self.output.self_check = self_check;
+        }
+        if let Some(partial_on_read_error) = cli.partial_on_read_error {
+            self.output.partial_on_read_error = partial_on_read_error;
+        }
+        if let Some(held_detail) = cli.held_detail {
+            self.output.held_detail = held_detail;
+        }
+        if let Some(out_file) = &cli.out_file {
+            self.output.out_file = Some(out_file.clone());
+        }
+        if let Some(metrics_file) = &cli.metrics_file {
+            self.output.metrics_file = Some(metrics_file.clone());
+        }
+        if let Some(warnings_json) = &cli.warnings_json {
+            self.output.warnings_json = Some(warnings_json.clone());
+        }
+        if let Some(annotate) = &cli.annotate {
+            self.output.annotate = Some(annotate.clone());
+        }
+        if let Some(report_json) = &cli.report_json {
+            self.output.report_json = Some(report_json.clone());
+        }
+        if let Some(disputes_out) = &cli.disputes_out {
+            self.output.disputes_out = Some(disputes_out.clone());
+        }
+        if let Some(expect) = &cli.expect {
+            self.output.expect = Some(expect.clone());
+        }
+    }
+
+    pub fn engine_builder(&self) -> TxEngineBuilder {
+        let builder = TxEngineBuilder::new()
+            .allow_withdrawal_disputes(self.engine.allow_withdrawal_disputes)
+            .reject_withdrawals_for_unknown_clients(
+                self.engine.reject_withdrawals_for_unknown_clients,
+            )
+            .negatives_are_withdrawals(self.engine.negatives_are_withdrawals)
+            .duplicate_tx_policy(self.engine.duplicate_tx_policy)
+            .dedup_scope(self.engine.dedup_scope)
+            .chargeback_policy(self.engine.chargeback_policy)
+            .resolve_scope(self.engine.resolve_scope)
+            .lifecycle_amount_policy(self.engine.lifecycle_amount)
+            .verify_holds(self.output.self_check)
+            .panic_safe(self.engine.panic_safe);
+        let builder = match self.engine.max_open_disputes {
+            Some(max_open_disputes) => builder.max_open_disputes(max_open_disputes),
+            None => builder,
+        };
+        let builder = match self.engine.dedup_identical {
+            Some(scope) => builder.dedup_identical(scope),
+            None => builder,
+        };
+        let builder = match self.engine.amount_scale {
+            Some(scale) => builder.amount_scale(scale),
+            None => builder,
+        };
+        match self.engine.system_account {
+            Some(client) => builder.system_account(ClientId(client)),
+            None => builder,
+        }
+    }
+
+    pub fn csv_options(&self) -> CsvOptions {
+        CsvOptions {
+            has_headers: self.input.has_headers,
+            max_field_len: self.input.max_field_len,
+            trim: self.input.trim,
+            delimiter: self.input.delimiter as u8,
+            decimal_comma: self.input.decimal_comma,
+            pad_short_records: self.input.pad_short_records,
+        }
+    }
+
+    pub fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            include_total: self.output.include_total,
+            json_amounts: self.output.json_amounts,
+            integer_cents: self.output.integer_cents,
+            scale: if self.output.integer_cents {
+                MINOR_UNIT_SCALE
+            } else {
+                OutputOptions::default().scale
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, TransactionType};
+    use crate::test_utils::make_tx;
+    use rust_decimal_macros::dec;
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn loaded_config_enables_withdrawal_disputes() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time should be after unix epoch")
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("tx_engine_config_{nanos}.toml"));
+        fs::write(&path, "[engine]\nallow_withdrawal_disputes = true\n")
+            .expect("must write config");
+
+        let config = Config::load(&path.to_string_lossy());
+        fs::remove_file(&path).expect("must remove temp config");
+        let mut engine = config.expect("config must load").engine_builder().build();
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn cli_flags_override_file_values() {
+        let mut config = Config::from_toml("[output]\ninclude_total = true\n").unwrap();
+        let cli = CliOptions {
+            include_total: Some(false),
+            ..CliOptions::default()
+        };
+
+        config.apply_cli(&cli);
+
+        assert!(!config.output_options().include_total);
+    }
+
+    #[test]
+    fn decimal_comma_requires_another_delimiter() {
+        let config =
+            Config::from_toml("[input]\ndecimal_comma = true\n").expect("config must parse");
+        assert!(matches!(config.validate(), Err(AppError::Config(_))));
+
+        let config = Config::from_toml("[input]\ndecimal_comma = true\ndelimiter = \";\"\n")
+            .expect("config must parse");
+        assert!(config.validate().is_ok());
+        assert_eq!(config.csv_options().delimiter, b';');
+    }
+
+    #[test]
+    fn increasing_tx_ids_cannot_be_required_with_per_client_ids() {
+        let config = Config::from_toml(
+            "[input]\nrequire_increasing_tx = true\n[engine]\ndedup_scope = \"per_client\"\n",
+        )
+        .expect("config must parse");
+
+        assert!(matches!(config.validate(), Err(AppError::Config(_))));
+    }
+
+    #[test]
+    fn run_config_round_trips_through_toml() {
+        let mut config = Config::from_toml("[input]\nstrict = true\n").unwrap();
+        config.apply_cli(&CliOptions {
+            chargeback_policy: Some(ChargebackPolicy::ReturnToAvailable),
+            max_open_disputes: Some(3),
+            ..CliOptions::default()
+        });
+
+        let printed = config.to_toml();
+
+        assert!(printed.contains("chargeback_policy = \"return_to_available\""));
+        assert_eq!(Config::from_toml(&printed).unwrap(), config);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::from_toml("[engine]\nunknown = 1\n").is_err());
+    }
+}
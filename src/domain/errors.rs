@@ -1,18 +1,71 @@
+use crate::domain::types::ClientId;
 use crate::io::input::ParseTransactionsError;
+use serde::Serialize;
 use std::error::Error;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum AppError {
     Parse(ParseTransactionsError),
+    Output(std::io::Error),
+    Config(String),
+    RoundingLoss(Vec<ClientId>),
+    /// Clients with a balance that is not a whole number of minor units, which
+    /// `--integer-cents` cannot print.
+    InexactMinorUnits(Vec<ClientId>),
+    SelfCheck(Vec<String>),
     TxProcessing(String),
     TxProcessingNonCritical(String),
 }
 
+impl AppError {
+    /// Whether the run may succeed if retried with the same input. Only I/O failures
+    /// reading the input or writing the output qualify; malformed data, bad
+    /// configuration and engine errors fail the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AppError::Output(_) => true,
+            AppError::Parse(err) => err.is_read_failure(),
+            AppError::Config(_)
+            | AppError::RoundingLoss(_)
+            | AppError::InexactMinorUnits(_)
+            | AppError::SelfCheck(_)
+            | AppError::TxProcessing(_)
+            | AppError::TxProcessingNonCritical(_) => false,
+        }
+    }
+
+    /// Whether writing the output failed because its reader closed the pipe early.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, AppError::Output(err) if err.kind() == std::io::ErrorKind::BrokenPipe)
+    }
+}
+
 impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppError::Parse(err) => write!(f, "{err}"),
+            AppError::Output(err) => write!(f, "{err}"),
+            AppError::Config(err) => write!(f, "{err}"),
+            AppError::RoundingLoss(clients) => {
+                let clients: Vec<String> = clients.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "Output rounding lost precision for clients {}",
+                    clients.join(", ")
+                )
+            }
+            AppError::InexactMinorUnits(clients) => {
+                let clients: Vec<String> = clients.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "Balances of clients {} are not whole minor units and cannot be printed as integers",
+                    clients.join(", ")
+                )
+            }
+            AppError::SelfCheck(violations) => {
+                write!(f, "Self-check failed:\n  {}", violations.join("\n  "))
+            }
             AppError::TxProcessing(err) => write!(f, "{err}"),
             AppError::TxProcessingNonCritical(err) => write!(f, "{err}, skipping"),
         }
@@ -23,7 +76,80 @@ impl Error for AppError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             AppError::Parse(err) => Some(err),
-            AppError::TxProcessing(_) | AppError::TxProcessingNonCritical(_) => None,
+            AppError::Output(err) => Some(err),
+            AppError::Config(_)
+            | AppError::RoundingLoss(_)
+            | AppError::InexactMinorUnits(_)
+            | AppError::SelfCheck(_)
+            | AppError::TxProcessing(_)
+            | AppError::TxProcessingNonCritical(_) => None,
+        }
+    }
+}
+
+/// Errors returned by the engine's public API. Data problems in a single transaction
+/// are `Rejected` and can be skipped; `Critical` errors mean processing must stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessError {
+    Rejected(Rejection),
+    Critical(String),
+}
+
+impl ProcessError {
+    pub fn rejected(category: RejectionCategory, message: impl Into<String>) -> Self {
+        ProcessError::Rejected(Rejection {
+            category,
+            message: message.into(),
+        })
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::Rejected(err) => write!(f, "{err}"),
+            ProcessError::Critical(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Broad reason a transaction was rejected, for grouping skipped rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionCategory {
+    InvalidAmount,
+    InsufficientFunds,
+    DuplicateTx,
+    UnknownClient,
+    UnknownTx,
+    InvalidDispute,
+    DisputeLimit,
+    FrozenAccount,
+    /// A correction without the reason it must carry.
+    MissingReason,
+    /// The row repeats an earlier one, see `TxEngineBuilder::dedup_identical`.
+    DuplicateRow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    pub category: RejectionCategory,
+    pub message: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ProcessError {}
+
+impl From<ProcessError> for AppError {
+    fn from(value: ProcessError) -> Self {
+        match value {
+            ProcessError::Rejected(err) => AppError::TxProcessingNonCritical(err.message),
+            ProcessError::Critical(err) => AppError::TxProcessing(err),
         }
     }
 }
@@ -33,3 +159,30 @@ impl From<ParseTransactionsError> for AppError {
         AppError::Parse(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::parse_transactions_from_reader;
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn only_io_failures_are_retryable() {
+        let io_error = || io::Error::new(io::ErrorKind::Interrupted, "interrupted");
+        assert!(AppError::Parse(ParseTransactionsError::Io(io_error())).is_retryable());
+        assert!(AppError::Output(io_error()).is_retryable());
+
+        let malformed =
+            parse_transactions_from_reader(Cursor::new("type,client,tx,amount\ndeposit,x,1,1.0\n"))
+                .next()
+                .expect("one row")
+                .expect_err("client must not parse");
+        assert!(!AppError::Parse(malformed).is_retryable());
+
+        let insufficient_funds: AppError =
+            ProcessError::rejected(RejectionCategory::InsufficientFunds, "Insufficient funds")
+                .into();
+        assert!(!insufficient_funds.is_retryable());
+        assert!(!AppError::TxProcessing("Duplicate transaction ID 1".to_string()).is_retryable());
+    }
+}
@@ -1,12 +1,13 @@
 use std::{
     fmt::{self, Display},
+    hash::Hash,
     ops::{Add, AddAssign, Neg, Sub, SubAssign},
 };
 
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ClientId(pub u16);
 
 impl Display for ClientId {
@@ -15,7 +16,7 @@ impl Display for ClientId {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TxID(pub u32);
 
 impl Display for TxID {
@@ -24,69 +25,158 @@ impl Display for TxID {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
-pub struct Amount(pub Decimal);
+/// Numeric backend for `Amount`. The engine only relies on these operations, so a
+/// fixed-point integer type can replace the default `Decimal`.
+pub trait Money:
+    Copy
+    + Ord
+    + Default
+    + Hash
+    + fmt::Debug
+    + Display
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + SubAssign
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+
+    fn abs(self) -> Self;
+
+    /// Cuts the value to `scale` decimal places, towards zero.
+    fn truncate_to(self, scale: u32) -> Self;
+}
+
+impl Money for Decimal {
+    const ZERO: Self = Decimal::ZERO;
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Decimal::checked_add(self, rhs)
+    }
+
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        Decimal::checked_sub(self, rhs)
+    }
+
+    fn abs(self) -> Self {
+        Decimal::abs(&self)
+    }
+
+    fn truncate_to(self, scale: u32) -> Self {
+        self.round_dp_with_strategy(scale, rust_decimal::RoundingStrategy::ToZero)
+    }
+}
+
+/// Fixed-point amounts in the smallest currency unit, e.g. integer cents.
+impl Money for i64 {
+    const ZERO: Self = 0;
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        i64::checked_add(self, rhs)
+    }
+
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        i64::checked_sub(self, rhs)
+    }
+
+    fn abs(self) -> Self {
+        i64::abs(self)
+    }
+
+    /// Values are already whole units of the smallest denomination.
+    fn truncate_to(self, _scale: u32) -> Self {
+        self
+    }
+}
 
-impl Amount {
-    pub const ZERO: Self = Self(Decimal::ZERO);
+#[derive(
+    Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Default, Hash,
+)]
+pub struct Amount<M = Decimal>(pub M);
 
-    pub fn new(value: Decimal) -> Self {
+impl<M: Money> Amount<M> {
+    pub const ZERO: Self = Self(M::ZERO);
+
+    pub fn new(value: M) -> Self {
         Self(value)
     }
 
     pub fn is_zero(self) -> bool {
-        self.0.is_zero()
+        self.eq_value(Self::ZERO)
+    }
+
+    /// Compares numeric values, so `1.50` and `1.5` are equal. `PartialEq` has the
+    /// same semantics; this spells it out where a scale mismatch would matter.
+    pub fn eq_value(self, other: Self) -> bool {
+        self.0.cmp(&other.0).is_eq()
     }
 
     pub fn abs(self) -> Self {
         Self(self.0.abs())
     }
 
-    pub fn inner(self) -> Decimal {
+    pub fn truncate_to(self, scale: u32) -> Self {
+        Self(self.0.truncate_to(scale))
+    }
+
+    pub fn inner(self) -> M {
         self.0
     }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
 }
 
-impl Display for Amount {
+impl<M: Money> Display for Amount<M> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
-impl Add for Amount {
+impl<M: Money> Add for Amount<M> {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
         Self(self.0 + rhs.0)
     }
 }
 
-impl AddAssign for Amount {
+impl<M: Money> AddAssign for Amount<M> {
     fn add_assign(&mut self, rhs: Self) {
         self.0 += rhs.0;
     }
 }
 
-impl Sub for Amount {
+impl<M: Money> Sub for Amount<M> {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self {
         Self(self.0 - rhs.0)
     }
 }
 
-impl SubAssign for Amount {
+impl<M: Money> SubAssign for Amount<M> {
     fn sub_assign(&mut self, rhs: Self) {
         self.0 -= rhs.0;
     }
 }
 
-impl Neg for Amount {
+impl<M: Money> Neg for Amount<M> {
     type Output = Self;
     fn neg(self) -> Self {
         Self(-self.0)
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -94,6 +184,9 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Manual adjustment by an operator: a signed amount applied straight to available,
+    /// with a required reason.
+    Correction,
 }
 
 impl Display for TransactionType {
@@ -104,6 +197,7 @@ impl Display for TransactionType {
             TransactionType::Dispute => "dispute",
             TransactionType::Resolve => "resolve",
             TransactionType::Chargeback => "chargeback",
+            TransactionType::Correction => "correction",
         };
         write!(f, "{as_str}")
     }
@@ -0,0 +1,58 @@
+use std::io::{self, Read, Write};
+
+use crate::{io::input::CsvOptions, processing::RowDisposition};
+
+/// Status of rows after the last processed one, e.g. when the run was interrupted.
+const NOT_PROCESSED: &str = "not_processed";
+
+/// Copies the CSV `input` to `output` with a trailing `status` column: `applied`,
+/// `rejected: <reason>` for rows the engine skipped or `parse error: <message>` for the
+/// row a run stopped at.
+/// `statuses` holds one entry per data row, in input order. Fields are copied as read,
+/// without trimming, and written back with the input's delimiter. With
+/// `pad_short_records`, a short row is padded to the header's width first so its status
+/// lines up.
+pub fn write_annotated_csv<R: Read, W: Write>(
+    input: R,
+    output: W,
+    options: &CsvOptions,
+    statuses: &[RowDisposition],
+) -> io::Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(options.has_headers)
+        .delimiter(options.delimiter)
+        .flexible(options.pad_short_records)
+        .from_reader(input);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .from_writer(output);
+
+    let mut width = 0;
+    if options.has_headers {
+        let mut headers = reader.byte_headers()?.clone();
+        width = headers.len();
+        headers.push_field(b"status");
+        writer.write_byte_record(&headers)?;
+    }
+
+    let mut record = csv::ByteRecord::new();
+    let mut row_index = 0;
+    while reader.read_byte_record(&mut record)? {
+        let status = match statuses.get(row_index) {
+            Some(RowDisposition::Applied) => "applied".to_string(),
+            Some(RowDisposition::Skipped(rejection)) => format!("rejected: {rejection}"),
+            Some(RowDisposition::ParseError(message)) => format!("parse error: {message}"),
+            None => NOT_PROCESSED.to_string(),
+        };
+        if options.pad_short_records {
+            while record.len() < width {
+                record.push_field(b"");
+            }
+        }
+        record.push_field(status.as_bytes());
+        writer.write_byte_record(&record)?;
+        row_index += 1;
+    }
+
+    writer.flush()
+}
@@ -0,0 +1,242 @@
+//! Compact binary transaction format.
+//!
+//! Every record is a one-byte payload length followed by the payload:
+//! op (1 byte), client (`u16` LE), tx (`u32` LE) and, when the length says so,
+//! the amount as a 16-byte serialized `Decimal`.
+
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+
+use rust_decimal::Decimal;
+
+use crate::domain::types::{Amount, ClientId, TransactionType, TxID};
+use crate::io::input::{row_digest, ParseTransactionsError, Transaction};
+
+const HEADER_LEN: u8 = 7;
+const WITH_AMOUNT_LEN: u8 = HEADER_LEN + 16;
+
+pub type BinaryTransactionRecords = BinaryTransactionRecordsFromReader<BufReader<File>>;
+
+/// Reads records until the end of the input. A record with an unknown op code is
+/// yielded as an error and reading goes on, but one with a bad length byte or cut off by
+/// the end of the input ends the records, as the next record's offset is unknown.
+pub struct BinaryTransactionRecordsFromReader<R> {
+    reader: R,
+    record_index: u64,
+    misaligned: bool,
+}
+
+impl<R: Read> Iterator for BinaryTransactionRecordsFromReader<R> {
+    type Item = Result<Transaction, ParseTransactionsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.misaligned {
+            return None;
+        }
+        let mut len = [0u8; 1];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err.into())),
+        }
+
+        self.record_index += 1;
+        Some(self.read_payload(len[0]))
+    }
+}
+
+impl<R: Read> BinaryTransactionRecordsFromReader<R> {
+    fn read_payload(&mut self, len: u8) -> Result<Transaction, ParseTransactionsError> {
+        if len != HEADER_LEN && len != WITH_AMOUNT_LEN {
+            self.misaligned = true;
+            return Err(self.invalid(format!("unexpected record length {len}")));
+        }
+
+        let mut payload = [0u8; WITH_AMOUNT_LEN as usize];
+        match self.reader.read_exact(&mut payload[..len as usize]) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.misaligned = true;
+                return Err(self.invalid("truncated record".to_string()));
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let op_type = match payload[0] {
+            0 => TransactionType::Deposit,
+            1 => TransactionType::Withdrawal,
+            2 => TransactionType::Dispute,
+            3 => TransactionType::Resolve,
+            4 => TransactionType::Chargeback,
+            other => return Err(self.invalid(format!("unknown op code {other}"))),
+        };
+        let client = u16::from_le_bytes([payload[1], payload[2]]);
+        let tx_id = u32::from_le_bytes([payload[3], payload[4], payload[5], payload[6]]);
+        let amount = (len == WITH_AMOUNT_LEN).then(|| {
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(&payload[HEADER_LEN as usize..]);
+            Amount::new(Decimal::deserialize(bytes))
+        });
+
+        Ok(Transaction {
+            op_type,
+            client: ClientId(client),
+            tx_id: TxID(tx_id),
+            amount,
+            reason: None,
+            row_digest: Some(row_digest([&payload[..len as usize]])),
+        })
+    }
+
+    fn invalid(&self, reason: String) -> ParseTransactionsError {
+        ParseTransactionsError::InvalidBinaryRecord {
+            record: self.record_index,
+            reason,
+        }
+    }
+}
+
+pub fn parse_binary_transactions_from_reader<R: Read>(
+    reader: R,
+) -> BinaryTransactionRecordsFromReader<R> {
+    BinaryTransactionRecordsFromReader {
+        reader,
+        record_index: 0,
+        misaligned: false,
+    }
+}
+
+pub fn parse_binary_transactions(
+    input_path: &str,
+) -> Result<BinaryTransactionRecords, ParseTransactionsError> {
+    let file = File::open(input_path)?;
+    let reader = BufReader::new(file);
+
+    Ok(parse_binary_transactions_from_reader(reader))
+}
+
+pub fn write_binary_transaction<W: Write>(writer: &mut W, tx: &Transaction) -> io::Result<()> {
+    let op = match tx.op_type {
+        TransactionType::Deposit => 0u8,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::Chargeback => 4,
+        TransactionType::Correction => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Correction {} cannot be encoded: the binary format has no reason field",
+                    tx.tx_id
+                ),
+            ))
+        }
+    };
+    let len = if tx.amount.is_some() {
+        WITH_AMOUNT_LEN
+    } else {
+        HEADER_LEN
+    };
+
+    writer.write_all(&[len, op])?;
+    writer.write_all(&tx.client.0.to_le_bytes())?;
+    writer.write_all(&tx.tx_id.0.to_le_bytes())?;
+    if let Some(amount) = tx.amount {
+        writer.write_all(&amount.inner().serialize())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::parse_transactions_from_reader;
+    use crate::test_utils::make_tx;
+    use crate::tx_engine::TxEngine;
+    use std::io::Cursor;
+
+    fn run_engine<I>(records: I) -> TxEngine
+    where
+        I: Iterator<Item = Result<Transaction, ParseTransactionsError>>,
+    {
+        let mut engine = TxEngine::new();
+        for tx in records {
+            let _ = engine.process_transaction(&tx.expect("record must decode"));
+        }
+        engine
+    }
+
+    #[test]
+    fn csv_to_binary_round_trip_produces_identical_snapshots() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,1,10.1234
+deposit,2,2,5.0
+withdrawal,2,3,1.0
+dispute,1,1,
+deposit,3,4,8.3456
+withdrawal,3,5,2.3456
+chargeback,1,1,
+";
+
+        let mut encoded = Vec::new();
+        for tx in parse_transactions_from_reader(Cursor::new(csv.as_bytes())) {
+            write_binary_transaction(&mut encoded, &tx.expect("row must parse")).unwrap();
+        }
+
+        let from_csv = run_engine(parse_transactions_from_reader(Cursor::new(csv.as_bytes())));
+        let from_binary = run_engine(parse_binary_transactions_from_reader(Cursor::new(encoded)));
+
+        assert_eq!(from_csv.clients_snapshot(), from_binary.clients_snapshot());
+        assert_eq!(from_binary.clients_snapshot().len(), 3);
+    }
+
+    #[test]
+    fn rejects_unknown_op_and_truncated_record() {
+        let mut iter = parse_binary_transactions_from_reader(Cursor::new(vec![
+            HEADER_LEN, 9, 0, 0, 0, 0, 0, 0,
+        ]));
+        assert!(matches!(
+            iter.next(),
+            Some(Err(ParseTransactionsError::InvalidBinaryRecord {
+                record: 1,
+                ..
+            }))
+        ));
+
+        let mut iter = parse_binary_transactions_from_reader(Cursor::new(vec![HEADER_LEN, 0, 1]));
+        match iter.next() {
+            Some(Err(err @ ParseTransactionsError::InvalidBinaryRecord { record: 1, .. })) => {
+                assert!(err.to_string().contains("truncated record"));
+                assert!(!err.is_read_failure());
+            }
+            other => panic!("expected a truncated record error, got {other:?}"),
+        }
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn stops_after_a_bad_record_length() {
+        let mut records = vec![3, 0, 0];
+        write_binary_transaction(
+            &mut records,
+            &make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(Decimal::ONE)),
+            ),
+        )
+        .unwrap();
+        let mut iter = parse_binary_transactions_from_reader(Cursor::new(records));
+
+        assert!(matches!(
+            iter.next(),
+            Some(Err(ParseTransactionsError::InvalidBinaryRecord {
+                record: 1,
+                ..
+            }))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    str::FromStr,
+};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    domain::errors::AppError,
+    io::output::{output_columns, OUTPUT_SCALE},
+    tx_engine::ClientSnapshot,
+};
+
+/// Columns an expected file may check besides `client`.
+const COLUMNS: [&str; 4] = ["available", "held", "total", "locked"];
+
+/// Compares `snapshots` with an expected snapshot CSV named `name`, returning one line
+/// per difference. The header picks the columns checked: `client` first, then any of
+/// `available`, `held`, `total` and `locked` in any order, so `client,total` checks only
+/// totals. Amounts are compared by value with the printed ones, so `3.5` matches
+/// `3.5000`. A client on only one side is a difference too.
+pub fn expected_mismatches<R: Read>(
+    name: &str,
+    reader: R,
+    snapshots: &[ClientSnapshot],
+) -> Result<Vec<String>, AppError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let header = reader
+        .headers()
+        .map_err(|err| AppError::Parse(err.into()))?
+        .clone();
+    if header.get(0) != Some("client") {
+        return Err(AppError::TxProcessing(format!(
+            "Expected snapshot {name} must start with a client column"
+        )));
+    }
+    if let Some(column) = header
+        .iter()
+        .skip(1)
+        .find(|column| !COLUMNS.contains(column))
+    {
+        return Err(AppError::TxProcessing(format!(
+            "Unknown column {column} in expected snapshot {name}"
+        )));
+    }
+
+    let actual: BTreeMap<u16, &ClientSnapshot> = snapshots
+        .iter()
+        .map(|snapshot| (snapshot.client_id.0, snapshot))
+        .collect();
+    let mut expected_clients = BTreeSet::new();
+    let mut mismatches = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|err| AppError::Parse(err.into()))?;
+        let client_field = record.get(0).unwrap_or_default();
+        let client: u16 = client_field.parse().map_err(|_| {
+            AppError::TxProcessing(format!(
+                "Invalid client id {client_field:?} in expected snapshot {name}"
+            ))
+        })?;
+        expected_clients.insert(client);
+        let Some(snapshot) = actual.get(&client) else {
+            mismatches.push(format!("client {client}: expected, but has no account"));
+            continue;
+        };
+
+        let (available, held, total) = output_columns(snapshot, OUTPUT_SCALE);
+        for (column, value) in header.iter().zip(record.iter()).skip(1) {
+            let (actual, matches) = match column {
+                "locked" => (
+                    snapshot.locked.to_string(),
+                    value
+                        .parse()
+                        .ok()
+                        .map(|locked: bool| locked == snapshot.locked),
+                ),
+                amount_column => {
+                    let amount = match amount_column {
+                        "available" => available,
+                        "held" => held,
+                        _ => total,
+                    };
+                    (
+                        format!("{amount:.scale$}", scale = OUTPUT_SCALE as usize),
+                        Decimal::from_str(value)
+                            .ok()
+                            .map(|expected| expected == amount),
+                    )
+                }
+            };
+            match matches {
+                Some(true) => {}
+                Some(false) => mismatches.push(format!(
+                    "client {client}: {column} is {actual}, expected {value}"
+                )),
+                None => {
+                    return Err(AppError::TxProcessing(format!(
+                        "Invalid {column} {value:?} for client {client} in expected snapshot {name}"
+                    )));
+                }
+            }
+        }
+    }
+
+    for client in actual
+        .keys()
+        .filter(|client| !expected_clients.contains(client))
+    {
+        mismatches.push(format!(
+            "client {client}: has an account, but is not expected"
+        ));
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, ClientId};
+    use rust_decimal_macros::dec;
+
+    fn snapshot(client: u16, available: Decimal, held: Decimal) -> ClientSnapshot {
+        ClientSnapshot {
+            client_id: ClientId(client),
+            available: Amount::new(available),
+            held: Amount::new(held),
+            parked: Amount::ZERO,
+            locked: false,
+            lock_reason: None,
+        }
+    }
+
+    #[test]
+    fn checks_only_the_columns_in_the_header() {
+        let snapshots = [
+            snapshot(1, dec!(3.0), dec!(0.5)),
+            snapshot(2, dec!(1.25), dec!(0)),
+        ];
+        let totals_only = "client,total\n1,3.5\n2,1.2500\n";
+
+        let mismatches = expected_mismatches("totals.csv", totals_only.as_bytes(), &snapshots);
+        assert_eq!(mismatches.unwrap(), Vec::<String>::new());
+
+        // Same totals, held moved to available: still a match.
+        let released = [
+            snapshot(1, dec!(3.5), dec!(0)),
+            snapshot(2, dec!(1.25), dec!(0)),
+        ];
+        let mismatches = expected_mismatches("totals.csv", totals_only.as_bytes(), &released);
+        assert_eq!(mismatches.unwrap(), Vec::<String>::new());
+
+        let with_held = "client,held,locked\n1,0,false\n3,0,false\n";
+        let mismatches = expected_mismatches("held.csv", with_held.as_bytes(), &snapshots);
+        assert_eq!(
+            mismatches.unwrap(),
+            [
+                "client 1: held is 0.5000, expected 0",
+                "client 3: expected, but has no account",
+                "client 2: has an account, but is not expected",
+            ]
+        );
+
+        let err = expected_mismatches("bad.csv", "client,parked\n".as_bytes(), &snapshots);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Unknown column parked in expected snapshot bad.csv"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    domain::types::{Amount, ClientId, TransactionType, TxID},
+    io::input::Transaction,
+};
+
+/// Amounts are generated in units of 0.0001, up to 1000.0000 per deposit.
+const AMOUNT_SCALE: u32 = 4;
+const MAX_DEPOSIT_UNITS: u64 = 10_000_000;
+/// Share of money rows that are withdrawals, when the client has funds.
+const WITHDRAWAL_SHARE: f64 = 0.3;
+/// Share of closed disputes that end in a chargeback rather than a resolve.
+const CHARGEBACK_SHARE: f64 = 0.2;
+
+/// Settings for the `generate` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerateOptions {
+    pub rows: usize,
+    /// Client ids are `1..=clients`.
+    pub clients: u16,
+    /// Chance that a row is a dispute, resolve or chargeback rather than a deposit or
+    /// withdrawal. A row falls back to a deposit or withdrawal when no such op is valid.
+    pub dispute_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            rows: 1000,
+            clients: 10,
+            dispute_rate: 0.05,
+            seed: 0,
+        }
+    }
+}
+
+/// SplitMix64. Small and fully specified, so a seed reproduces the same file on every
+/// platform and release.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform enough in `0..bound` for test data; `bound` must not be zero.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// What the generator knows of each client, mirroring the engine's balances.
+#[derive(Default)]
+struct ClientState {
+    available: u64,
+    /// Deposits not yet disputed, with their amount in units.
+    deposits: Vec<(TxID, u64)>,
+    open_disputes: Vec<(TxID, u64)>,
+}
+
+struct Generator {
+    rng: SplitMix64,
+    clients: Vec<ClientState>,
+    /// Indices of clients not locked by a chargeback.
+    unlocked: Vec<usize>,
+    next_tx_id: u32,
+}
+
+impl Generator {
+    fn row(&mut self, dispute_rate: f64) -> Transaction {
+        let lifecycle = if self.rng.chance(dispute_rate) {
+            self.lifecycle_row()
+        } else {
+            None
+        };
+        lifecycle.unwrap_or_else(|| self.money_row())
+    }
+
+    fn money_row(&mut self) -> Transaction {
+        let index = self.unlocked[self.rng.below(self.unlocked.len())];
+        let tx_id = TxID(self.next_tx_id);
+        self.next_tx_id += 1;
+
+        let client = &mut self.clients[index];
+        if client.available > 0 && self.rng.chance(WITHDRAWAL_SHARE) {
+            let units = 1 + self.rng.below(client.available as usize) as u64;
+            client.available -= units;
+            transaction(TransactionType::Withdrawal, index, tx_id, Some(units))
+        } else {
+            let units = 1 + self.rng.below(MAX_DEPOSIT_UNITS as usize) as u64;
+            client.available += units;
+            client.deposits.push((tx_id, units));
+            transaction(TransactionType::Deposit, index, tx_id, Some(units))
+        }
+    }
+
+    /// Closes an open dispute or opens a new one on a random unlocked client, or returns
+    /// `None` if that client has nothing valid to do.
+    fn lifecycle_row(&mut self) -> Option<Transaction> {
+        let position = self.rng.below(self.unlocked.len());
+        let index = self.unlocked[position];
+        let can_lock = self.unlocked.len() > 1;
+        let client = &mut self.clients[index];
+
+        if !client.open_disputes.is_empty() && (client.deposits.is_empty() || self.rng.chance(0.5))
+        {
+            let dispute = self.rng.below(client.open_disputes.len());
+            let (tx_id, units) = client.open_disputes.swap_remove(dispute);
+            if can_lock && self.rng.chance(CHARGEBACK_SHARE) {
+                self.unlocked.swap_remove(position);
+                return Some(transaction(TransactionType::Chargeback, index, tx_id, None));
+            }
+            client.available += units;
+            return Some(transaction(TransactionType::Resolve, index, tx_id, None));
+        }
+
+        if client.deposits.is_empty() {
+            return None;
+        }
+        let deposit = self.rng.below(client.deposits.len());
+        let (tx_id, units) = client.deposits[deposit];
+        if units > client.available {
+            return None;
+        }
+        client.deposits.swap_remove(deposit);
+        client.available -= units;
+        client.open_disputes.push((tx_id, units));
+        Some(transaction(TransactionType::Dispute, index, tx_id, None))
+    }
+}
+
+fn transaction(
+    op_type: TransactionType,
+    client_index: usize,
+    tx_id: TxID,
+    units: Option<u64>,
+) -> Transaction {
+    Transaction {
+        op_type,
+        client: ClientId(client_index as u16 + 1),
+        tx_id,
+        amount: units.map(|units| Amount::new(Decimal::new(units as i64, AMOUNT_SCALE))),
+        reason: None,
+        row_digest: None,
+    }
+}
+
+/// Writes `options.rows` transactions that all apply cleanly on a fresh engine:
+/// withdrawals stay within the available balance, disputes only target a client's own
+/// deposits while available still covers them, and no deposit is disputed twice. A
+/// chargeback never locks the last unlocked client.
+pub fn write_generated_csv<W: Write>(writer: W, options: &GenerateOptions) -> io::Result<()> {
+    let clients = usize::from(options.clients.max(1));
+    let mut generator = Generator {
+        rng: SplitMix64(options.seed),
+        clients: (0..clients).map(|_| ClientState::default()).collect(),
+        unlocked: (0..clients).collect(),
+        next_tx_id: 1,
+    };
+
+    let mut writer = csv::Writer::from_writer(writer);
+    for _ in 0..options.rows {
+        writer.serialize(generator.row(options.dispute_rate))?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::parse_transactions_from_reader;
+    use crate::processing::{process_all, RowDisposition};
+    use crate::tx_engine::TxEngine;
+    use std::io::Cursor;
+
+    fn generate(options: &GenerateOptions) -> Vec<u8> {
+        let mut written = Vec::new();
+        write_generated_csv(&mut written, options).unwrap();
+        written
+    }
+
+    #[test]
+    fn same_seed_generates_identical_valid_feed() {
+        let options = GenerateOptions {
+            rows: 500,
+            clients: 5,
+            dispute_rate: 0.3,
+            seed: 42,
+        };
+
+        let first = generate(&options);
+        let second = generate(&options);
+        let reseeded = generate(&GenerateOptions { seed: 7, ..options });
+
+        assert_eq!(first, second);
+        assert_ne!(first, reseeded);
+        let text = String::from_utf8(first).unwrap();
+        assert!(text.starts_with("type,client,tx,amount,reason\n"));
+        assert_eq!(text.lines().count(), 501);
+
+        let mut engine = TxEngine::new();
+        let dispositions = process_all(
+            &mut engine,
+            parse_transactions_from_reader(Cursor::new(text)),
+        )
+        .unwrap();
+        assert_eq!(dispositions.len(), 500);
+        assert!(dispositions
+            .iter()
+            .all(|disposition| *disposition == RowDisposition::Applied));
+        assert!(engine.stats().disputes > 0);
+        assert!(engine.check_invariants().is_empty());
+    }
+}
@@ -1,28 +1,399 @@
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::rc::Rc;
 
 use crate::domain::types::{Amount, ClientId, TransactionType, TxID};
 
-#[derive(Debug, Deserialize)]
-pub struct Transaction {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    Bin,
+}
+
+/// Which CSV fields have surrounding whitespace removed before parsing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrimMode {
+    #[default]
+    All,
+    Headers,
+    Fields,
+    None,
+}
+
+impl TrimMode {
+    fn csv_trim(self) -> csv::Trim {
+        match self {
+            TrimMode::All => csv::Trim::All,
+            TrimMode::Headers => csv::Trim::Headers,
+            TrimMode::Fields => csv::Trim::Fields,
+            TrimMode::None => csv::Trim::None,
+        }
+    }
+
+    fn trims_headers(self) -> bool {
+        matches!(self, TrimMode::All | TrimMode::Headers)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Transaction<M = Decimal> {
     #[serde(rename = "type")]
     pub op_type: TransactionType,
     pub client: ClientId,
     #[serde(rename = "tx")]
     pub tx_id: TxID,
-    pub amount: Option<Amount>,
+    pub amount: Option<Amount<M>>,
+    /// Free-text reason code on dispute, resolve and chargeback rows. The column is
+    /// optional, so four-column files still parse.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Digest of the row's fields exactly as the reader read them, after `--trim`, used
+    /// to detect repeated rows. `None` for transactions not read from input.
+    #[serde(skip)]
+    pub row_digest: Option<RowDigest>,
+}
+
+/// Digest of the raw fields of one input row, see `row_digest`.
+pub type RowDigest = [u8; 16];
+
+/// SHA-256 over `fields`, each prefixed with its length so that moving bytes between
+/// fields changes the digest, cut to 128 bits. Collisions are negligible at any input
+/// size, while a digest stays far smaller than the row it stands for.
+pub fn row_digest<'a>(fields: impl IntoIterator<Item = &'a [u8]>) -> RowDigest {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    let mut digest = [0; 16];
+    digest.copy_from_slice(&hasher.finalize()[..16]);
+    digest
+}
+
+/// Deserializes `record` and stamps the result with `digest`.
+fn deserialize_row(
+    record: &csv::StringRecord,
+    headers: Option<&csv::StringRecord>,
+    digest: RowDigest,
+) -> Result<Transaction, ParseTransactionsError> {
+    let mut tx: Transaction = record.deserialize(headers)?;
+    tx.row_digest = Some(digest);
+    Ok(tx)
+}
+
+/// Positions of `tx` and `amount` in headerless input.
+const TX_COLUMN: usize = 2;
+const AMOUNT_COLUMN: usize = 3;
+
+/// Digits a `Decimal` holds for any value. Some 29-digit values fit too, but an
+/// amount that long is far outside anything a ledger sees.
+const MAX_AMOUNT_DIGITS: usize = 28;
+
+/// CSV reader settings shared by the strict and lossy readers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// When `false`, the first line is data and columns are read in the fixed
+    /// `type,client,tx,amount` order.
+    pub has_headers: bool,
+    /// Rows with any field longer than this many bytes are rejected, including
+    /// extra columns the engine otherwise ignores.
+    pub max_field_len: Option<usize>,
+    /// Without field trimming, `type`, `client`, `tx` and `amount` must not be padded.
+    pub trim: TrimMode,
+    /// Field separator, `,` by default.
+    pub delimiter: u8,
+    /// Reads a comma in the amount as the decimal separator, e.g. `5,50`. Needs a
+    /// delimiter other than `,`.
+    pub decimal_comma: bool,
+    /// Reads a row that ends before the `amount` column, or before a column after it,
+    /// as if the missing fields were empty, instead of failing it.
+    pub pad_short_records: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            has_headers: true,
+            max_field_len: None,
+            trim: TrimMode::All,
+            delimiter: b',',
+            decimal_comma: false,
+            pad_short_records: false,
+        }
+    }
+}
+
+impl CsvOptions {
+    fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .trim(self.trim.csv_trim())
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(self.pad_short_records);
+        builder
+    }
+
+    /// With `pad_short_records`, appends empty fields to a row that stops at or after
+    /// the `amount` column, up to the header's width; without headers, up to `amount`.
+    /// Shorter rows, and rows wider than the header, fail as they would unpadded.
+    fn pad_record<'r>(
+        &self,
+        record: &'r csv::StringRecord,
+        headers: Option<&csv::StringRecord>,
+        line: u64,
+    ) -> Result<Cow<'r, csv::StringRecord>, ParseTransactionsError> {
+        if !self.pad_short_records {
+            return Ok(Cow::Borrowed(record));
+        }
+        let width = headers.map_or(AMOUNT_COLUMN + 1, csv::StringRecord::len);
+        let first_optional = column_index(headers, "amount", AMOUNT_COLUMN).unwrap_or(width);
+        if record.len() < first_optional || (headers.is_some() && record.len() > width) {
+            return Err(unequal_fields(line, record.len(), width, headers));
+        }
+        if record.len() >= width {
+            return Ok(Cow::Borrowed(record));
+        }
+        let mut padded = record.clone();
+        for _ in record.len()..width {
+            padded.push_field("");
+        }
+        Ok(Cow::Owned(padded))
+    }
+
+    /// With `decimal_comma`, rewrites an amount such as `5,50` as `5.50` before it is
+    /// parsed. Only the decimal separator is handled; `1.234,50` still fails to parse.
+    fn localize_amount<'r>(
+        &self,
+        record: &'r csv::StringRecord,
+        headers: Option<&csv::StringRecord>,
+    ) -> Cow<'r, csv::StringRecord> {
+        if !self.decimal_comma {
+            return Cow::Borrowed(record);
+        }
+        match column_index(headers, "amount", AMOUNT_COLUMN) {
+            Some(column)
+                if record
+                    .get(column)
+                    .is_some_and(|amount| amount.contains(',')) =>
+            {
+                Cow::Owned(
+                    record
+                        .iter()
+                        .enumerate()
+                        .map(|(index, field)| {
+                            if index == column {
+                                field.replace(',', ".")
+                            } else {
+                                field.to_string()
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            _ => Cow::Borrowed(record),
+        }
+    }
+
+    fn check_field_lengths(&self, record: &csv::ByteRecord) -> Result<(), ParseTransactionsError> {
+        let Some(max) = self.max_field_len else {
+            return Ok(());
+        };
+        match record.iter().position(|field| field.len() > max) {
+            Some(index) => Err(ParseTransactionsError::FieldTooLong {
+                line: record.position().map_or(0, |position| position.line()),
+                field: index + 1,
+                len: record[index].len(),
+                max,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The error for a row with `len` fields where `expected` were expected, naming the
+/// missing columns so a missing `amount` column reads differently from an empty amount.
+fn unequal_fields(
+    line: u64,
+    len: usize,
+    expected: usize,
+    headers: Option<&csv::StringRecord>,
+) -> ParseTransactionsError {
+    const FIXED_COLUMNS: [&str; AMOUNT_COLUMN + 1] = ["type", "client", "tx", "amount"];
+    let missing = (len..expected)
+        .map(|index| match headers {
+            Some(headers) => headers.get(index).unwrap_or_default().to_string(),
+            None => FIXED_COLUMNS
+                .get(index)
+                .map_or_else(|| format!("column {}", index + 1), |name| name.to_string()),
+        })
+        .collect();
+    ParseTransactionsError::UnequalFields {
+        line,
+        len,
+        expected,
+        missing,
+    }
+}
+
+/// Turns the CSV reader's length mismatch into `UnequalFields`, which names what is
+/// missing; other errors are kept as they are.
+fn record_error(err: csv::Error, headers: Option<&csv::StringRecord>) -> ParseTransactionsError {
+    match err.kind() {
+        csv::ErrorKind::UnequalLengths {
+            pos,
+            expected_len,
+            len,
+        } => unequal_fields(
+            pos.as_ref().map_or(0, csv::Position::line),
+            *len as usize,
+            *expected_len as usize,
+            headers,
+        ),
+        _ => err.into(),
+    }
+}
+
+fn column_index(headers: Option<&csv::StringRecord>, name: &str, fixed: usize) -> Option<usize> {
+    match headers {
+        Some(headers) => headers.iter().position(|header| header == name),
+        None => Some(fixed),
+    }
+}
+
+/// Digits the amount needs in a `Decimal`: leading integer zeros and trailing fraction
+/// zeros don't count. `None` for text that isn't a plain decimal number, which is left
+/// for deserializing to reject.
+fn amount_digits(amount: &str) -> Option<usize> {
+    let unsigned = amount.strip_prefix(['-', '+']).unwrap_or(amount);
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if !integer
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(integer.trim_start_matches('0').len() + fraction.trim_end_matches('0').len())
+}
+
+/// Rejects an amount with more digits than a `Decimal` holds, which would otherwise
+/// surface as an opaque deserialize error.
+fn check_amount_precision(
+    record: &csv::StringRecord,
+    headers: Option<&csv::StringRecord>,
+    line: u64,
+) -> Result<(), ParseTransactionsError> {
+    let Some(amount) =
+        column_index(headers, "amount", AMOUNT_COLUMN).and_then(|column| record.get(column))
+    else {
+        return Ok(());
+    };
+    match amount_digits(amount) {
+        Some(digits) if digits > MAX_AMOUNT_DIGITS => {
+            let tx = column_index(headers, "tx", TX_COLUMN)
+                .and_then(|column| record.get(column))
+                .unwrap_or_default();
+            Err(ParseTransactionsError::AmountTooPrecise {
+                line,
+                tx: tx.to_string(),
+                digits,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Somewhere CSV transactions can be read from. Files and in-memory text share the
+/// same parsing path, so tests don't need temp files.
+pub trait TransactionSource {
+    type Reader: Read;
+
+    fn open(&self) -> io::Result<Self::Reader>;
+}
+
+impl TransactionSource for Path {
+    type Reader = BufReader<File>;
+
+    fn open(&self) -> io::Result<Self::Reader> {
+        Ok(BufReader::new(File::open(self)?))
+    }
 }
 
-pub type TransactionRecords = csv::DeserializeRecordsIntoIter<BufReader<File>, Transaction>;
-pub type TransactionRecordsFromReader<R> = csv::DeserializeRecordsIntoIter<R, Transaction>;
+/// CSV text held in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct InMemorySource<'a>(pub &'a str);
+
+impl<'a> TransactionSource for InMemorySource<'a> {
+    type Reader = &'a [u8];
+
+    fn open(&self) -> io::Result<Self::Reader> {
+        Ok(self.0.as_bytes())
+    }
+}
+
+pub type TransactionRecords = TransactionRecordsFromReader<BufReader<File>>;
+pub type LossyTransactionRecords = LossyTransactionRecordsFromReader<BufReader<File>>;
 
 #[derive(Debug)]
 pub enum ParseTransactionsError {
     Io(std::io::Error),
     Csv(csv::Error),
+    NonIncreasingTxId {
+        previous: TxID,
+        current: TxID,
+    },
+    InvalidBinaryRecord {
+        record: u64,
+        reason: String,
+    },
+    FieldTooLong {
+        line: u64,
+        field: usize,
+        len: usize,
+        max: usize,
+    },
+    AmountTooPrecise {
+        line: u64,
+        /// The raw `tx` field, since the row has not been deserialized.
+        tx: String,
+        digits: usize,
+    },
+    /// A row with fewer or more fields than the header, or than the first row without
+    /// one.
+    UnequalFields {
+        line: u64,
+        len: usize,
+        expected: usize,
+        /// Names of the columns the row stops short of; empty for a row that is too wide.
+        missing: Vec<String>,
+    },
+}
+
+impl ParseTransactionsError {
+    /// Whether reading the input failed, as opposed to the data being malformed.
+    pub fn is_read_failure(&self) -> bool {
+        match self {
+            ParseTransactionsError::Io(_) => true,
+            ParseTransactionsError::Csv(err) => matches!(err.kind(), csv::ErrorKind::Io(_)),
+            ParseTransactionsError::NonIncreasingTxId { .. }
+            | ParseTransactionsError::InvalidBinaryRecord { .. }
+            | ParseTransactionsError::FieldTooLong { .. }
+            | ParseTransactionsError::AmountTooPrecise { .. }
+            | ParseTransactionsError::UnequalFields { .. } => false,
+        }
+    }
 }
 
 impl Display for ParseTransactionsError {
@@ -30,6 +401,49 @@ impl Display for ParseTransactionsError {
         match self {
             ParseTransactionsError::Io(err) => write!(f, "{err}"),
             ParseTransactionsError::Csv(err) => write!(f, "{err}"),
+            ParseTransactionsError::NonIncreasingTxId { previous, current } => write!(
+                f,
+                "Transaction ID {current} is not greater than previous transaction ID {previous}"
+            ),
+            ParseTransactionsError::InvalidBinaryRecord { record, reason } => {
+                write!(f, "Invalid binary record {record}: {reason}")
+            }
+            ParseTransactionsError::FieldTooLong {
+                line,
+                field,
+                len,
+                max,
+            } => write!(
+                f,
+                "Field {field} on line {line} is {len} bytes long, more than the limit of {max}"
+            ),
+            ParseTransactionsError::AmountTooPrecise { line, tx, digits } => write!(
+                f,
+                "Amount of transaction {tx} on line {line} exceeds supported precision: \
+                 {digits} digits, at most {MAX_AMOUNT_DIGITS} are supported"
+            ),
+            ParseTransactionsError::UnequalFields {
+                line,
+                len,
+                expected,
+                missing,
+            } => {
+                write!(
+                    f,
+                    "Row on line {line} has {len} fields, {expected} expected"
+                )?;
+                if !missing.is_empty() {
+                    write!(f, "; missing {}", missing.join(", "))?;
+                }
+                if missing.first().is_some_and(|column| column == "amount") {
+                    write!(
+                        f,
+                        " (an empty amount needs the field, e.g. a trailing comma, \
+                         or --pad-short-records)"
+                    )?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -39,6 +453,11 @@ impl Error for ParseTransactionsError {
         match self {
             ParseTransactionsError::Io(err) => Some(err),
             ParseTransactionsError::Csv(err) => Some(err),
+            ParseTransactionsError::NonIncreasingTxId { .. }
+            | ParseTransactionsError::InvalidBinaryRecord { .. }
+            | ParseTransactionsError::FieldTooLong { .. }
+            | ParseTransactionsError::AmountTooPrecise { .. }
+            | ParseTransactionsError::UnequalFields { .. } => None,
         }
     }
 }
@@ -55,24 +474,373 @@ impl From<csv::Error> for ParseTransactionsError {
     }
 }
 
+/// Fails on deposit, withdrawal and correction rows whose tx id is not strictly greater
+/// than the previous such tx id. Disputes, resolves and chargebacks reference older ids
+/// and pass through unchecked.
+pub struct IncreasingTxIds<I> {
+    inner: I,
+    last_tx_id: Option<TxID>,
+}
+
+impl<I> IncreasingTxIds<I> {
+    pub fn new(inner: I) -> Self {
+        IncreasingTxIds {
+            inner,
+            last_tx_id: None,
+        }
+    }
+}
+
+impl<I, E> Iterator for IncreasingTxIds<I>
+where
+    I: Iterator<Item = Result<Transaction, E>>,
+    E: Into<ParseTransactionsError>,
+{
+    type Item = Result<Transaction, ParseTransactionsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tx = match self.inner.next()? {
+            Ok(tx) => tx,
+            Err(err) => return Some(Err(err.into())),
+        };
+
+        match tx.op_type {
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Correction => {
+                if let Some(previous) = self.last_tx_id {
+                    if tx.tx_id <= previous {
+                        return Some(Err(ParseTransactionsError::NonIncreasingTxId {
+                            previous,
+                            current: tx.tx_id,
+                        }));
+                    }
+                }
+                self.last_tx_id = Some(tx.tx_id);
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {}
+        }
+
+        Some(Ok(tx))
+    }
+}
+
+/// Passes rows through unchanged while recording the largest decimal scale among their
+/// amounts in `max_scale`, which the caller reads once the rows are consumed. Trailing
+/// zeros count, so `1.50` has scale 2.
+pub struct TrackAmountScale<I> {
+    inner: I,
+    max_scale: Rc<Cell<u32>>,
+}
+
+impl<I> TrackAmountScale<I> {
+    pub fn new(inner: I, max_scale: Rc<Cell<u32>>) -> Self {
+        TrackAmountScale { inner, max_scale }
+    }
+}
+
+impl<I, E> Iterator for TrackAmountScale<I>
+where
+    I: Iterator<Item = Result<Transaction, E>>,
+{
+    type Item = Result<Transaction, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.inner.next()?;
+        if let Ok(Transaction {
+            amount: Some(amount),
+            ..
+        }) = &row
+        {
+            let scale = amount.inner().scale();
+            if scale > self.max_scale.get() {
+                self.max_scale.set(scale);
+            }
+        }
+        Some(row)
+    }
+}
+
+/// Ends the rows at the first read failure and keeps that error in `failure` for the
+/// caller, so the rows read before it can still be used. Malformed rows pass through.
+pub struct StopAtReadFailure<I> {
+    inner: I,
+    failure: Rc<RefCell<Option<ParseTransactionsError>>>,
+}
+
+impl<I> StopAtReadFailure<I> {
+    pub fn new(inner: I, failure: Rc<RefCell<Option<ParseTransactionsError>>>) -> Self {
+        StopAtReadFailure { inner, failure }
+    }
+}
+
+impl<I> Iterator for StopAtReadFailure<I>
+where
+    I: Iterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    type Item = Result<Transaction, ParseTransactionsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failure.borrow().is_some() {
+            return None;
+        }
+        match self.inner.next()? {
+            Err(err) if err.is_read_failure() => {
+                *self.failure.borrow_mut() = Some(err);
+                None
+            }
+            row => Some(row),
+        }
+    }
+}
+
+/// Reads rows as raw records so field lengths can be checked before deserializing.
+///
+/// A bad row is yielded as an error and reading goes on with the next row, since the
+/// csv reader has already consumed the whole record. An unterminated quote is the
+/// exception: quoted fields may span lines, so it runs to the end of the input. A bad
+/// header row is yielded once and ends the rows, as no row can be read without it.
+pub struct TransactionRecordsFromReader<R> {
+    reader: csv::Reader<R>,
+    options: CsvOptions,
+    headers: Option<csv::StringRecord>,
+    record: csv::StringRecord,
+    header_failed: bool,
+}
+
+impl<R: Read> Iterator for TransactionRecordsFromReader<R> {
+    type Item = Result<Transaction, ParseTransactionsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.header_failed {
+            return None;
+        }
+        if self.options.has_headers && self.headers.is_none() {
+            match self.reader.headers() {
+                Ok(headers) => self.headers = Some(normalize_headers(headers, self.options.trim)),
+                Err(err) => {
+                    self.header_failed = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+
+        match self.reader.read_record(&mut self.record) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some(Err(record_error(err, self.headers.as_ref()))),
+        }
+        if let Err(err) = self
+            .options
+            .check_field_lengths(self.record.as_byte_record())
+        {
+            return Some(Err(err));
+        }
+
+        let digest = row_digest(self.record.as_byte_record());
+        let line = self.record.position().map_or(0, |position| position.line());
+        let record = match self
+            .options
+            .pad_record(&self.record, self.headers.as_ref(), line)
+        {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err)),
+        };
+        let record = self.options.localize_amount(&record, self.headers.as_ref());
+        if let Err(err) = check_amount_precision(&record, self.headers.as_ref(), line) {
+            return Some(Err(err));
+        }
+        Some(deserialize_row(&record, self.headers.as_ref(), digest))
+    }
+}
+
+/// Decodes each row with lossy UTF-8 conversion so an invalid byte only replaces the
+/// offending characters instead of failing the row. Numeric fields are still parsed
+/// strictly from the decoded text. Recovers from bad rows the same way as
+/// [`TransactionRecordsFromReader`].
+pub struct LossyTransactionRecordsFromReader<R> {
+    reader: csv::Reader<R>,
+    options: CsvOptions,
+    headers: Option<csv::StringRecord>,
+    record: csv::ByteRecord,
+    header_failed: bool,
+}
+
+impl<R: Read> Iterator for LossyTransactionRecordsFromReader<R> {
+    type Item = Result<Transaction, ParseTransactionsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.header_failed {
+            return None;
+        }
+        if self.options.has_headers && self.headers.is_none() {
+            let headers = match self.reader.byte_headers() {
+                Ok(headers) => headers,
+                Err(err) => {
+                    self.header_failed = true;
+                    return Some(Err(err.into()));
+                }
+            };
+            self.headers = Some(normalize_headers(
+                &decode_lossy(headers).0,
+                self.options.trim,
+            ));
+        }
+
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some(Err(record_error(err, self.headers.as_ref()))),
+        }
+        if let Err(err) = self.options.check_field_lengths(&self.record) {
+            return Some(Err(err));
+        }
+
+        let digest = row_digest(&self.record);
+        let (record, replaced) = decode_lossy(&self.record);
+        let line = self.record.position().map_or(0, |position| position.line());
+        if replaced {
+            log::warn!("Invalid UTF-8 replaced in row at line {line}");
+        }
+        let record = match self
+            .options
+            .pad_record(&record, self.headers.as_ref(), line)
+        {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err)),
+        };
+        let record = self.options.localize_amount(&record, self.headers.as_ref());
+        if let Err(err) = check_amount_precision(&record, self.headers.as_ref(), line) {
+            return Some(Err(err));
+        }
+        Some(deserialize_row(&record, self.headers.as_ref(), digest))
+    }
+}
+
+/// Strips the quotes the CSV parser leaves on a quoted header preceded by spaces, such
+/// as `type, "client"`, so it still matches the field name. Headers are left as read
+/// unless `trim` covers them.
+fn normalize_headers(headers: &csv::StringRecord, trim: TrimMode) -> csv::StringRecord {
+    if !trim.trims_headers() {
+        return headers.clone();
+    }
+    headers
+        .iter()
+        .map(|header| {
+            let header = header.trim();
+            header
+                .strip_prefix('"')
+                .and_then(|header| header.strip_suffix('"'))
+                .map_or(header, str::trim)
+        })
+        .collect()
+}
+
+fn decode_lossy(record: &csv::ByteRecord) -> (csv::StringRecord, bool) {
+    let mut replaced = false;
+    let fields = record.iter().map(|field| {
+        let decoded = String::from_utf8_lossy(field);
+        replaced |= matches!(decoded, std::borrow::Cow::Owned(_));
+        decoded.into_owned()
+    });
+    let record = csv::StringRecord::from_iter(fields);
+    (record, replaced)
+}
+
+pub fn parse_transactions_lossy_from_reader<R: Read>(
+    reader: R,
+    options: &CsvOptions,
+) -> LossyTransactionRecordsFromReader<R> {
+    let csv_reader = options.reader_builder().from_reader(reader);
+
+    LossyTransactionRecordsFromReader {
+        reader: csv_reader,
+        options: *options,
+        headers: None,
+        header_failed: false,
+        record: csv::ByteRecord::new(),
+    }
+}
+
+pub fn parse_transactions_lossy(
+    input_path: &str,
+    options: &CsvOptions,
+) -> Result<LossyTransactionRecords, ParseTransactionsError> {
+    parse_transactions_lossy_from_source(Path::new(input_path), options)
+}
+
+pub fn parse_transactions_lossy_from_source<S: TransactionSource + ?Sized>(
+    source: &S,
+    options: &CsvOptions,
+) -> Result<LossyTransactionRecordsFromReader<S::Reader>, ParseTransactionsError> {
+    Ok(parse_transactions_lossy_from_reader(
+        source.open()?,
+        options,
+    ))
+}
+
 pub fn parse_transactions_from_reader<R: Read>(reader: R) -> TransactionRecordsFromReader<R> {
-    let csv_reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_reader(reader);
+    parse_transactions_from_reader_with_options(reader, &CsvOptions::default())
+}
 
-    csv_reader.into_deserialize::<Transaction>()
+pub fn parse_transactions_from_reader_with_options<R: Read>(
+    reader: R,
+    options: &CsvOptions,
+) -> TransactionRecordsFromReader<R> {
+    let csv_reader = options.reader_builder().from_reader(reader);
+
+    TransactionRecordsFromReader {
+        reader: csv_reader,
+        options: *options,
+        headers: None,
+        header_failed: false,
+        record: csv::StringRecord::new(),
+    }
 }
 
 pub fn parse_transactions(input_path: &str) -> Result<TransactionRecords, ParseTransactionsError> {
-    let file = File::open(input_path)?;
-    let reader = BufReader::new(file);
+    parse_transactions_with_options(input_path, &CsvOptions::default())
+}
+
+pub fn parse_transactions_with_options(
+    input_path: &str,
+    options: &CsvOptions,
+) -> Result<TransactionRecords, ParseTransactionsError> {
+    parse_transactions_from_source(Path::new(input_path), options)
+}
+
+pub fn parse_transactions_from_source<S: TransactionSource + ?Sized>(
+    source: &S,
+    options: &CsvOptions,
+) -> Result<TransactionRecordsFromReader<S::Reader>, ParseTransactionsError> {
+    Ok(parse_transactions_from_reader_with_options(
+        source.open()?,
+        options,
+    ))
+}
 
-    Ok(parse_transactions_from_reader(reader))
+/// Parses one headerless CSV record in `type,client,tx,amount[,reason]` order, trimmed
+/// like file input, for rows that arrive one at a time. Anything after the first line
+/// is ignored.
+pub fn parse_transaction_line(line: &str) -> Result<Transaction, ParseTransactionsError> {
+    let options = CsvOptions {
+        has_headers: false,
+        ..CsvOptions::default()
+    };
+    let mut record = csv::StringRecord::new();
+    options
+        .reader_builder()
+        .from_reader(line.as_bytes())
+        .read_record(&mut record)?;
+    check_amount_precision(&record, None, 1)?;
+    deserialize_row(&record, None, row_digest(record.as_byte_record()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::processing::{process_all, RowDisposition};
+    use crate::tx_engine::TxEngine;
     use rust_decimal_macros::dec;
     use std::io::Cursor;
 
@@ -116,6 +884,143 @@ dispute,5,42,
         assert_eq!(tx.amount, None);
     }
 
+    #[test]
+    fn parses_optional_reason_column() {
+        let csv = "\
+type,client,tx,amount,reason
+deposit,1,1,1.0,
+dispute,1,1,,\"fraud, card stolen\"
+";
+        let headerless = "\
+dispute,1,1,,fraud
+resolve,1,1,,
+";
+        let options = CsvOptions {
+            has_headers: false,
+            ..CsvOptions::default()
+        };
+
+        let rows: Result<Vec<_>, _> = parse_transactions_from_reader(Cursor::new(csv.as_bytes()))
+            .chain(parse_transactions_from_reader_with_options(
+                Cursor::new(headerless.as_bytes()),
+                &options,
+            ))
+            .collect();
+        let rows = rows.expect("rows must parse");
+
+        assert_eq!(rows[0].reason, None);
+        assert_eq!(rows[1].reason.as_deref(), Some("fraud, card stolen"));
+        assert_eq!(rows[2].reason.as_deref(), Some("fraud"));
+        assert_eq!(rows[3].reason, None);
+    }
+
+    #[test]
+    fn in_memory_source_parses_like_a_file() {
+        let source = InMemorySource(
+            "\
+type, client, tx, amount
+deposit, 1, 10, 1.2345
+dispute, 1, 10,
+",
+        );
+        let options = CsvOptions::default();
+
+        let strict: Result<Vec<_>, _> = parse_transactions_from_source(&source, &options)
+            .expect("in-memory source must open")
+            .collect();
+        let lossy: Result<Vec<_>, _> = parse_transactions_lossy_from_source(&source, &options)
+            .expect("in-memory source must open")
+            .collect();
+
+        for rows in [
+            strict.expect("rows must parse"),
+            lossy.expect("rows must parse"),
+        ] {
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].op_type, TransactionType::Deposit);
+            assert_eq!(rows[0].client, ClientId(1));
+            assert_eq!(rows[0].tx_id, TxID(10));
+            assert_eq!(rows[0].amount, Some(Amount::new(dec!(1.2345))));
+            assert_eq!(rows[1].op_type, TransactionType::Dispute);
+            assert_eq!(rows[1].amount, None);
+        }
+    }
+
+    #[test]
+    fn accepts_quoted_headers() {
+        let csv = "\
+\"type\", \"client\" , \"tx\",\"amount\"
+deposit,1,1,2.5
+";
+
+        let strict: Result<Vec<_>, _> =
+            parse_transactions_from_reader(Cursor::new(csv.as_bytes())).collect();
+        let lossy: Result<Vec<_>, _> = parse_transactions_lossy_from_reader(
+            Cursor::new(csv.as_bytes()),
+            &CsvOptions::default(),
+        )
+        .collect();
+
+        for rows in [
+            strict.expect("quoted headers must be accepted"),
+            lossy.expect("quoted headers must be accepted"),
+        ] {
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].client, ClientId(1));
+            assert_eq!(rows[0].amount, Some(Amount::new(dec!(2.5))));
+        }
+    }
+
+    /// Returns `data`, then fails every later read.
+    struct FailingReader {
+        data: Cursor<&'static [u8]>,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.data.read(buf)? {
+                0 => Err(io::Error::new(
+                    io::ErrorKind::ConnectionReset,
+                    "mount went away",
+                )),
+                read => Ok(read),
+            }
+        }
+    }
+
+    #[test]
+    fn read_failure_ends_rows_and_keeps_the_partial_snapshot() {
+        use crate::io::output::snapshot_to_string;
+        use crate::processing::process_records;
+        use crate::tx_engine::TxEngine;
+        use std::sync::atomic::AtomicBool;
+
+        let reader = FailingReader {
+            data: Cursor::new(b"type,client,tx,amount\ndeposit,1,1,5.0\nwithdrawal,1,2,1.5\n"),
+        };
+        let failure = Rc::new(RefCell::new(None));
+        let records =
+            StopAtReadFailure::new(parse_transactions_from_reader(reader), Rc::clone(&failure));
+        let mut engine = TxEngine::new();
+        process_records(
+            &mut engine,
+            records,
+            false,
+            &AtomicBool::new(false),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            snapshot_to_string(&engine.clients_snapshot()),
+            "client,available,held,total,locked\n1,3.5000,0.0000,3.5000,false\n"
+        );
+        let failure = failure.take().expect("the read failure must be kept");
+        assert!(failure.is_read_failure());
+        assert!(failure.to_string().contains("mount went away"));
+    }
+
     #[test]
     fn returns_io_error_for_missing_file() {
         let missing_path = std::env::temp_dir()
@@ -127,6 +1032,21 @@ dispute,5,42,
         match result {
             Err(ParseTransactionsError::Io(_)) => {}
             Err(ParseTransactionsError::Csv(_)) => panic!("expected io error, got csv error"),
+            Err(ParseTransactionsError::NonIncreasingTxId { .. }) => {
+                panic!("expected io error, got tx id order error")
+            }
+            Err(ParseTransactionsError::InvalidBinaryRecord { .. }) => {
+                panic!("expected io error, got binary record error")
+            }
+            Err(ParseTransactionsError::FieldTooLong { .. }) => {
+                panic!("expected io error, got field length error")
+            }
+            Err(ParseTransactionsError::AmountTooPrecise { .. }) => {
+                panic!("expected io error, got amount precision error")
+            }
+            Err(ParseTransactionsError::UnequalFields { .. }) => {
+                panic!("expected io error, got field count error")
+            }
             Ok(_) => panic!("expected io error, got success"),
         }
     }
@@ -144,4 +1064,360 @@ deposit,abc,1,1.0
 
         assert!(row_result.is_err());
     }
+
+    #[test]
+    fn increasing_tx_ids_pass_and_exempt_dispute_references() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+dispute,1,1,
+withdrawal,1,5,0.5
+resolve,1,1,
+";
+        let cursor = Cursor::new(csv.as_bytes());
+
+        let rows: Result<Vec<_>, _> =
+            IncreasingTxIds::new(parse_transactions_from_reader(cursor)).collect();
+
+        assert_eq!(rows.expect("feed is increasing").len(), 5);
+    }
+
+    #[test]
+    fn decreasing_tx_id_yields_violation_error() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,3,1.0
+withdrawal,1,2,0.5
+";
+        let cursor = Cursor::new(csv.as_bytes());
+
+        let mut iter = IncreasingTxIds::new(parse_transactions_from_reader(cursor));
+        assert!(iter.next().expect("first row").is_ok());
+
+        match iter.next().expect("second row") {
+            Err(ParseTransactionsError::NonIncreasingTxId { previous, current }) => {
+                assert_eq!(previous, TxID(3));
+                assert_eq!(current, TxID(2));
+            }
+            other => panic!("expected tx id order error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn bad_rows_are_reported_and_the_next_row_still_parses() {
+        let csv = b"\
+type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,abc
+refund,1,3,1.0
+deposit,1,4,1.0,extra,fields
+deposit,1,5,caf\xff
+deposit,1,6,2.0
+";
+
+        let mut engine = TxEngine::new();
+        let dispositions = process_all(
+            &mut engine,
+            parse_transactions_from_reader(Cursor::new(&csv[..])),
+        )
+        .unwrap();
+
+        assert_eq!(dispositions.len(), 6);
+        assert_eq!(dispositions[0], RowDisposition::Applied);
+        for disposition in &dispositions[1..5] {
+            assert!(matches!(disposition, RowDisposition::ParseError(_)));
+        }
+        assert_eq!(dispositions[5], RowDisposition::Applied);
+        assert_eq!(
+            engine.clients_snapshot()[0].available,
+            Amount::new(dec!(3.0))
+        );
+
+        let bad_header = b"type,cli\xffent,tx,amount\ndeposit,1,1,1.0\n";
+        let rows: Vec<_> = parse_transactions_from_reader(Cursor::new(&bad_header[..])).collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].is_err());
+    }
+
+    #[test]
+    fn lossy_mode_replaces_invalid_utf8_and_keeps_processing() {
+        let csv = b"\
+type,client,tx,amount,description
+deposit,1,1,1.5,caf\xff
+deposit,1,2,2.0,ok
+";
+
+        let strict_first_row = parse_transactions_from_reader(Cursor::new(&csv[..]))
+            .next()
+            .expect("one row is expected");
+        assert!(strict_first_row.is_err());
+
+        let rows: Result<Vec<_>, _> =
+            parse_transactions_lossy_from_reader(Cursor::new(&csv[..]), &CsvOptions::default())
+                .collect();
+        let rows = rows.expect("lossy rows must parse");
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].tx_id, TxID(1));
+        assert_eq!(rows[0].amount, Some(Amount::new(dec!(1.5))));
+        assert_eq!(rows[1].tx_id, TxID(2));
+    }
+
+    #[test]
+    fn lossy_mode_still_rejects_invalid_numbers() {
+        let csv = b"\
+type,client,tx,amount
+deposit,1,1,1.\xff
+";
+
+        let mut iter =
+            parse_transactions_lossy_from_reader(Cursor::new(&csv[..]), &CsvOptions::default());
+
+        assert!(iter.next().expect("one row is expected").is_err());
+    }
+
+    #[test]
+    fn headerless_csv_treats_first_line_as_data() {
+        let csv = "\
+deposit,1,1,2.5
+dispute,1,1,
+";
+        let options = CsvOptions {
+            has_headers: false,
+            ..CsvOptions::default()
+        };
+
+        let strict: Result<Vec<_>, _> =
+            parse_transactions_from_reader_with_options(Cursor::new(csv.as_bytes()), &options)
+                .collect();
+        let lossy: Result<Vec<_>, _> =
+            parse_transactions_lossy_from_reader(Cursor::new(csv.as_bytes()), &options).collect();
+
+        for rows in [
+            strict.expect("rows must parse"),
+            lossy.expect("rows must parse"),
+        ] {
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].op_type, TransactionType::Deposit);
+            assert_eq!(rows[0].amount, Some(Amount::new(dec!(2.5))));
+            assert_eq!(rows[1].op_type, TransactionType::Dispute);
+            assert_eq!(rows[1].amount, None);
+        }
+    }
+
+    #[test]
+    fn trim_none_preserves_leading_space_in_reason() {
+        let csv = "\
+type,client,tx,amount,reason
+deposit,1,1,1.0,
+dispute,1,1,, chargeback requested
+";
+        let options = CsvOptions {
+            trim: TrimMode::None,
+            ..CsvOptions::default()
+        };
+
+        let rows: Vec<_> =
+            parse_transactions_from_reader_with_options(Cursor::new(csv.as_bytes()), &options)
+                .collect::<Result<_, _>>()
+                .expect("rows must parse");
+        let trimmed: Vec<_> = parse_transactions_from_reader(Cursor::new(csv.as_bytes()))
+            .collect::<Result<_, _>>()
+            .expect("rows must parse");
+
+        assert_eq!(rows[1].reason.as_deref(), Some(" chargeback requested"));
+        assert_eq!(trimmed[1].reason.as_deref(), Some("chargeback requested"));
+    }
+
+    #[test]
+    fn trim_none_and_fields_leave_padded_headers_unmatched() {
+        let csv = "type, client,tx,amount\ndeposit,1,1,1.0\n";
+
+        for trim in [TrimMode::None, TrimMode::Fields] {
+            let options = CsvOptions {
+                trim,
+                ..CsvOptions::default()
+            };
+            let result: Result<Vec<_>, _> =
+                parse_transactions_from_reader_with_options(Cursor::new(csv.as_bytes()), &options)
+                    .collect();
+            assert!(result.is_err(), "{trim:?} must not trim headers");
+        }
+        let rows: Vec<_> = parse_transactions_from_reader(Cursor::new(csv.as_bytes()))
+            .collect::<Result<_, _>>()
+            .expect("rows must parse");
+        assert_eq!(rows[0].client, ClientId(1));
+    }
+
+    #[test]
+    fn decimal_comma_reads_amount_with_semicolon_delimiter() {
+        let options = CsvOptions {
+            delimiter: b';',
+            decimal_comma: true,
+            ..CsvOptions::default()
+        };
+        let headerless = CsvOptions {
+            has_headers: false,
+            ..options
+        };
+
+        for (csv, options) in [
+            ("type;client;tx;amount\ndeposit;1;1;5,50\n", &options),
+            ("deposit;1;1;5,50\n", &headerless),
+        ] {
+            let strict: Vec<_> =
+                parse_transactions_from_reader_with_options(Cursor::new(csv.as_bytes()), options)
+                    .collect::<Result<_, _>>()
+                    .expect("rows must parse");
+            let lossy: Vec<_> =
+                parse_transactions_lossy_from_reader(Cursor::new(csv.as_bytes()), options)
+                    .collect::<Result<_, _>>()
+                    .expect("rows must parse");
+
+            for rows in [strict, lossy] {
+                assert_eq!(rows[0].op_type, TransactionType::Deposit);
+                assert_eq!(rows[0].tx_id, TxID(1));
+                assert_eq!(rows[0].amount, Some(Amount::new(dec!(5.50))));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_a_single_transaction_line() {
+        let deposit = parse_transaction_line("deposit, 1, 7, 2.5\n").unwrap();
+        assert_eq!(deposit.op_type, TransactionType::Deposit);
+        assert_eq!(deposit.client, ClientId(1));
+        assert_eq!(deposit.tx_id, TxID(7));
+        assert_eq!(deposit.amount, Some(Amount::new(dec!(2.5))));
+
+        let dispute = parse_transaction_line("dispute,1,7,,fraud").unwrap();
+        assert_eq!(dispute.op_type, TransactionType::Dispute);
+        assert_eq!(dispute.tx_id, TxID(7));
+        assert_eq!(dispute.amount, None);
+        assert_eq!(dispute.reason.as_deref(), Some("fraud"));
+
+        assert!(parse_transaction_line("").is_err());
+        assert!(parse_transaction_line("deposit,x,7,2.5").is_err());
+    }
+
+    #[test]
+    fn short_record_fails_naming_missing_amount_unless_padded() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,1,2.0
+dispute,1,1,
+dispute,1,1
+";
+        let padded = CsvOptions {
+            pad_short_records: true,
+            ..CsvOptions::default()
+        };
+
+        let rows: Vec<_> = parse_transactions_from_reader(Cursor::new(csv)).collect();
+        assert_eq!(rows[1].as_ref().unwrap().amount, None);
+        let err = rows[2]
+            .as_ref()
+            .expect_err("missing amount column must fail");
+        assert!(matches!(
+            err,
+            ParseTransactionsError::UnequalFields { line: 4, len: 3, expected: 4, missing }
+                if missing == &["amount"]
+        ));
+        assert!(err.to_string().starts_with(
+            "Row on line 4 has 3 fields, 4 expected; missing amount (an empty amount needs"
+        ));
+
+        let strict = parse_transactions_from_reader_with_options(Cursor::new(csv), &padded);
+        let lossy = parse_transactions_lossy_from_reader(Cursor::new(csv), &padded);
+        for rows in [strict.collect::<Vec<_>>(), lossy.collect()] {
+            let short = rows[2].as_ref().expect("short row is padded");
+            assert_eq!(short.op_type, TransactionType::Dispute);
+            assert_eq!(short.amount, None);
+        }
+
+        let no_tx = parse_transactions_from_reader_with_options(
+            Cursor::new("type,client,tx,amount\ndispute,1\n"),
+            &padded,
+        )
+        .next()
+        .unwrap();
+        assert!(matches!(
+            no_tx,
+            Err(ParseTransactionsError::UnequalFields { missing, .. }) if missing == ["tx", "amount"]
+        ));
+    }
+
+    #[test]
+    fn rejects_description_longer_than_limit() {
+        let csv = format!(
+            "\
+type,client,tx,amount,description
+deposit,1,1,1.0,short
+deposit,1,2,1.0,{}
+",
+            "x".repeat(65)
+        );
+        let options = CsvOptions {
+            max_field_len: Some(64),
+            ..CsvOptions::default()
+        };
+
+        let mut strict =
+            parse_transactions_from_reader_with_options(Cursor::new(csv.as_bytes()), &options);
+        let mut lossy = parse_transactions_lossy_from_reader(Cursor::new(csv.as_bytes()), &options);
+
+        for iter in [&mut strict as &mut dyn Iterator<Item = _>, &mut lossy] {
+            assert!(iter.next().expect("first row is expected").is_ok());
+            let err = iter
+                .next()
+                .expect("second row is expected")
+                .expect_err("long description must be rejected");
+            assert!(matches!(
+                err,
+                ParseTransactionsError::FieldTooLong {
+                    line: 3,
+                    field: 5,
+                    len: 65,
+                    max: 64
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_amount_beyond_decimal_precision_with_clear_error() {
+        let csv = format!(
+            "\
+type,client,tx,amount
+deposit,1,1,{}
+deposit,1,2,{}.5
+",
+            "1".repeat(40),
+            "9".repeat(27)
+        );
+
+        let mut strict = parse_transactions_from_reader(Cursor::new(csv.as_bytes()));
+        let mut lossy = parse_transactions_lossy_from_reader(
+            Cursor::new(csv.as_bytes()),
+            &CsvOptions::default(),
+        );
+
+        for iter in [&mut strict as &mut dyn Iterator<Item = _>, &mut lossy] {
+            let err = iter
+                .next()
+                .expect("first row is expected")
+                .expect_err("40-digit amount must be rejected");
+            assert!(matches!(
+                &err,
+                ParseTransactionsError::AmountTooPrecise { line: 2, tx, digits: 40 } if tx == "1"
+            ));
+            assert_eq!(
+                err.to_string(),
+                "Amount of transaction 1 on line 2 exceeds supported precision: \
+                 40 digits, at most 28 are supported"
+            );
+            assert!(iter.next().expect("second row is expected").is_ok());
+        }
+    }
 }
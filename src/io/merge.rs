@@ -0,0 +1,123 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use crate::domain::errors::AppError;
+
+/// Combines snapshot CSVs from runs over disjoint client shards into one CSV sorted by
+/// client. Each input is named for error messages. All inputs must share a header, and
+/// a client found in two inputs fails the merge, since it means the shards overlapped.
+/// Rows are copied as they are, so amounts keep their printed form.
+pub fn merge_snapshots<R: Read, W: Write>(
+    inputs: Vec<(String, R)>,
+    writer: W,
+) -> Result<(), AppError> {
+    let mut header: Option<(String, csv::StringRecord)> = None;
+    let mut rows: BTreeMap<u16, (String, csv::StringRecord)> = BTreeMap::new();
+
+    for (name, reader) in inputs {
+        let mut reader = csv::Reader::from_reader(reader);
+        let input_header = reader
+            .headers()
+            .map_err(|err| AppError::Parse(err.into()))?
+            .clone();
+        match &header {
+            Some((first, expected)) if *expected != input_header => {
+                return Err(AppError::TxProcessing(format!(
+                    "Snapshot {name} has columns {}, but {first} has {}",
+                    join(&input_header),
+                    join(expected)
+                )));
+            }
+            Some(_) => {}
+            None => header = Some((name.clone(), input_header)),
+        }
+
+        for record in reader.records() {
+            let record = record.map_err(|err| AppError::Parse(err.into()))?;
+            let client_field = record.get(0).unwrap_or_default();
+            let client: u16 = client_field.parse().map_err(|_| {
+                AppError::TxProcessing(format!(
+                    "Invalid client id {client_field:?} in snapshot {name}"
+                ))
+            })?;
+            if let Some((other, _)) = rows.get(&client) {
+                return Err(AppError::TxProcessing(format!(
+                    "Client {client} appears in both {other} and {name}; the shards overlap"
+                )));
+            }
+            rows.insert(client, (name.clone(), record));
+        }
+    }
+
+    let Some((_, header)) = header else {
+        return Ok(());
+    };
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record(&header)
+        .map_err(|err| AppError::Parse(err.into()))?;
+    for (_, record) in rows.values() {
+        writer
+            .write_record(record)
+            .map_err(|err| AppError::Parse(err.into()))?;
+    }
+    writer.flush().map_err(AppError::Output)
+}
+
+fn join(record: &csv::StringRecord) -> String {
+    record.iter().collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(inputs: &[(&str, &str)]) -> Result<String, AppError> {
+        let inputs = inputs
+            .iter()
+            .map(|(name, csv)| (name.to_string(), csv.as_bytes()))
+            .collect();
+        let mut written = Vec::new();
+        merge_snapshots(inputs, &mut written)?;
+        Ok(String::from_utf8(written).unwrap())
+    }
+
+    #[test]
+    fn merges_shards_sorted_by_client() {
+        let first = "\
+client,available,held,total,locked
+3,1.0000,0.0000,1.0000,false
+1,2.5000,0.5000,3.0000,false
+";
+        let second = "\
+client,available,held,total,locked
+2,0.0000,0.0000,0.0000,true
+";
+
+        assert_eq!(
+            merge(&[("a.csv", first), ("b.csv", second)]).unwrap(),
+            "\
+client,available,held,total,locked
+1,2.5000,0.5000,3.0000,false
+2,0.0000,0.0000,0.0000,true
+3,1.0000,0.0000,1.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn rejects_client_in_two_shards_and_mismatched_columns() {
+        let first = "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n";
+        let second = "client,available,held,total,locked\n1,2.0000,0.0000,2.0000,false\n";
+        let no_total = "client,available,held,locked\n2,1.0000,0.0000,false\n";
+
+        let err = merge(&[("a.csv", first), ("b.csv", second)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Client 1 appears in both a.csv and b.csv; the shards overlap"
+        );
+        assert!(merge(&[("a.csv", first), ("c.csv", no_total)]).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::Write;
+
+use crate::tx_engine::{ProcessingStats, Totals};
+
+/// Renders engine counters and gauges in the Prometheus text exposition format.
+pub fn format_prometheus_metrics(stats: &ProcessingStats, totals: &Totals) -> String {
+    let mut out = String::new();
+    write_metric(
+        &mut out,
+        "deposits_total",
+        "counter",
+        "Applied deposit transactions.",
+        stats.deposits,
+    );
+    write_metric(
+        &mut out,
+        "withdrawals_total",
+        "counter",
+        "Applied withdrawal transactions.",
+        stats.withdrawals,
+    );
+    write_metric(
+        &mut out,
+        "chargebacks_total",
+        "counter",
+        "Applied chargeback transactions.",
+        stats.chargebacks,
+    );
+    write_metric(
+        &mut out,
+        "locked_accounts",
+        "gauge",
+        "Accounts locked by a chargeback.",
+        totals.locked_accounts,
+    );
+    write_metric(
+        &mut out,
+        "held_sum",
+        "gauge",
+        "Funds held by open disputes across all clients.",
+        totals.held,
+    );
+    out
+}
+
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl std::fmt::Display,
+) {
+    // Writing into a String cannot fail.
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, TransactionType};
+    use crate::test_utils::make_tx;
+    use crate::tx_engine::TxEngine;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn formats_metrics_after_workload() {
+        let mut engine = TxEngine::new();
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 2, 2, Some(Amount::new(dec!(2.5)))),
+            make_tx(
+                TransactionType::Withdrawal,
+                1,
+                3,
+                Some(Amount::new(dec!(1.0))),
+            ),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Chargeback, 1, 1, None),
+            make_tx(TransactionType::Dispute, 2, 2, None),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let text = format_prometheus_metrics(engine.stats(), &engine.totals());
+
+        assert!(text.contains("# TYPE deposits_total counter\ndeposits_total 2\n"));
+        assert!(text.contains("\nwithdrawals_total 1\n"));
+        assert!(text.contains("\nchargebacks_total 1\n"));
+        assert!(text.contains("# TYPE locked_accounts gauge\nlocked_accounts 1\n"));
+        assert!(text.contains("\nheld_sum 2.5\n"));
+    }
+}
@@ -1,2 +1,12 @@
+pub mod annotate;
+pub mod binary;
+pub mod expect;
+pub mod generate;
 pub mod input;
+pub mod merge;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod output;
+pub mod report;
+pub mod seed;
+pub mod warnings;
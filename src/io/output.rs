@@ -1,15 +1,651 @@
-use crate::tx_engine::ClientSnapshot;
+use std::io::{self, Write};
 
-pub fn print_clients_snapshot(snapshots: &[ClientSnapshot]) {
-    println!("client,available,held,total,locked");
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::types::ClientId,
+    tx_engine::{ClientSnapshot, HeldEntry},
+};
+
+/// Number of decimal places printed for amounts.
+pub const OUTPUT_SCALE: u32 = 4;
+
+/// Largest scale `--auto-scale` picks, so one over-precise row cannot widen every amount
+/// column to 28 places.
+pub const MAX_AUTO_SCALE: u32 = 8;
+
+/// Decimal places of the minor unit `--integer-cents` counts in, i.e. cents.
+pub const MINOR_UNIT_SCALE: u32 = 2;
+
+/// Version of the snapshot output layout, including the held detail section. Bump it
+/// whenever columns or their formatting change. Version 2 added the optional omission of
+/// `total`; version 3 prints `total` as the sum of the printed `available` and `held`;
+/// version 4 added the `reason` column to the held detail; version 5 added the option of
+/// printing amounts as integer cents.
+pub const OUTPUT_FORMAT_VERSION: u32 = 5;
+
+/// How amounts are written in JSON output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonAmounts {
+    /// Quoted decimal strings with the output scale, exactly as in the CSV.
+    #[default]
+    String,
+    /// JSON numbers. Consumers usually parse these as 64-bit floats, which cannot hold
+    /// every decimal exactly and lose digits beyond about 15 significant ones.
+    Number,
+}
+
+/// Layout of the snapshot written by the command-line run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// One JSON array of all clients.
+    Json,
+    /// One JSON object per client per line, for consumers that read incrementally.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub include_total: bool,
+    pub json_amounts: JsonAmounts,
+    /// Decimal places printed for amounts.
+    pub scale: u32,
+    /// Prints amounts as whole numbers of `10^-scale` units, e.g. `350` for `3.50` at
+    /// scale 2, quoted in JSON unless `json_amounts` asks for numbers.
+    pub integer_cents: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            include_total: true,
+            json_amounts: JsonAmounts::default(),
+            scale: OUTPUT_SCALE,
+            integer_cents: false,
+        }
+    }
+}
+
+/// Cuts `amount` to `scale` decimal places, as printing with that precision does.
+fn round_for_output(amount: Decimal, scale: u32) -> Decimal {
+    amount.round_dp_with_strategy(scale, RoundingStrategy::ToZero)
+}
+
+/// Available, held and total as printed. The total is the sum of the rounded parts
+/// rather than the rounded exact total, so the printed columns always add up.
+pub fn output_columns(snapshot: &ClientSnapshot, scale: u32) -> (Decimal, Decimal, Decimal) {
+    let available = round_for_output(snapshot.available.inner(), scale);
+    let held = round_for_output(snapshot.held.inner(), scale);
+    (available, held, available + held)
+}
+
+/// `amount`, already cut to `scale` places, as a whole number of `10^-scale` units.
+fn minor_units(amount: Decimal, scale: u32) -> i128 {
+    let mut amount = amount;
+    amount.rescale(scale);
+    amount.mantissa()
+}
+
+/// Prints an amount already cut to `options.scale` places.
+fn format_amount(amount: Decimal, options: &OutputOptions) -> String {
+    if options.integer_cents {
+        minor_units(amount, options.scale).to_string()
+    } else {
+        format!("{amount:.scale$}", scale = options.scale as usize)
+    }
+}
+
+/// Formats one snapshot as a `client,available,held,total,locked` line, without the
+/// trailing newline.
+pub fn format_snapshot_line(snapshot: &ClientSnapshot) -> String {
+    snapshot_line(snapshot, &OutputOptions::default())
+}
+
+fn snapshot_line(snapshot: &ClientSnapshot, options: &OutputOptions) -> String {
+    let (available, held, total) = output_columns(snapshot, options.scale);
+    format!(
+        "{},{},{},{},{}",
+        snapshot.client_id,
+        format_amount(available, options),
+        format_amount(held, options),
+        format_amount(total, options),
+        snapshot.locked
+    )
+}
+
+/// Describes the snapshot columns produced with `options`, one `name: type` per line,
+/// after a `version` line.
+pub fn format_schema(options: &OutputOptions) -> String {
+    let amount = if options.integer_cents {
+        format!("integer(10^-{})", options.scale)
+    } else {
+        format!("decimal({})", options.scale)
+    };
+    let mut columns = vec![
+        ("client", "u16".to_string()),
+        ("available", amount.clone()),
+        ("held", amount.clone()),
+    ];
+    if options.include_total {
+        columns.push(("total", amount));
+    }
+    columns.push(("locked", "bool".to_string()));
+
+    let mut schema = format!("version: {OUTPUT_FORMAT_VERSION}\n");
+    for (name, column_type) in columns {
+        schema.push_str(&format!("{name}: {column_type}\n"));
+    }
+    schema
+}
+
+pub fn write_clients_snapshot<W: Write>(
+    writer: &mut W,
+    snapshots: &[ClientSnapshot],
+    options: &OutputOptions,
+) -> io::Result<()> {
+    if options.include_total {
+        writeln!(writer, "client,available,held,total,locked")?;
+    } else {
+        writeln!(writer, "client,available,held,locked")?;
+    }
     for snapshot in snapshots {
-        println!(
-            "{},{:.4},{:.4},{:.4},{}",
-            snapshot.client_id,
-            snapshot.available.inner(),
-            snapshot.held.inner(),
-            snapshot.total().inner(),
-            snapshot.locked
+        if options.include_total {
+            writeln!(writer, "{}", snapshot_line(snapshot, options))?;
+        } else {
+            let (available, held, _) = output_columns(snapshot, options.scale);
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                snapshot.client_id,
+                format_amount(available, options),
+                format_amount(held, options),
+                snapshot.locked
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Lines describing how two sorted snapshots differ, one `-`/`+` pair of CSV lines per
+/// client whose balances or lock state differ, or a single line for a client present on
+/// only one side. Balances compare by exact value, so the two lines of a pair can print
+/// alike when they differ only beyond `OUTPUT_SCALE` places.
+pub fn diff_snapshots(first: &[ClientSnapshot], second: &[ClientSnapshot]) -> Vec<String> {
+    let mut diff = Vec::new();
+    let (mut first, mut second) = (first.iter().peekable(), second.iter().peekable());
+    loop {
+        match (first.peek(), second.peek()) {
+            (None, None) => break,
+            (Some(left), Some(right)) if left.client_id == right.client_id => {
+                if left.available != right.available
+                    || left.held != right.held
+                    || left.locked != right.locked
+                {
+                    diff.push(format!("- {}", format_snapshot_line(left)));
+                    diff.push(format!("+ {}", format_snapshot_line(right)));
+                }
+                first.next();
+                second.next();
+            }
+            (Some(left), Some(right)) if left.client_id.0 > right.client_id.0 => {
+                diff.push(format!("+ {}", format_snapshot_line(right)));
+                second.next();
+            }
+            (None, Some(right)) => {
+                diff.push(format!("+ {}", format_snapshot_line(right)));
+                second.next();
+            }
+            (Some(left), _) => {
+                diff.push(format!("- {}", format_snapshot_line(left)));
+                first.next();
+            }
+        }
+    }
+    diff
+}
+
+/// The full CSV output, header included, with the default options.
+pub fn snapshot_to_string(snapshots: &[ClientSnapshot]) -> String {
+    let mut buffer = Vec::new();
+    write_clients_snapshot(&mut buffer, snapshots, &OutputOptions::default())
+        .expect("writing to a Vec cannot fail");
+    String::from_utf8(buffer).expect("snapshot output is ASCII")
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum JsonAmount {
+    String(String),
+    Number(f64),
+    Integer(i128),
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotJson {
+    client: u16,
+    available: JsonAmount,
+    held: JsonAmount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<JsonAmount>,
+    locked: bool,
+}
+
+/// Amounts are rounded like the CSV columns and written as strings or numbers according
+/// to `options.json_amounts`; with `options.integer_cents` they are whole minor units,
+/// quoted or as JSON integers.
+fn snapshot_json(snapshot: &ClientSnapshot, options: &OutputOptions) -> SnapshotJson {
+    let format_amount = |amount: Decimal| match options.json_amounts {
+        JsonAmounts::String => JsonAmount::String(format_amount(amount, options)),
+        JsonAmounts::Number if options.integer_cents => {
+            JsonAmount::Integer(minor_units(amount, options.scale))
+        }
+        JsonAmounts::Number => {
+            JsonAmount::Number(f64::try_from(amount).expect("every Decimal is within f64 range"))
+        }
+    };
+    let (available, held, total) = output_columns(snapshot, options.scale);
+    SnapshotJson {
+        client: snapshot.client_id.0,
+        available: format_amount(available),
+        held: format_amount(held),
+        total: options.include_total.then(|| format_amount(total)),
+        locked: snapshot.locked,
+    }
+}
+
+/// Writes the snapshots as a JSON array.
+pub fn write_clients_snapshot_json<W: Write>(
+    writer: &mut W,
+    snapshots: &[ClientSnapshot],
+    options: &OutputOptions,
+) -> io::Result<()> {
+    let rows: Vec<SnapshotJson> = snapshots
+        .iter()
+        .map(|snapshot| snapshot_json(snapshot, options))
+        .collect();
+    serde_json::to_writer(&mut *writer, &rows)?;
+    writeln!(writer)
+}
+
+/// Writes one JSON object per snapshot, each on its own line, with the same fields as
+/// `write_clients_snapshot_json`.
+pub fn write_clients_snapshot_jsonl<W: Write>(
+    writer: &mut W,
+    snapshots: &[ClientSnapshot],
+    options: &OutputOptions,
+) -> io::Result<()> {
+    for snapshot in snapshots {
+        serde_json::to_writer(&mut *writer, &snapshot_json(snapshot, options))?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+pub fn write_held_detail<W: Write>(
+    writer: &mut W,
+    entries: &[HeldEntry],
+    options: &OutputOptions,
+) -> io::Result<()> {
+    writeln!(writer)?;
+    writeln!(writer, "client,tx,held,reason")?;
+    for entry in entries {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            entry.client_id,
+            entry.tx_id,
+            format_amount(round_for_output(entry.held.inner(), options.scale), options),
+            entry
+                .reason
+                .as_deref()
+                .map_or_else(String::new, quote_field)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes open disputes as a standalone `client,tx,held_amount` CSV, in the order given,
+/// which for `TxEngine::held_breakdown` is by client then tx. Amounts are cut and
+/// printed like the snapshot's `held` column.
+pub fn write_open_disputes<W: Write>(
+    writer: &mut W,
+    entries: &[HeldEntry],
+    options: &OutputOptions,
+) -> io::Result<()> {
+    writeln!(writer, "client,tx,held_amount")?;
+    for entry in entries {
+        writeln!(
+            writer,
+            "{},{},{}",
+            entry.client_id,
+            entry.tx_id,
+            format_amount(round_for_output(entry.held.inner(), options.scale), options)
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes free text that would otherwise break the CSV line.
+fn quote_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Clients with an available, held or total value that is not exactly representable
+/// with `scale` decimal places and therefore gets rounded on output.
+pub fn clients_with_rounding_loss(snapshots: &[ClientSnapshot], scale: u32) -> Vec<ClientId> {
+    snapshots
+        .iter()
+        .filter(|snapshot| {
+            [snapshot.available, snapshot.held, snapshot.total()]
+                .iter()
+                .any(|amount| amount.inner().round_dp(scale) != amount.inner())
+        })
+        .map(|snapshot| snapshot.client_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, TransactionType, TxID};
+    use crate::io::input::Transaction;
+    use crate::tx_engine::TxEngine;
+    use rust_decimal_macros::dec;
+
+    fn snapshot(client: u16, available: Amount, held: Amount) -> ClientSnapshot {
+        ClientSnapshot {
+            client_id: ClientId(client),
+            available,
+            held,
+            parked: Amount::ZERO,
+            locked: false,
+            lock_reason: None,
+        }
+    }
+
+    #[test]
+    fn json_amounts_are_exact_strings_or_numbers() {
+        let snapshots = [snapshot(1, Amount::new(dec!(1.2345)), Amount::new(dec!(0)))];
+        let write_json = |json_amounts| {
+            let mut written = Vec::new();
+            let options = OutputOptions {
+                json_amounts,
+                ..OutputOptions::default()
+            };
+            write_clients_snapshot_json(&mut written, &snapshots, &options).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        assert_eq!(
+            write_json(JsonAmounts::String),
+            "[{\"client\":1,\"available\":\"1.2345\",\"held\":\"0.0000\",\
+             \"total\":\"1.2345\",\"locked\":false}]\n"
+        );
+        assert_eq!(
+            write_json(JsonAmounts::Number),
+            "[{\"client\":1,\"available\":1.2345,\"held\":0.0,\"total\":1.2345,\
+             \"locked\":false}]\n"
+        );
+    }
+
+    #[test]
+    fn integer_cents_json_amounts_are_quoted_unless_numbers_are_asked_for() {
+        let snapshots = [snapshot(1, Amount::new(dec!(3.5)), Amount::new(dec!(0)))];
+        let write_json = |json_amounts| {
+            let mut written = Vec::new();
+            let options = OutputOptions {
+                json_amounts,
+                scale: 2,
+                integer_cents: true,
+                ..OutputOptions::default()
+            };
+            write_clients_snapshot_json(&mut written, &snapshots, &options).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        assert_eq!(
+            write_json(JsonAmounts::String),
+            "[{\"client\":1,\"available\":\"350\",\"held\":\"0\",\"total\":\"350\",\
+             \"locked\":false}]\n"
+        );
+        assert_eq!(
+            write_json(JsonAmounts::Number),
+            "[{\"client\":1,\"available\":350,\"held\":0,\"total\":350,\"locked\":false}]\n"
+        );
+    }
+
+    #[test]
+    fn jsonl_writes_one_object_per_client() {
+        let mut locked = snapshot(2, Amount::new(dec!(0)), Amount::new(dec!(1.5)));
+        locked.locked = true;
+        let snapshots = [
+            snapshot(1, Amount::new(dec!(3.25)), Amount::new(dec!(0))),
+            locked,
+        ];
+        let mut written = Vec::new();
+        write_clients_snapshot_jsonl(&mut written, &snapshots, &OutputOptions::default()).unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(written)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                serde_json::json!({"client": 1, "available": "3.2500", "held": "0.0000",
+                                   "total": "3.2500", "locked": false}),
+                serde_json::json!({"client": 2, "available": "0.0000", "held": "1.5000",
+                                   "total": "1.5000", "locked": true}),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_lists_changed_and_one_sided_clients() {
+        let first = [
+            snapshot(1, Amount::new(dec!(1.0)), Amount::ZERO),
+            snapshot(2, Amount::new(dec!(2.0)), Amount::ZERO),
+            snapshot(3, Amount::new(dec!(3.0)), Amount::ZERO),
+        ];
+        let second = [
+            snapshot(1, Amount::new(dec!(1.00)), Amount::ZERO),
+            snapshot(3, Amount::new(dec!(2.5)), Amount::new(dec!(0.5))),
+            snapshot(4, Amount::new(dec!(4.0)), Amount::ZERO),
+        ];
+
+        assert!(diff_snapshots(&first, &first).is_empty());
+        assert_eq!(
+            diff_snapshots(&first, &second),
+            [
+                "- 2,2.0000,0.0000,2.0000,false",
+                "- 3,3.0000,0.0000,3.0000,false",
+                "+ 3,2.5000,0.5000,3.0000,false",
+                "+ 4,4.0000,0.0000,4.0000,false",
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_to_string_matches_csv_output() {
+        let mut locked = snapshot(2, Amount::new(dec!(0)), Amount::new(dec!(0)));
+        locked.locked = true;
+        let snapshots = [
+            snapshot(1, Amount::new(dec!(1.5)), Amount::new(dec!(0))),
+            locked,
+        ];
+
+        assert_eq!(
+            snapshot_to_string(&snapshots),
+            "\
+client,available,held,total,locked
+1,1.5000,0.0000,1.5000,false
+2,0.0000,0.0000,0.0000,true
+"
+        );
+    }
+
+    #[test]
+    fn formats_snapshot_line_like_csv_output() {
+        let mut snapshot = snapshot(1, Amount::new(dec!(3.5)), Amount::new(dec!(1.25)));
+        assert_eq!(
+            format_snapshot_line(&snapshot),
+            "1,3.5000,1.2500,4.7500,false"
+        );
+
+        snapshot.locked = true;
+        let mut written = Vec::new();
+        write_clients_snapshot(&mut written, &[snapshot.clone()], &OutputOptions::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            format!(
+                "client,available,held,total,locked\n{}\n",
+                format_snapshot_line(&snapshot)
+            )
+        );
+    }
+
+    #[test]
+    fn printed_total_is_the_sum_of_printed_parts() {
+        let snapshot = snapshot(1, Amount::new(dec!(0.00005)), Amount::new(dec!(0.00005)));
+        assert_eq!(
+            snapshot.total().inner().round_dp(OUTPUT_SCALE),
+            dec!(0.0001)
+        );
+
+        let line = format_snapshot_line(&snapshot);
+        assert_eq!(line, "1,0.0000,0.0000,0.0000,false");
+
+        let columns: Vec<Decimal> = line
+            .split(',')
+            .skip(1)
+            .take(3)
+            .map(|column| column.parse().unwrap())
+            .collect();
+        assert_eq!(columns[0] + columns[1], columns[2]);
+    }
+
+    #[test]
+    fn detects_values_rounded_away_on_output() {
+        let snapshots = [
+            snapshot(1, Amount::new(dec!(1.23456)), Amount::ZERO),
+            snapshot(2, Amount::new(dec!(1.2345)), Amount::new(dec!(0.50000))),
+            snapshot(3, Amount::new(dec!(2.0)), Amount::new(dec!(0.00001))),
+        ];
+
+        assert_eq!(
+            clients_with_rounding_loss(&snapshots, OUTPUT_SCALE),
+            vec![ClientId(1), ClientId(3)]
+        );
+    }
+
+    #[test]
+    fn buffered_output_of_a_large_snapshot_matches_per_line_output() {
+        let snapshots: Vec<_> = (1..=10_000u16)
+            .map(|client| {
+                let available = Amount::new(Decimal::new(i64::from(client) * 12_345, 5));
+                snapshot(client, available, Amount::new(dec!(0.5)))
+            })
+            .collect();
+        let mut expected = String::from("client,available,held,total,locked\n");
+        for snapshot in &snapshots {
+            expected.push_str(&format_snapshot_line(snapshot));
+            expected.push('\n');
+        }
+
+        let mut written = Vec::new();
+        {
+            let mut writer = io::BufWriter::new(&mut written);
+            write_clients_snapshot(&mut writer, &snapshots, &OutputOptions::default()).unwrap();
+            writer.flush().unwrap();
+        }
+
+        assert_eq!(String::from_utf8(written).unwrap(), expected);
+    }
+
+    #[test]
+    fn held_detail_cuts_integer_cents_like_the_snapshot() {
+        let entries = [
+            HeldEntry {
+                client_id: ClientId(1),
+                tx_id: TxID(1),
+                held: Amount::new(dec!(0.005)),
+                reason: None,
+            },
+            HeldEntry {
+                client_id: ClientId(1),
+                tx_id: TxID(2),
+                held: Amount::new(dec!(0.019)),
+                reason: Some("fraud".to_string()),
+            },
+        ];
+        let options = OutputOptions {
+            scale: 2,
+            integer_cents: true,
+            ..OutputOptions::default()
+        };
+
+        let mut written = Vec::new();
+        write_held_detail(&mut written, &entries, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "\nclient,tx,held,reason\n1,1,0,\n1,2,1,fraud\n"
+        );
+    }
+
+    #[test]
+    fn open_disputes_list_each_dispute_summing_to_held() {
+        let workload = [
+            (TransactionType::Deposit, 1, 1, Some(dec!(5.0))),
+            (TransactionType::Deposit, 2, 2, Some(dec!(1.25))),
+            (TransactionType::Deposit, 1, 3, Some(dec!(2.5))),
+            (TransactionType::Dispute, 2, 2, None),
+            (TransactionType::Dispute, 1, 3, None),
+            (TransactionType::Dispute, 1, 1, None),
+            (TransactionType::Resolve, 1, 1, None),
+        ];
+        let mut engine = TxEngine::new();
+        for (op_type, client, tx_id, amount) in workload {
+            engine
+                .process_transaction(&Transaction {
+                    op_type,
+                    client: ClientId(client),
+                    tx_id: TxID(tx_id),
+                    amount: amount.map(Amount::new),
+                    reason: None,
+                    row_digest: None,
+                })
+                .unwrap();
+        }
+
+        let mut written = Vec::new();
+        write_open_disputes(
+            &mut written,
+            &engine.held_breakdown(),
+            &OutputOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "client,tx,held_amount\n1,3,2.5000\n2,2,1.2500\n"
         );
+        let held: Vec<_> = engine
+            .clients_snapshot()
+            .iter()
+            .map(|snapshot| snapshot.held.inner())
+            .collect();
+        assert_eq!(held, [dec!(2.5), dec!(1.25)]);
     }
 }
@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use serde::Serialize;
+
+use crate::{
+    domain::{errors::RejectionCategory, types::Amount},
+    processing::RowDisposition,
+    tx_engine::{ProcessingStats, Totals},
+};
+
+/// Summary of a whole run, written by `--report-json`.
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    /// Rows processed before the run ended; after an interrupt, the rows left unread
+    /// are not counted.
+    pub rows_read: usize,
+    pub applied: usize,
+    pub skipped: usize,
+    pub skipped_by_category: BTreeMap<RejectionCategory, usize>,
+    pub applied_by_type: ProcessingStats,
+    /// Clients with an account at the end of the run.
+    pub clients: usize,
+    pub locked_accounts: usize,
+    pub total_available: Amount,
+    pub total_held: Amount,
+    /// `false` if the run was interrupted, reading the input failed or an output check
+    /// failed.
+    pub success: bool,
+}
+
+impl RunReport {
+    pub fn new(
+        statuses: &[RowDisposition],
+        stats: &ProcessingStats,
+        totals: &Totals,
+        clients: usize,
+        success: bool,
+    ) -> Self {
+        let mut skipped_by_category = BTreeMap::new();
+        for status in statuses {
+            if let RowDisposition::Skipped(rejection) = status {
+                *skipped_by_category.entry(rejection.category).or_default() += 1;
+            }
+        }
+        RunReport {
+            rows_read: statuses.len(),
+            applied: statuses
+                .iter()
+                .filter(|status| **status == RowDisposition::Applied)
+                .count(),
+            skipped: skipped_by_category.values().sum(),
+            skipped_by_category,
+            applied_by_type: *stats,
+            clients,
+            locked_accounts: totals.locked_accounts,
+            total_available: totals.available,
+            total_held: totals.held,
+            success,
+        }
+    }
+}
+
+/// Writes the report as a single-line JSON object.
+pub fn write_report_json<W: Write>(writer: &mut W, report: &RunReport) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, report)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::TransactionType;
+    use crate::processing::process_records;
+    use crate::test_utils::make_tx;
+    use crate::tx_engine::TxEngine;
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn report_aggregates_rows_categories_and_totals() {
+        let workload = vec![
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 2, 2, Some(Amount::new(dec!(3.0)))),
+            make_tx(
+                TransactionType::Withdrawal,
+                1,
+                3,
+                Some(Amount::new(dec!(9.0))),
+            ),
+            make_tx(TransactionType::Dispute, 2, 2, None),
+            make_tx(TransactionType::Chargeback, 2, 2, None),
+            make_tx(TransactionType::Deposit, 2, 4, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 3, 9, None),
+        ];
+        let mut engine = TxEngine::new();
+        let mut statuses = Vec::new();
+        process_records(
+            &mut engine,
+            workload.into_iter().map(Ok),
+            false,
+            &AtomicBool::new(false),
+            None,
+            Some(&mut statuses),
+        )
+        .unwrap();
+
+        let report = RunReport::new(
+            &statuses,
+            engine.stats(),
+            &engine.totals(),
+            engine.clients_snapshot().len(),
+            true,
+        );
+        let mut written = Vec::new();
+        write_report_json(&mut written, &report).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&written).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "rows_read": 7,
+                "applied": 4,
+                "skipped": 3,
+                "skipped_by_category": {
+                    "insufficient_funds": 1,
+                    "unknown_client": 1,
+                    "frozen_account": 1
+                },
+                "applied_by_type": {
+                    "deposits": 2,
+                    "withdrawals": 0,
+                    "disputes": 1,
+                    "resolves": 0,
+                    "chargebacks": 1,
+                    "corrections": 0
+                },
+                "clients": 2,
+                "locked_accounts": 1,
+                "total_available": "5.0",
+                "total_held": "0.0",
+                "success": true
+            })
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{io::Read, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::{
+    balance_store::BalanceStore,
+    domain::{
+        errors::AppError,
+        types::{Amount, ClientId},
+    },
+    tx_engine::TxEngine,
+};
+
+const SNAPSHOT_HEADER: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// Seeds `engine` with every account of a snapshot CSV named `name`, as printed by a
+/// previous run with the default output options, and returns the number of accounts
+/// seeded. Each row must have a `total` equal to `available + held`, a non-negative
+/// `held` and a client without an account yet. Open disputes are not part of a
+/// snapshot, so the seeded held funds stay held; see `TxEngine::seed_client`.
+pub fn seed_from_snapshot<R: Read, S: BalanceStore>(
+    name: &str,
+    reader: R,
+    engine: &mut TxEngine<S>,
+) -> Result<usize, AppError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let header = reader
+        .headers()
+        .map_err(|err| AppError::Parse(err.into()))?;
+    if !header.iter().eq(SNAPSHOT_HEADER) {
+        return Err(AppError::TxProcessing(format!(
+            "Snapshot {name} must have the columns {}",
+            SNAPSHOT_HEADER.join(",")
+        )));
+    }
+
+    let mut seeded = 0;
+    for record in reader.records() {
+        let record = record.map_err(|err| AppError::Parse(err.into()))?;
+        let field = |index: usize| record.get(index).unwrap_or_default();
+        let invalid = |column: &str, value: &str| {
+            AppError::TxProcessing(format!(
+                "Invalid {column} {value:?} for client {} in snapshot {name}",
+                field(0)
+            ))
+        };
+        let client: u16 = field(0).parse().map_err(|_| {
+            AppError::TxProcessing(format!(
+                "Invalid client id {:?} in snapshot {name}",
+                field(0)
+            ))
+        })?;
+        let amount = |index: usize| {
+            Decimal::from_str(field(index))
+                .map_err(|_| invalid(SNAPSHOT_HEADER[index], field(index)))
+        };
+        let (available, held, total) = (amount(1)?, amount(2)?, amount(3)?);
+        let locked: bool = field(4).parse().map_err(|_| invalid("locked", field(4)))?;
+
+        if held < Decimal::ZERO {
+            return Err(invalid("held", field(2)));
+        }
+        if available + held != total {
+            return Err(AppError::TxProcessing(format!(
+                "Total {} for client {client} in snapshot {name} is not available plus held",
+                field(3)
+            )));
+        }
+        if !engine.seed_client(
+            ClientId(client),
+            Amount::new(available),
+            Amount::new(held),
+            locked,
+        ) {
+            return Err(AppError::TxProcessing(format!(
+                "Client {client} in snapshot {name} already has an account"
+            )));
+        }
+        seeded += 1;
+    }
+    Ok(seeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::parse_transactions_from_reader;
+    use crate::io::output::snapshot_to_string;
+    use crate::processing::process_all;
+
+    #[test]
+    fn seeding_from_a_snapshot_reproduces_it() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,1,5.25
+withdrawal,1,2,1.5
+deposit,2,3,3.0
+deposit,2,4,2.0
+dispute,2,4,
+deposit,3,5,1.0
+dispute,3,5,
+chargeback,3,5,
+";
+        let mut engine = TxEngine::new();
+        process_all(&mut engine, parse_transactions_from_reader(csv.as_bytes())).unwrap();
+        let snapshot = snapshot_to_string(&engine.clients_snapshot());
+
+        let mut seeded = TxEngine::new();
+        let count = seed_from_snapshot("out.csv", snapshot.as_bytes(), &mut seeded).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(snapshot_to_string(&seeded.clients_snapshot()), snapshot);
+        assert_eq!(seeded.state_digest(), engine.state_digest());
+        assert!(seeded.check_invariants().is_empty());
+
+        let err = seed_from_snapshot("out.csv", snapshot.as_bytes(), &mut seeded).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Client 1 in snapshot out.csv already has an account"
+        );
+        let bad_total = "client,available,held,total,locked\n4,1.0,0.5,2.0,false\n";
+        assert!(seed_from_snapshot("bad.csv", bad_total.as_bytes(), &mut seeded).is_err());
+    }
+}
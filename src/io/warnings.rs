@@ -0,0 +1,35 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+    domain::errors::{Rejection, RejectionCategory},
+    io::input::Transaction,
+};
+
+#[derive(Debug, Serialize)]
+struct SkippedRow<'a> {
+    row: usize,
+    client: u16,
+    tx: u32,
+    reason: &'a str,
+    category: RejectionCategory,
+}
+
+/// Writes one skipped transaction as a single-line JSON object.
+pub fn write_warning_json<W: Write + ?Sized>(
+    writer: &mut W,
+    row: usize,
+    tx: &Transaction,
+    rejection: &Rejection,
+) -> io::Result<()> {
+    let skipped = SkippedRow {
+        row,
+        client: tx.client.0,
+        tx: tx.tx_id.0,
+        reason: &rejection.message,
+        category: rejection.category,
+    };
+    serde_json::to_writer(&mut *writer, &skipped)?;
+    writeln!(writer)
+}
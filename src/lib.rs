@@ -0,0 +1,16 @@
+//! Transaction engine behind the `tx-engine-example` binary: CSV and binary input,
+//! the engine itself, and snapshot output. With the `test-utils` feature, `test_utils`
+//! has helpers for tests written around the engine.
+
+pub mod balance_store;
+pub mod cli;
+pub mod config;
+pub mod domain;
+pub mod io;
+pub mod processing;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod tx_engine;
+pub mod tx_engine_handle;
@@ -1,16 +1,49 @@
-pub mod domain;
-pub mod io;
-pub mod tx_engine;
+#[cfg(feature = "server")]
+use tx_engine_example::server;
+use tx_engine_example::{cli, config, domain, io, processing, tx_engine};
 
+use cli::parse_args;
+use config::Config;
 use domain::errors::AppError;
-use io::input::{parse_transactions, ParseTransactionsError};
-use io::output::print_clients_snapshot;
+use io::annotate::write_annotated_csv;
+use io::binary::{parse_binary_transactions, write_binary_transaction};
+use io::expect::expected_mismatches;
+use io::generate::write_generated_csv;
+use io::input::{
+    parse_transactions_lossy, parse_transactions_with_options, IncreasingTxIds, InputFormat,
+    ParseTransactionsError, StopAtReadFailure, TrackAmountScale, Transaction,
+};
+use io::merge::merge_snapshots;
+use io::output::{
+    clients_with_rounding_loss, diff_snapshots, format_schema, write_clients_snapshot,
+    write_clients_snapshot_json, write_clients_snapshot_jsonl, write_held_detail,
+    write_open_disputes, OutputFormat, OutputOptions, MAX_AUTO_SCALE,
+};
+use io::report::{write_report_json, RunReport};
+use io::seed::seed_from_snapshot;
+use processing::{
+    process_batches, process_records, process_records_two_pass, BatchStats, ProcessingOutcome,
+    RowDisposition,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::env;
-use tx_engine::TxEngine;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tx_engine::{ClientSnapshot, TxEngine};
+
+type TransactionStream = Box<dyn Iterator<Item = Result<Transaction, ParseTransactionsError>>>;
 
 fn main() {
     env_logger::init();
     if let Err(err) = run() {
+        // The reader of stdout went away, e.g. `| head`: there is no one left to tell.
+        if err.is_broken_pipe() {
+            std::process::exit(0);
+        }
         eprintln!("error: {err}");
         std::process::exit(1);
     }
@@ -18,30 +51,392 @@ fn main() {
 
 fn run() -> Result<(), AppError> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
+    let options = parse_args(&args)?;
+    if let Some(generate) = &options.generate {
+        let stdout = std::io::stdout();
+        return write_generated_csv(BufWriter::new(stdout.lock()), generate)
+            .map_err(AppError::Output);
+    }
+    if let Some(paths) = &options.merge_output {
+        let inputs = paths
+            .iter()
+            .map(|path| {
+                let file = File::open(path).map_err(|err| AppError::Parse(err.into()))?;
+                Ok((path.clone(), BufReader::new(file)))
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+        let stdout = std::io::stdout();
+        return merge_snapshots(inputs, BufWriter::new(stdout.lock()));
+    }
+    let mut config = match &options.config_file {
+        Some(config_path) => Config::load(config_path)?,
+        None => Config::default(),
+    };
+    config.apply_cli(&options);
+    config.validate()?;
+
+    if options.print_schema {
+        let schema = format_schema(&config.output_options());
+        return std::io::stdout()
+            .write_all(schema.as_bytes())
+            .map_err(AppError::Output);
+    }
+
+    if let Some(addr) = &options.http {
+        return serve_http(addr, &config, options.queue_size);
+    }
+
+    if let Some(compare_path) = &options.compare_path {
+        return compare_feeds(&options.input_path, compare_path, &config);
+    }
+
+    if let Some(paths) = &options.batches {
+        return run_batches(paths, &config);
+    }
+
+    if config.output.annotate.is_some() && config.input.format != InputFormat::Csv {
         return Err(AppError::TxProcessing(
-            "Usage: cargo run -- <transactions.csv>".to_string(),
+            "--annotate requires CSV input".to_string(),
         ));
     }
-    let input_path = &args[1];
 
-    let mut tx_engine = TxEngine::new();
+    let mut records = open_transactions(&options.input_path, &config)?;
+    let max_input_scale = Rc::new(Cell::new(0));
+    if config.output.auto_scale {
+        records = Box::new(TrackAmountScale::new(records, Rc::clone(&max_input_scale)));
+    }
+
+    if let Some(encode_path) = &options.encode_bin {
+        return encode_binary(records, encode_path);
+    }
+    let read_failure = Rc::new(RefCell::new(None));
+    if config.output.partial_on_read_error {
+        records = Box::new(StopAtReadFailure::new(records, Rc::clone(&read_failure)));
+    }
+
+    let mut tx_engine = build_engine(&config)?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst)).map_err(|err| {
+        AppError::TxProcessing(format!("Cannot install interrupt handler: {err}"))
+    })?;
+
+    let mut warnings = match &config.output.warnings_json {
+        Some(path) => Some(BufWriter::new(
+            File::create(path).map_err(AppError::Output)?,
+        )),
+        None => None,
+    };
+    let mut row_statuses =
+        (config.output.annotate.is_some() || config.output.report_json.is_some()).then(Vec::new);
+    let process = if config.input.two_pass {
+        process_records_two_pass
+    } else {
+        process_records
+    };
+    let outcome = process(
+        &mut tx_engine,
+        records,
+        config.input.strict,
+        &stop,
+        warnings.as_mut().map(|writer| writer as &mut dyn Write),
+        row_statuses.as_mut(),
+    );
+    if let Some(writer) = &mut warnings {
+        writer.flush().map_err(AppError::Output)?;
+    }
+    // Also after a parse error, so the copy shows the row the run stopped at.
+    if let (Some(annotate_path), Some(statuses)) = (&config.output.annotate, &row_statuses) {
+        annotate_input(&options.input_path, annotate_path, &config, statuses)?;
+    }
+    let outcome = outcome?;
+
+    if options.print_run_config {
+        eprint!("{}", config.to_toml());
+    }
 
-    for tx_result in parse_transactions(input_path)? {
-        let tx = tx_result.map_err(ParseTransactionsError::from)?;
-        if let Err(err) = tx_engine.process_transaction(&tx) {
-            match err {
-                AppError::TxProcessingNonCritical(_) => {
-                    log::warn!("{err}");
-                    continue;
-                }
-                _ => return Err(err),
-            }
+    let mut snapshots = tx_engine.clients_snapshot();
+    if config.output.hide_system_account {
+        if let Some(system_account) = config.engine.system_account {
+            snapshots.retain(|snapshot| snapshot.client_id.0 != system_account);
+        }
+    }
+    let mut output_options = config.output_options();
+    if config.output.auto_scale {
+        output_options.scale = max_input_scale.get().min(MAX_AUTO_SCALE);
+    }
+    if output_options.integer_cents {
+        let inexact = clients_with_rounding_loss(&snapshots, output_options.scale);
+        if !inexact.is_empty() {
+            return Err(AppError::InexactMinorUnits(inexact));
         }
     }
+    write_snapshot(&tx_engine, &snapshots, &config, &output_options).map_err(AppError::Output)?;
+    if let Some(disputes_path) = &config.output.disputes_out {
+        write_disputes(&tx_engine, disputes_path, &output_options).map_err(AppError::Output)?;
+    }
+
+    if let Some(metrics_path) = &config.output.metrics_file {
+        write_metrics(&tx_engine, metrics_path)?;
+    }
+
+    let checked = check_output(&tx_engine, &snapshots, &config, output_options.scale);
+    if let (Some(report_path), Some(statuses)) = (&config.output.report_json, &row_statuses) {
+        let report = RunReport::new(
+            statuses,
+            tx_engine.stats(),
+            &tx_engine.totals(),
+            snapshots.len(),
+            outcome == ProcessingOutcome::Completed
+                && checked.is_ok()
+                && read_failure.borrow().is_none(),
+        );
+        write_report(report_path, &report).map_err(AppError::Output)?;
+    }
+    checked?;
+
+    if let Some(err) = read_failure.take() {
+        log::warn!("Reading the input failed, printed partial snapshot");
+        return Err(AppError::Parse(err));
+    }
+
+    if outcome == ProcessingOutcome::Interrupted {
+        log::warn!("Interrupted, printed partial snapshot");
+        std::process::exit(130);
+    }
+
+    Ok(())
+}
+
+/// Runs each feed on a fresh engine and prints how the final snapshots differ. Fails
+/// if they do.
+fn compare_feeds(first: &str, second: &str, config: &Config) -> Result<(), AppError> {
+    let diff = diff_snapshots(
+        &final_snapshots(first, config)?,
+        &final_snapshots(second, config)?,
+    );
+    let mut stdout = std::io::stdout().lock();
+    if diff.is_empty() {
+        return writeln!(stdout, "Final states are identical").map_err(AppError::Output);
+    }
+    for line in &diff {
+        writeln!(stdout, "{line}").map_err(AppError::Output)?;
+    }
+    Err(AppError::TxProcessing(format!(
+        "Final states of {first} and {second} differ"
+    )))
+}
+
+/// Runs the feeds in order on one engine, prints the final snapshot, then the row counts
+/// of each feed to stderr as `batch,applied,skipped,parse_errors` CSV. Rows that fail to
+/// parse are counted and skipped rather than ending the run.
+fn run_batches(paths: &[String], config: &Config) -> Result<(), AppError> {
+    let batches = paths
+        .iter()
+        .map(|path| Ok((path.clone(), open_transactions(path, config)?)))
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let mut tx_engine = build_engine(config)?;
+    let stats = process_batches(&mut tx_engine, batches)?;
 
     let snapshots = tx_engine.clients_snapshot();
-    print_clients_snapshot(&snapshots);
+    let output_options = config.output_options();
+    write_snapshot(&tx_engine, &snapshots, config, &output_options).map_err(AppError::Output)?;
+    write_batch_stats(std::io::stderr().lock(), &stats).map_err(AppError::Output)
+}
+
+fn write_batch_stats<W: Write>(
+    writer: W,
+    stats: &BTreeMap<String, BatchStats>,
+) -> std::io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["batch", "applied", "skipped", "parse_errors"])?;
+    for (batch_id, batch) in stats {
+        writer.write_record([
+            batch_id.clone(),
+            batch.applied.to_string(),
+            batch.skipped.to_string(),
+            batch.parse_errors.to_string(),
+        ])?;
+    }
+    writer.flush()
+}
+
+fn final_snapshots(input_path: &str, config: &Config) -> Result<Vec<ClientSnapshot>, AppError> {
+    let mut tx_engine = build_engine(config)?;
+    let process = if config.input.two_pass {
+        process_records_two_pass
+    } else {
+        process_records
+    };
+    process(
+        &mut tx_engine,
+        open_transactions(input_path, config)?,
+        config.input.strict,
+        &AtomicBool::new(false),
+        None,
+        None,
+    )?;
+    Ok(tx_engine.clients_snapshot())
+}
+
+/// Runs the checks enabled by `--error-on-rounding-loss`, `--self-check` and `--expect`,
+/// the first against amounts printed with `scale` places.
+fn check_output(
+    tx_engine: &TxEngine,
+    snapshots: &[ClientSnapshot],
+    config: &Config,
+    scale: u32,
+) -> Result<(), AppError> {
+    if config.output.error_on_rounding_loss {
+        let affected = clients_with_rounding_loss(snapshots, scale);
+        if !affected.is_empty() {
+            return Err(AppError::RoundingLoss(affected));
+        }
+    }
+
+    if config.output.self_check {
+        let violations = tx_engine.check_invariants();
+        if !violations.is_empty() {
+            return Err(AppError::SelfCheck(violations));
+        }
+    }
 
+    if let Some(expected_path) = &config.output.expect {
+        let file = File::open(expected_path).map_err(|err| AppError::Parse(err.into()))?;
+        let mismatches = expected_mismatches(expected_path, BufReader::new(file), snapshots)?;
+        if !mismatches.is_empty() {
+            return Err(AppError::TxProcessing(format!(
+                "Snapshot does not match {expected_path}:\n  {}",
+                mismatches.join("\n  ")
+            )));
+        }
+    }
     Ok(())
 }
+
+/// Writes the run report to `path`, or to stderr if `path` is `-`.
+fn write_report(path: &str, report: &RunReport) -> std::io::Result<()> {
+    if path == "-" {
+        return write_report_json(&mut std::io::stderr().lock(), report);
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_report_json(&mut writer, report)?;
+    writer.flush()
+}
+
+/// Writes the snapshot, and the held detail if enabled, to `--out` or stdout.
+fn write_snapshot(
+    tx_engine: &TxEngine,
+    snapshots: &[ClientSnapshot],
+    config: &Config,
+    options: &OutputOptions,
+) -> std::io::Result<()> {
+    let mut writer: Box<dyn Write> = match &config.output.out_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+    match config.output.format {
+        OutputFormat::Csv => write_clients_snapshot(&mut writer, snapshots, options)?,
+        OutputFormat::Json => write_clients_snapshot_json(&mut writer, snapshots, options)?,
+        OutputFormat::Jsonl => write_clients_snapshot_jsonl(&mut writer, snapshots, options)?,
+    }
+    if config.output.held_detail {
+        write_held_detail(&mut writer, &tx_engine.held_breakdown(), options)?;
+    }
+    writer.flush()
+}
+
+fn write_disputes(
+    tx_engine: &TxEngine,
+    path: &str,
+    options: &OutputOptions,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_open_disputes(&mut writer, &tx_engine.held_breakdown(), options)?;
+    writer.flush()
+}
+
+/// Builds the engine for `config`, with the accounts of `--seed-snapshot` opened.
+fn build_engine(config: &Config) -> Result<TxEngine, AppError> {
+    let mut tx_engine = config.engine_builder().build();
+    if let Some(path) = &config.input.seed_snapshot {
+        let file = File::open(path).map_err(|err| AppError::Parse(err.into()))?;
+        let seeded = seed_from_snapshot(path, BufReader::new(file), &mut tx_engine)?;
+        log::info!("Seeded {seeded} accounts from {path}");
+    }
+    Ok(tx_engine)
+}
+
+fn open_transactions(input_path: &str, config: &Config) -> Result<TransactionStream, AppError> {
+    let records: TransactionStream = match config.input.format {
+        InputFormat::Bin => Box::new(parse_binary_transactions(input_path)?),
+        InputFormat::Csv if config.input.lossy_utf8 => {
+            Box::new(parse_transactions_lossy(input_path, &config.csv_options())?)
+        }
+        InputFormat::Csv => Box::new(parse_transactions_with_options(
+            input_path,
+            &config.csv_options(),
+        )?),
+    };
+
+    if config.input.require_increasing_tx {
+        return Ok(Box::new(IncreasingTxIds::new(records)));
+    }
+    Ok(records)
+}
+
+fn annotate_input(
+    input_path: &str,
+    output_path: &str,
+    config: &Config,
+    statuses: &[RowDisposition],
+) -> Result<(), AppError> {
+    let input = File::open(input_path).map_err(|err| AppError::Parse(err.into()))?;
+    let output = BufWriter::new(File::create(output_path).map_err(AppError::Output)?);
+    write_annotated_csv(
+        BufReader::new(input),
+        output,
+        &config.csv_options(),
+        statuses,
+    )
+    .map_err(AppError::Output)
+}
+
+fn encode_binary(records: TransactionStream, output_path: &str) -> Result<(), AppError> {
+    let file = std::fs::File::create(output_path).map_err(AppError::Output)?;
+    let mut writer = std::io::BufWriter::new(file);
+    for tx_result in records {
+        write_binary_transaction(&mut writer, &tx_result?).map_err(AppError::Output)?;
+    }
+    std::io::Write::flush(&mut writer).map_err(AppError::Output)
+}
+
+#[cfg(feature = "server")]
+fn serve_http(addr: &str, config: &Config, queue_size: Option<usize>) -> Result<(), AppError> {
+    server::serve(
+        addr,
+        config,
+        queue_size.unwrap_or(server::DEFAULT_QUEUE_SIZE),
+    )
+}
+
+#[cfg(not(feature = "server"))]
+fn serve_http(_addr: &str, _config: &Config, _queue_size: Option<usize>) -> Result<(), AppError> {
+    Err(AppError::TxProcessing(
+        "--http requires building with the `server` feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "metrics")]
+fn write_metrics(tx_engine: &TxEngine, path: &str) -> Result<(), AppError> {
+    let metrics = io::metrics::format_prometheus_metrics(tx_engine.stats(), &tx_engine.totals());
+    std::fs::write(path, metrics).map_err(AppError::Output)
+}
+
+#[cfg(not(feature = "metrics"))]
+fn write_metrics(_tx_engine: &TxEngine, _path: &str) -> Result<(), AppError> {
+    Err(AppError::TxProcessing(
+        "--metrics-file requires building with the `metrics` feature".to_string(),
+    ))
+}
@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    domain::errors::{AppError, ProcessError, Rejection},
+    domain::types::TransactionType,
+    io::{
+        input::{ParseTransactionsError, Transaction},
+        warnings::write_warning_json,
+    },
+    tx_engine::TxEngine,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingOutcome {
+    Completed,
+    Interrupted,
+}
+
+/// Final outcome of one input row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowDisposition {
+    Applied,
+    Skipped(Rejection),
+    ParseError(String),
+}
+
+/// Processes every row and reports each one's disposition, indexed by row. Unlike
+/// `process_records`, rows that fail to parse are recorded and processing goes on; only
+/// critical engine errors stop it.
+pub fn process_all<I>(tx_engine: &mut TxEngine, records: I) -> Result<Vec<RowDisposition>, AppError>
+where
+    I: IntoIterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    let mut dispositions = Vec::new();
+    process_each(tx_engine, records, |disposition| {
+        dispositions.push(disposition)
+    })?;
+    Ok(dispositions)
+}
+
+/// Row counts of one input batch, as reported by `process_batches`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub applied: usize,
+    pub skipped: usize,
+    pub parse_errors: usize,
+}
+
+impl BatchStats {
+    fn count(&mut self, disposition: &RowDisposition) {
+        match disposition {
+            RowDisposition::Applied => self.applied += 1,
+            RowDisposition::Skipped(_) => self.skipped += 1,
+            RowDisposition::ParseError(_) => self.parse_errors += 1,
+        }
+    }
+}
+
+/// Processes each batch in turn on the same engine like `process_all`, counting every
+/// row under the id of the batch it came from as it is processed, so merged feeds can
+/// be reported per source. Batches sharing an id are counted together.
+pub fn process_batches<I, B>(
+    tx_engine: &mut TxEngine,
+    batches: I,
+) -> Result<BTreeMap<String, BatchStats>, AppError>
+where
+    I: IntoIterator<Item = (String, B)>,
+    B: IntoIterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    let mut stats: BTreeMap<String, BatchStats> = BTreeMap::new();
+    for (batch_id, records) in batches {
+        let batch = stats.entry(batch_id).or_default();
+        process_each(tx_engine, records, |disposition| batch.count(&disposition))?;
+    }
+    Ok(stats)
+}
+
+/// Applies every row and hands its disposition to `on_row`; see `process_all`.
+fn process_each<I>(
+    tx_engine: &mut TxEngine,
+    records: I,
+    mut on_row: impl FnMut(RowDisposition),
+) -> Result<(), AppError>
+where
+    I: IntoIterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    for tx_result in records {
+        let disposition = match tx_result {
+            Ok(tx) => match tx_engine.process_transaction(&tx) {
+                Ok(()) => RowDisposition::Applied,
+                Err(ProcessError::Rejected(rejection)) => RowDisposition::Skipped(rejection),
+                Err(err @ ProcessError::Critical(_)) => return Err(err.into()),
+            },
+            Err(err) => RowDisposition::ParseError(err.to_string()),
+        };
+        on_row(disposition);
+    }
+    Ok(())
+}
+
+/// Feeds records into the engine, skipping non-critical failures unless `strict` is set,
+/// in which case the first one becomes fatal. `stop` is checked before every record so
+/// an interrupt leaves the engine with a consistent partial state. Skipped rows are also
+/// written to `warnings` as JSON lines when given. `row_statuses` receives the
+/// disposition of each processed row; a parse error, which ends the run, is recorded as
+/// the `ParseError` of its row before it is returned.
+pub fn process_records<I>(
+    tx_engine: &mut TxEngine,
+    records: I,
+    strict: bool,
+    stop: &AtomicBool,
+    warnings: Option<&mut dyn Write>,
+    row_statuses: Option<&mut Vec<RowDisposition>>,
+) -> Result<ProcessingOutcome, AppError>
+where
+    I: IntoIterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    let rows = records
+        .into_iter()
+        .enumerate()
+        .map(|(row_index, tx_result)| tx_result.map(|tx| (row_index, tx)));
+    process_rows(tx_engine, rows, strict, stop, warnings, row_statuses)
+}
+
+/// Like `process_records`, but reads the whole feed first and applies it in two passes:
+/// every deposit, withdrawal and correction, then every dispute, resolve and chargeback,
+/// each pass in input order. A dispute listed before the deposit it references is then
+/// still applied.
+///
+/// Unlike streaming, the whole input is held in memory, a parse error anywhere fails the
+/// run before anything is applied, and a dispute may see a balance that already includes
+/// deposits and withdrawals listed after it. Row numbers in warnings and `row_statuses`
+/// still refer to input order.
+pub fn process_records_two_pass<I>(
+    tx_engine: &mut TxEngine,
+    records: I,
+    strict: bool,
+    stop: &AtomicBool,
+    warnings: Option<&mut dyn Write>,
+    row_statuses: Option<&mut Vec<RowDisposition>>,
+) -> Result<ProcessingOutcome, AppError>
+where
+    I: IntoIterator<Item = Result<Transaction, ParseTransactionsError>>,
+{
+    let rows = records
+        .into_iter()
+        .enumerate()
+        .map(|(row_index, tx_result)| tx_result.map(|tx| (row_index, tx)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (creating, lifecycle): (Vec<_>, Vec<_>) = rows.into_iter().partition(|(_, tx)| {
+        matches!(
+            tx.op_type,
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Correction
+        )
+    });
+    let ordered = creating.into_iter().chain(lifecycle).map(Ok);
+    process_rows(tx_engine, ordered, strict, stop, warnings, row_statuses)
+}
+
+fn process_rows<I>(
+    tx_engine: &mut TxEngine,
+    rows: I,
+    strict: bool,
+    stop: &AtomicBool,
+    mut warnings: Option<&mut dyn Write>,
+    row_statuses: Option<&mut Vec<RowDisposition>>,
+) -> Result<ProcessingOutcome, AppError>
+where
+    I: Iterator<Item = Result<(usize, Transaction), ParseTransactionsError>>,
+{
+    let mut statuses = Vec::new();
+    let mut outcome = ProcessingOutcome::Completed;
+    let mut parse_failure = None;
+    for row in rows {
+        if stop.load(Ordering::SeqCst) {
+            outcome = ProcessingOutcome::Interrupted;
+            break;
+        }
+
+        let (row_index, tx) = match row {
+            Ok(row) => row,
+            Err(err) => {
+                // Only streaming hands parse errors on, so every row before this one has
+                // a status and this one's index is the count so far.
+                if row_statuses.is_some() {
+                    let message = err.to_string();
+                    statuses.push((statuses.len(), RowDisposition::ParseError(message)));
+                }
+                parse_failure = Some(err);
+                break;
+            }
+        };
+        if let Err(err) = tx_engine.process_transaction(&tx) {
+            match err {
+                ProcessError::Rejected(message) if strict => {
+                    return Err(AppError::TxProcessing(format!(
+                        "Row {} ({},{},{}): {}",
+                        row_index + 1,
+                        tx.op_type,
+                        tx.client,
+                        tx.tx_id,
+                        message
+                    )));
+                }
+                ProcessError::Rejected(rejection) => {
+                    log::warn!("{rejection}, skipping");
+                    if let Some(writer) = warnings.as_deref_mut() {
+                        write_warning_json(writer, row_index + 1, &tx, &rejection)
+                            .map_err(AppError::Output)?;
+                    }
+                    if row_statuses.is_some() {
+                        statuses.push((row_index, RowDisposition::Skipped(rejection)));
+                    }
+                    continue;
+                }
+                ProcessError::Critical(_) => return Err(err.into()),
+            }
+        }
+        if row_statuses.is_some() {
+            statuses.push((row_index, RowDisposition::Applied));
+        }
+    }
+
+    if let Some(row_statuses) = row_statuses {
+        // Back to input order. After an interrupt only the rows up to the first one left
+        // unprocessed are reported.
+        statuses.sort_by_key(|(row_index, _)| *row_index);
+        row_statuses.extend(
+            statuses
+                .into_iter()
+                .enumerate()
+                .take_while(|(position, (row_index, _))| position == row_index)
+                .map(|(_, (_, status))| status),
+        );
+    }
+    if let Some(err) = parse_failure {
+        return Err(err.into());
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::errors::RejectionCategory;
+    use crate::domain::types::{Amount, ClientId, TransactionType};
+    use crate::io::input::parse_transactions_from_reader;
+    use crate::test_utils::make_tx;
+    use rust_decimal_macros::dec;
+
+    fn deposit(client: u16, tx_id: u32) -> Result<Transaction, ParseTransactionsError> {
+        Ok(make_tx(
+            TransactionType::Deposit,
+            client,
+            tx_id,
+            Some(Amount::new(dec!(1.0))),
+        ))
+    }
+
+    #[test]
+    fn processes_all_records_when_not_stopped() {
+        let mut engine = TxEngine::new();
+        let stop = AtomicBool::new(false);
+
+        let outcome = process_records(
+            &mut engine,
+            vec![deposit(1, 1), deposit(2, 2)],
+            false,
+            &stop,
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(outcome, ProcessingOutcome::Completed);
+        assert_eq!(engine.clients_snapshot().len(), 2);
+    }
+
+    #[test]
+    fn stop_flag_is_honored_before_the_next_record() {
+        let mut engine = TxEngine::new();
+        let stop = AtomicBool::new(false);
+        let records = (1..=3).map(|tx_id| {
+            if tx_id == 2 {
+                stop.store(true, Ordering::SeqCst);
+            }
+            deposit(tx_id as u16, tx_id)
+        });
+
+        let outcome = process_records(&mut engine, records, false, &stop, None, None).unwrap();
+
+        assert_eq!(outcome, ProcessingOutcome::Interrupted);
+        let snapshots = engine.clients_snapshot();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].client_id, ClientId(1));
+    }
+
+    #[test]
+    fn strict_mode_turns_first_skip_into_fatal_error_with_row_index() {
+        let mut engine = TxEngine::new();
+        let stop = AtomicBool::new(false);
+
+        let result = process_records(
+            &mut engine,
+            vec![deposit(1, 1), deposit(2, 1), deposit(3, 3)],
+            true,
+            &stop,
+            None,
+            None,
+        );
+
+        match result {
+            Err(AppError::TxProcessing(message)) => assert!(message.starts_with("Row 2 ")),
+            other => panic!("expected fatal error, got {other:?}"),
+        }
+        assert_eq!(engine.clients_snapshot().len(), 1);
+    }
+
+    #[test]
+    fn two_pass_applies_dispute_listed_before_its_deposit() {
+        let dispute = || {
+            Ok(Transaction {
+                op_type: TransactionType::Dispute,
+                amount: None,
+                ..deposit(1, 1).unwrap()
+            })
+        };
+        let stop = AtomicBool::new(false);
+
+        let mut streaming = TxEngine::new();
+        let mut streaming_statuses = Vec::new();
+        process_records(
+            &mut streaming,
+            vec![dispute(), deposit(1, 1)],
+            false,
+            &stop,
+            None,
+            Some(&mut streaming_statuses),
+        )
+        .unwrap();
+
+        let mut two_pass = TxEngine::new();
+        let mut two_pass_statuses = Vec::new();
+        process_records_two_pass(
+            &mut two_pass,
+            vec![dispute(), deposit(1, 1)],
+            false,
+            &stop,
+            None,
+            Some(&mut two_pass_statuses),
+        )
+        .unwrap();
+
+        assert_eq!(streaming.clients_snapshot()[0].held, Amount::ZERO);
+        assert!(matches!(streaming_statuses[0], RowDisposition::Skipped(_)));
+        assert_eq!(two_pass.clients_snapshot()[0].held, Amount::new(dec!(1.0)));
+        assert_eq!(two_pass.clients_snapshot()[0].available, Amount::ZERO);
+        assert_eq!(
+            two_pass_statuses,
+            vec![RowDisposition::Applied, RowDisposition::Applied]
+        );
+    }
+
+    #[test]
+    fn process_all_reports_disposition_of_every_row() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,5.0
+deposit,abc,3,1.0
+withdrawal,1,4,1.0
+";
+        let mut engine = TxEngine::new();
+
+        let dispositions =
+            process_all(&mut engine, parse_transactions_from_reader(csv.as_bytes())).unwrap();
+
+        assert_eq!(dispositions.len(), 4);
+        assert_eq!(dispositions[0], RowDisposition::Applied);
+        match &dispositions[1] {
+            RowDisposition::Skipped(rejection) => {
+                assert_eq!(rejection.category, RejectionCategory::InsufficientFunds)
+            }
+            other => panic!("expected skipped row, got {other:?}"),
+        }
+        assert!(matches!(dispositions[2], RowDisposition::ParseError(_)));
+        assert_eq!(dispositions[3], RowDisposition::Applied);
+        assert_eq!(
+            engine.clients_snapshot()[0].available,
+            Amount::new(dec!(1.0))
+        );
+    }
+
+    #[test]
+    fn process_batches_counts_each_batch_separately() {
+        let first = "\
+type,client,tx,amount
+deposit,1,1,2.0
+withdrawal,1,2,5.0
+";
+        let second = "\
+type,client,tx,amount
+deposit,2,3,1.0
+deposit,2,4,1.0
+deposit,abc,5,1.0
+";
+        let mut engine = TxEngine::new();
+
+        let stats = process_batches(
+            &mut engine,
+            [
+                (
+                    "bank-a".to_string(),
+                    parse_transactions_from_reader(first.as_bytes()),
+                ),
+                (
+                    "bank-b".to_string(),
+                    parse_transactions_from_reader(second.as_bytes()),
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            stats["bank-a"],
+            BatchStats {
+                applied: 1,
+                skipped: 1,
+                parse_errors: 0
+            }
+        );
+        assert_eq!(
+            stats["bank-b"],
+            BatchStats {
+                applied: 2,
+                skipped: 0,
+                parse_errors: 1
+            }
+        );
+        assert_eq!(engine.clients_snapshot().len(), 2);
+    }
+}
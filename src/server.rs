@@ -0,0 +1,361 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    config::Config,
+    domain::errors::AppError,
+    io::{
+        input::{parse_transactions_from_source, InMemorySource},
+        output::{clients_with_rounding_loss, write_clients_snapshot_json},
+    },
+    processing::process_records,
+};
+
+/// Requests read ahead of the engine worker when `--queue-size` is not given. Each one
+/// holds a whole request body of at most `MAX_BODY_SIZE`, so this bounds the server's
+/// memory to a few bodies.
+pub const DEFAULT_QUEUE_SIZE: usize = 16;
+
+/// Largest request body accepted; a longer `Content-Length` is answered with 413
+/// before any of the body is read.
+pub const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Most bytes read for the request line and headers together.
+const MAX_HEAD_SIZE: u64 = 16 * 1024;
+
+/// How long a read from or write to a client may block before the request is dropped.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest the accepting thread spends reading one request, so a client sending a
+/// byte at a time cannot hold it past `IO_TIMEOUT` on every read.
+pub const REQUEST_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Serves `POST` requests on `addr`. Each request body is a CSV feed run through a
+/// fresh engine built from `config`; the response is the JSON snapshot. Requests are
+/// read on the accepting thread and processed one at a time, in arrival order, by a
+/// single worker. Up to `queue_size` read requests wait for the worker; once the queue
+/// is full, the server stops accepting until the worker catches up. Reading a request
+/// is bounded in size and time, so one client can hold up the queue for at most
+/// `REQUEST_DEADLINE`; a client that stalls for `IO_TIMEOUT` is dropped.
+pub fn serve(addr: &str, config: &Config, queue_size: usize) -> Result<(), AppError> {
+    let listener = TcpListener::bind(addr).map_err(AppError::Output)?;
+    log::info!("Listening on {addr} with a queue of {queue_size} requests");
+    let requests = listener.incoming().filter_map(|stream| {
+        let read = stream.and_then(|stream| {
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+            Ok((read_request_within(&stream, REQUEST_DEADLINE)?, stream))
+        });
+        read.map_err(|err| log::warn!("Request failed: {err}")).ok()
+    });
+    run_queued(requests, queue_size, |(request, stream)| {
+        if let Err(err) = respond(stream, request, config) {
+            log::warn!("Request failed: {err}");
+        }
+    });
+    Ok(())
+}
+
+/// Runs `handle` on a worker thread for each item of `requests`, in order. At most
+/// `queue_size` items wait for the worker; past that, pulling the next item blocks, so
+/// a producer faster than the worker is throttled rather than buffered without limit.
+fn run_queued<T: Send>(
+    requests: impl Iterator<Item = T>,
+    queue_size: usize,
+    handle: impl FnMut(T) + Send,
+) {
+    thread::scope(|scope| {
+        let (sender, queue) = mpsc::sync_channel(queue_size);
+        scope.spawn(move || queue.into_iter().for_each(handle));
+        for request in requests {
+            // Only fails if the worker panicked, which the scope re-raises.
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Reads one HTTP request from `stream` and writes the response.
+pub fn handle_connection<S: Read + Write>(mut stream: S, config: &Config) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    respond(stream, request, config)
+}
+
+/// Writes the response to a request read by `read_request`.
+fn respond<S: Write>(mut stream: S, request: Request, config: &Config) -> io::Result<()> {
+    let (status, content_type, body) = match request {
+        Request::Received { method, .. } if method != "POST" => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Only POST is supported\n".to_vec(),
+        ),
+        Request::Received { body, .. } => match run_feed(&body, config) {
+            Ok(json) => ("200 OK", "application/json", json),
+            Err(err) => (
+                "400 Bad Request",
+                "text/plain",
+                format!("{err}\n").into_bytes(),
+            ),
+        },
+        Request::Malformed => (
+            "400 Bad Request",
+            "text/plain",
+            b"Malformed request\n".to_vec(),
+        ),
+        Request::TooLarge => (
+            "413 Content Too Large",
+            "text/plain",
+            format!("Request body is over {MAX_BODY_SIZE} bytes\n").into_bytes(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+/// Reads one request from `stream`, failing with `TimedOut` if it takes longer than
+/// `limit` overall or any single read blocks for `IO_TIMEOUT`.
+fn read_request_within(stream: &TcpStream, limit: Duration) -> io::Result<Request> {
+    let mut reader = DeadlineReader {
+        stream,
+        deadline: Instant::now() + limit,
+    };
+    read_request(&mut reader)
+}
+
+/// Reads from a stream with each read's timeout cut to what is left until `deadline`.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream
+            .set_read_timeout(Some(remaining.min(IO_TIMEOUT)))?;
+        // Unix reports an expired read timeout as `WouldBlock`, Windows as `TimedOut`.
+        self.stream.read(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut.into(),
+            _ => err,
+        })
+    }
+}
+
+/// A request as read by `read_request`.
+enum Request {
+    Received {
+        method: String,
+        body: Vec<u8>,
+    },
+    /// The request line or headers are malformed, or the headers are over
+    /// `MAX_HEAD_SIZE`.
+    Malformed,
+    /// `Content-Length` is over `MAX_BODY_SIZE`; the body is left unread.
+    TooLarge,
+}
+
+fn read_request<S: Read>(stream: &mut S) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEAD_SIZE);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let Some(method) = line.split_whitespace().next().map(str::to_string) else {
+        return Ok(Request::Malformed);
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            return Ok(Request::Malformed);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(len) => content_length = len,
+                    Err(_) => return Ok(Request::Malformed),
+                }
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Request::TooLarge);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request::Received { method, body })
+}
+
+fn run_feed(body: &[u8], config: &Config) -> Result<Vec<u8>, AppError> {
+    let csv = std::str::from_utf8(body)
+        .map_err(|err| AppError::TxProcessing(format!("Request body is not UTF-8: {err}")))?;
+    let records = parse_transactions_from_source(&InMemorySource(csv), &config.csv_options())?;
+
+    let output_options = config.output_options();
+    let mut tx_engine = config.engine_builder().build();
+    let stop = AtomicBool::new(false);
+    process_records(
+        &mut tx_engine,
+        records,
+        config.input.strict,
+        &stop,
+        None,
+        None,
+    )?;
+
+    let snapshots = tx_engine.clients_snapshot();
+    if output_options.integer_cents {
+        let inexact = clients_with_rounding_loss(&snapshots, output_options.scale);
+        if !inexact.is_empty() {
+            return Err(AppError::InexactMinorUnits(inexact));
+        }
+    }
+    let mut json = Vec::new();
+    write_clients_snapshot_json(&mut json, &snapshots, &output_options)
+        .map_err(AppError::Output)?;
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn post_csv_returns_json_balances() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &Config::default()).unwrap();
+        });
+
+        let csv = "type,client,tx,amount\ndeposit,1,1,5.0\nwithdrawal,1,2,1.5\ndeposit,2,3,2.0\n";
+        let mut client = TcpStream::connect(addr).unwrap();
+        write!(
+            client,
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{csv}",
+            csv.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        let balances: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            balances,
+            serde_json::json!([
+                {"client": 1, "available": "3.5000", "held": "0.0000", "total": "3.5000", "locked": false},
+                {"client": 2, "available": "2.0000", "held": "0.0000", "total": "2.0000", "locked": false},
+            ])
+        );
+    }
+
+    #[test]
+    fn oversized_content_length_is_rejected_unread() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &Config::default()).unwrap();
+        });
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        write!(
+            client,
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        )
+        .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 413 Content Too Large"));
+    }
+
+    #[test]
+    fn trickling_client_times_out_at_the_request_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut client = TcpStream::connect(addr).unwrap();
+            // Each byte arrives well within `IO_TIMEOUT`, but the request never ends.
+            for _ in 0..40 {
+                if client.write_all(b"X").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let started = Instant::now();
+        let result = read_request_within(&stream, Duration::from_millis(100));
+
+        assert_eq!(
+            result.err().map(|err| err.kind()),
+            Some(io::ErrorKind::TimedOut)
+        );
+        assert!(started.elapsed() < Duration::from_millis(350));
+        drop(stream);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn full_queue_throttles_the_producer() {
+        let pulled = AtomicUsize::new(0);
+        let handled = Mutex::new(Vec::new());
+        let (release, gate) = mpsc::channel::<()>();
+
+        thread::scope(|scope| {
+            let (pulled, handled) = (&pulled, &handled);
+            scope.spawn(move || {
+                let requests = (0..100).inspect(|_| {
+                    pulled.fetch_add(1, Ordering::SeqCst);
+                });
+                run_queued(requests, 2, move |request| {
+                    // Blocks until the test lets the worker go.
+                    let _ = gate.recv();
+                    handled.lock().unwrap().push(request);
+                });
+            });
+
+            // One request with the worker, two queued and one blocked in `send`.
+            while pulled.load(Ordering::SeqCst) < 4 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(pulled.load(Ordering::SeqCst), 4);
+            assert!(handled.lock().unwrap().is_empty());
+            drop(release);
+        });
+
+        assert_eq!(pulled.load(Ordering::SeqCst), 100);
+        assert_eq!(*handled.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,166 @@
+//! Helpers for tests written around the engine. Enabled by the `test-utils` feature.
+
+use rust_decimal::Decimal;
+
+use crate::{
+    balance_store::BalanceStore,
+    domain::types::{Amount, ClientId, TransactionType, TxID},
+    io::input::Transaction,
+    tx_engine::TxEngine,
+};
+
+/// Builds a `Transaction` without going through CSV.
+#[derive(Debug)]
+pub struct TransactionBuilder(Transaction);
+
+impl TransactionBuilder {
+    pub fn new(op_type: TransactionType, client: u16, tx_id: u32) -> Self {
+        TransactionBuilder(Transaction {
+            op_type,
+            client: ClientId(client),
+            tx_id: TxID(tx_id),
+            amount: None,
+            reason: None,
+            row_digest: None,
+        })
+    }
+
+    pub fn amount(mut self, amount: Decimal) -> Self {
+        self.0.amount = Some(Amount::new(amount));
+        self
+    }
+
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.0.reason = Some(reason.to_owned());
+        self
+    }
+
+    pub fn build(self) -> Transaction {
+        self.0
+    }
+}
+
+/// A `TransactionBuilder` in one call, for rows written out as plain values.
+pub fn make_tx(
+    op_type: TransactionType,
+    client: u16,
+    tx_id: u32,
+    amount: Option<Amount>,
+) -> Transaction {
+    let builder = TransactionBuilder::new(op_type, client, tx_id);
+    match amount {
+        Some(amount) => builder.amount(amount.inner()),
+        None => builder,
+    }
+    .build()
+}
+
+/// `per_client` rows for each of `clients` clients, either grouped by client (`bursty`)
+/// or round-robin across them. Each client repeats deposit, deposit, withdrawal, then a
+/// dispute of its own first deposit of that round, so every row applies.
+pub fn client_feed(clients: u16, per_client: u32, bursty: bool) -> Vec<Transaction> {
+    let row = |client: u16, step: u32| {
+        // Ids are unique across clients: client-major, then step.
+        let tx_id = u32::from(client) * per_client + step + 1;
+        match step % 4 {
+            0 | 1 => make_tx(
+                TransactionType::Deposit,
+                client,
+                tx_id,
+                Some(Amount::new(Decimal::new(25, 1))),
+            ),
+            2 => make_tx(
+                TransactionType::Withdrawal,
+                client,
+                tx_id,
+                Some(Amount::new(Decimal::new(125, 2))),
+            ),
+            _ => make_tx(TransactionType::Dispute, client, tx_id - 3, None),
+        }
+    };
+    if bursty {
+        (0..clients)
+            .flat_map(|client| (0..per_client).map(move |step| row(client, step)))
+            .collect()
+    } else {
+        (0..per_client)
+            .flat_map(|step| (0..clients).map(move |client| row(client, step)))
+            .collect()
+    }
+}
+
+/// Panics unless `client` has an account with these balances. Amounts compare by value,
+/// so `1.5` matches `1.50`.
+#[track_caller]
+pub fn assert_balances<S: BalanceStore>(
+    engine: &TxEngine<S>,
+    client: u16,
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+) {
+    let snapshot = engine
+        .clients_snapshot()
+        .into_iter()
+        .find(|snapshot| snapshot.client_id == ClientId(client))
+        .unwrap_or_else(|| panic!("client {client} has no account"));
+    let actual = (
+        snapshot.available.inner(),
+        snapshot.held.inner(),
+        snapshot.locked,
+    );
+    assert_eq!(
+        actual,
+        (available, held, locked),
+        "balances of client {client} as (available, held, locked)"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn builds_transactions_and_asserts_balances() {
+        let mut engine = TxEngine::new();
+        let workload = [
+            TransactionBuilder::new(TransactionType::Deposit, 1, 1)
+                .amount(dec!(2.5))
+                .build(),
+            TransactionBuilder::new(TransactionType::Deposit, 1, 2)
+                .amount(dec!(1.0))
+                .build(),
+            TransactionBuilder::new(TransactionType::Dispute, 1, 2)
+                .reason("fraud")
+                .build(),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        assert_balances(&engine, 1, dec!(2.50), dec!(1), false);
+
+        engine
+            .process_transaction(
+                &TransactionBuilder::new(TransactionType::Chargeback, 1, 2).build(),
+            )
+            .unwrap();
+        assert_balances(&engine, 1, dec!(2.5), dec!(0), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "balances of client 1")]
+    fn assert_balances_panics_on_mismatch() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(
+                &TransactionBuilder::new(TransactionType::Deposit, 1, 1)
+                    .amount(dec!(2.5))
+                    .build(),
+            )
+            .unwrap();
+
+        assert_balances(&engine, 1, dec!(2.0), dec!(0), false);
+    }
+}
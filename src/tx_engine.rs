@@ -1,80 +1,655 @@
 use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
+    balance_store::{BalanceStore, CachedClientStore},
     domain::{
-        errors::AppError,
-        types::{Amount, ClientId, TransactionType, TxID},
+        errors::{AppError, ProcessError, Rejection, RejectionCategory},
+        types::{Amount, ClientId, Money, TransactionType, TxID},
     },
-    io::input::Transaction,
+    io::input::{row_digest, RowDigest, Transaction},
 };
 
-pub struct TxEngine {
-    users: std::collections::HashMap<ClientId, ClientData>,
+/// Applies transactions to client accounts.
+///
+/// Account lifecycle: an account is created by the first successful deposit for its
+/// client, or up front with `create_account`. Every other operation on an unknown
+/// client is rejected without creating anything. Accounts are only removed when
+/// `undo_last` reverts the deposit that opened one; a chargeback may lock one, after
+/// which it only shows up in snapshots.
+pub struct TxEngine<S = CachedClientStore, M = Decimal> {
+    users: S,
     processed_tx_ids: HashSet<TxID>,
+    stats: ProcessingStats,
+    audit_log: Vec<AuditEntry<M>>,
+    /// Rows compared against by `dedup_identical`: only the last one with `Previous`.
+    seen_rows: HashSet<RowDigest>,
+    /// How to revert the most recently applied transaction, for `undo_last`.
+    last_applied: Option<UndoEntry<M>>,
+    /// Skipped transactions, oldest first, of which `recent_skips` shows the last ones.
+    recent_skips: Vec<SkipRecord>,
+    options: EngineOptions<M>,
+}
+
+/// Skipped transactions kept by `TxEngine::recent_skips` unless the builder sets another
+/// bound.
+pub const DEFAULT_RECENT_SKIPS: usize = 32;
+
+/// A transaction the engine skipped, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkipRecord {
+    pub op_type: TransactionType,
+    pub client_id: ClientId,
+    pub tx_id: TxID,
+    pub rejection: Rejection,
+}
+
+/// Which earlier rows a row is compared with to detect an accidentally repeated line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdenticalRowScope {
+    /// Only the immediately preceding row.
+    Previous,
+    /// Every row processed so far.
+    Any,
+}
+
+/// What to do with a deposit/withdrawal whose tx id was already processed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateTxPolicy {
+    /// Skip the transaction as a non-critical error.
+    #[default]
+    Reject,
+    /// Treat an identical repeat as a successful no-op. A repeat with a different
+    /// client, type or amount is skipped as a non-critical error.
+    IgnoreSecond,
+    /// Fail processing with a critical error.
+    Error,
+}
+
+/// Which deposits and withdrawals a tx id must be unique among.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupScope {
+    /// Those of every client.
+    #[default]
+    Global,
+    /// Those of the same client, so different clients may reuse a tx id. Disputes,
+    /// resolves and chargebacks always refer to the tx id within their own client.
+    PerClient,
+}
+
+/// What a chargeback does with the disputed funds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargebackPolicy {
+    /// Remove the held funds from the account and lock it.
+    #[default]
+    RemoveFunds,
+    /// Return the held funds to available and leave the account unlocked, like a
+    /// resolve forced by an admin.
+    ReturnToAvailable,
+}
+
+/// What is done with an amount given on a resolve or chargeback row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleAmountPolicy {
+    /// The amount is not read.
+    #[default]
+    Ignore,
+    /// An amount other than the one the row releases or charges back rejects the row,
+    /// as a sign of a corrupted feed. Rows without an amount are unaffected.
+    Strict,
+}
+
+/// How much a resolve releases when a transaction has several partial disputes open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveScope {
+    /// Release everything held for the transaction and close the dispute.
+    #[default]
+    Transaction,
+    /// Release only the oldest open partial dispute. The transaction stays in dispute
+    /// until a resolve or chargeback has closed every portion.
+    Dispute,
+}
+
+#[derive(Debug, Clone, Default)]
+struct EngineOptions<M = Decimal> {
+    allow_withdrawal_disputes: bool,
+    reject_unknown_client_withdrawals: bool,
+    negatives_are_withdrawals: bool,
+    duplicate_tx_policy: DuplicateTxPolicy,
+    dedup_scope: DedupScope,
+    chargeback_policy: ChargebackPolicy,
+    resolve_scope: ResolveScope,
+    lifecycle_amount_policy: LifecycleAmountPolicy,
+    max_open_disputes: Option<usize>,
+    dedup_identical: Option<IdenticalRowScope>,
+    available_ceiling: Option<Amount<M>>,
+    record_audit: bool,
+    recent_skips: Option<usize>,
+    panic_safe: bool,
+    verify_holds: bool,
+    amount_scale: Option<u32>,
+    system_account: Option<ClientId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxEngineBuilder<M = Decimal> {
+    options: EngineOptions<M>,
+}
+
+impl<M: Money> TxEngineBuilder<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets withdrawals be disputed. A disputed withdrawal holds the withdrawn amount
+    /// until it is resolved (dropped) or charged back (returned to available).
+    pub fn allow_withdrawal_disputes(mut self, allow: bool) -> Self {
+        self.options.allow_withdrawal_disputes = allow;
+        self
+    }
+
+    /// Rejects a withdrawal for a client without an account as `UnknownClient` rather
+    /// than `InsufficientFunds`. Off by default for now; the default is meant to flip in
+    /// a later release.
+    pub fn reject_withdrawals_for_unknown_clients(mut self, reject: bool) -> Self {
+        self.options.reject_unknown_client_withdrawals = reject;
+        self
+    }
+
+    /// Reads a deposit with a negative amount as a withdrawal of its absolute value.
+    /// Otherwise negative amounts are rejected.
+    pub fn negatives_are_withdrawals(mut self, enabled: bool) -> Self {
+        self.options.negatives_are_withdrawals = enabled;
+        self
+    }
+
+    pub fn duplicate_tx_policy(mut self, policy: DuplicateTxPolicy) -> Self {
+        self.options.duplicate_tx_policy = policy;
+        self
+    }
+
+    /// Chooses whether the duplicate tx policy applies to tx ids reused by any client
+    /// (the default) or only by the same client.
+    pub fn dedup_scope(mut self, scope: DedupScope) -> Self {
+        self.options.dedup_scope = scope;
+        self
+    }
+
+    pub fn chargeback_policy(mut self, policy: ChargebackPolicy) -> Self {
+        self.options.chargeback_policy = policy;
+        self
+    }
+
+    pub fn lifecycle_amount_policy(mut self, policy: LifecycleAmountPolicy) -> Self {
+        self.options.lifecycle_amount_policy = policy;
+        self
+    }
+
+    /// Chooses whether a resolve closes every partial dispute of a transaction or only
+    /// the oldest one. A chargeback always closes all of them.
+    pub fn resolve_scope(mut self, scope: ResolveScope) -> Self {
+        self.options.resolve_scope = scope;
+        self
+    }
+
+    /// Caps the number of disputes a client can have open at once. Unlimited by default.
+    pub fn max_open_disputes(mut self, max: usize) -> Self {
+        self.options.max_open_disputes = Some(max);
+        self
+    }
+
+    /// Skips a row whose fields are byte for byte those of an earlier row in `scope`, as
+    /// read after `--trim`, before any other check; `1.0` and `1.00` differ. Unlike the
+    /// duplicate tx policy, this catches a line repeated by an export bug even when the
+    /// repeat would be an error or a valid dispute lifecycle op. Off by default.
+    ///
+    /// With `IdenticalRowScope::Any` a 16-byte digest of every row is kept for the whole
+    /// run, so memory grows with the input: roughly 20 to 40 MB per million rows, hash
+    /// set overhead included. `Previous` keeps one.
+    pub fn dedup_identical(mut self, scope: IdenticalRowScope) -> Self {
+        self.options.dedup_identical = Some(scope);
+        self
+    }
+
+    /// Keeps available at or below `ceiling`: after each applied transaction, funds above
+    /// it are parked in held, and parked funds move back while available is below it.
+    /// Parked funds are reported separately from disputed ones, see
+    /// `ClientSnapshot::parked`, and are never touched by resolves or chargebacks.
+    pub fn available_ceiling(mut self, ceiling: Amount<M>) -> Self {
+        self.options.available_ceiling = Some(ceiling);
+        self
+    }
+
+    /// Records the balance change of every applied transaction, see `TxEngine::audit_log`.
+    pub fn record_audit(mut self, enabled: bool) -> Self {
+        self.options.record_audit = enabled;
+        self
+    }
+
+    /// Keeps the last `capacity` skipped transactions for `TxEngine::recent_skips`
+    /// instead of `DEFAULT_RECENT_SKIPS`. Zero keeps none.
+    pub fn recent_skips(mut self, capacity: usize) -> Self {
+        self.options.recent_skips = Some(capacity);
+        self
+    }
+
+    /// Turns a panic while processing a transaction into a `Critical` error naming that
+    /// transaction instead of unwinding into the caller. Changes the transaction made
+    /// before panicking are not rolled back, so the error stops the run rather than
+    /// letting it go on from possibly inconsistent balances.
+    pub fn panic_safe(mut self, enabled: bool) -> Self {
+        self.options.panic_safe = enabled;
+        self
+    }
+
+    /// Checks on every dispute that held stays within what the client deposited, failing
+    /// with a critical error if it would not. Always on in debug builds.
+    pub fn verify_holds(mut self, enabled: bool) -> Self {
+        self.options.verify_holds = enabled;
+        self
+    }
+
+    /// Cuts every transaction amount to `scale` decimal places, towards zero, before it
+    /// is applied or stored. Sums and differences of such amounts need no rounding, so
+    /// balances, holds and recorded transactions all stay at `scale` and, for a scale of
+    /// at most 4, equal the printed values. Off by default: amounts are kept exact and
+    /// only cut on output.
+    pub fn amount_scale(mut self, scale: u32) -> Self {
+        self.options.amount_scale = Some(scale);
+        self
+    }
+
+    /// Designates `client` as the house account that fees, transfers and corrections
+    /// flow through. Its account is opened up front, withdrawals may take it negative,
+    /// and a chargeback against it never locks it.
+    pub fn system_account(mut self, client: ClientId) -> Self {
+        self.options.system_account = Some(client);
+        self
+    }
+
+    pub fn build_with_store<S: BalanceStore<M>>(self, store: S) -> TxEngine<S, M> {
+        let system_account = self.options.system_account;
+        let mut engine = TxEngine {
+            users: store,
+            processed_tx_ids: HashSet::new(),
+            stats: ProcessingStats::default(),
+            audit_log: Vec::new(),
+            seen_rows: HashSet::new(),
+            last_applied: None,
+            recent_skips: Vec::new(),
+            options: self.options,
+        };
+        if let Some(client) = system_account {
+            engine.create_account(client);
+        }
+        engine
+    }
+}
+
+impl TxEngineBuilder {
+    pub fn build(self) -> TxEngine {
+        self.build_with_store(CachedClientStore::default())
+    }
 }
 
-struct ClientData {
-    balances: Balances,
-    txs: HashMap<TxID, TransactionRecord>,
-    disputed_txs: HashMap<TxID, Amount>,
-    frozen: bool,
+/// Per-client state owned by a `BalanceStore`. Its contents are managed by the engine.
+#[derive(Clone)]
+pub struct ClientData<M = Decimal> {
+    balances: Balances<M>,
+    txs: HashMap<TxID, TransactionRecord<M>>,
+    disputed_txs: HashMap<TxID, DisputedFunds<M>>,
+    /// Keys of `disputed_txs` in the order their disputes were opened, so bulk
+    /// operations handle the oldest first.
+    dispute_order: Vec<TxID>,
+    /// Amounts of the dispute rows making up each entry of `disputed_txs`, oldest first.
+    dispute_portions: HashMap<TxID, Vec<Amount<M>>>,
+    /// Reason codes given on the dispute rows of open disputes.
+    dispute_reasons: HashMap<TxID, String>,
+    /// Amount charged back per transaction, which may be less than the full amount
+    /// after partial disputes.
+    charged_back_txs: HashMap<TxID, Amount<M>>,
+    /// Applied disputes, resolves and chargebacks per referenced transaction, in order.
+    lifecycle_events: HashMap<TxID, Vec<TransactionRecord<M>>>,
+    /// Part of `balances.held` parked above the available ceiling, not disputed.
+    parked: Amount<M>,
+    /// Balances given to `seed_client`, which no recorded transaction accounts for.
+    opening: Balances<M>,
+    lock_reason: Option<LockReason>,
+    /// Highest `available` and `available + held` after any applied transaction.
+    peak_available: Amount<M>,
+    peak_total: Amount<M>,
 }
 
-impl ClientData {
+impl<M: Money> ClientData<M> {
     fn init() -> Self {
         ClientData {
             balances: Balances::init(),
             txs: HashMap::new(),
             disputed_txs: HashMap::new(),
-            frozen: false,
+            dispute_order: Vec::new(),
+            dispute_portions: HashMap::new(),
+            dispute_reasons: HashMap::new(),
+            charged_back_txs: HashMap::new(),
+            lifecycle_events: HashMap::new(),
+            parked: Amount::ZERO,
+            opening: Balances::init(),
+            lock_reason: None,
+            peak_available: Amount::ZERO,
+            peak_total: Amount::ZERO,
         }
     }
+
+    /// Forgets the open dispute of `tx_id` once it is resolved or charged back.
+    fn close_dispute(&mut self, tx_id: TxID) {
+        self.disputed_txs.remove(&tx_id);
+        self.dispute_order.retain(|open| *open != tx_id);
+        self.dispute_portions.remove(&tx_id);
+        self.dispute_reasons.remove(&tx_id);
+    }
+
+    fn record_peaks(&mut self) {
+        self.peak_available = self.peak_available.max(self.balances.available);
+        self.peak_total = self
+            .peak_total
+            .max(self.balances.available + self.balances.held);
+    }
+
+    /// The most `balances.held` can be without holding more than was deposited: deposits
+    /// net of chargebacks, plus parked funds, seeded held funds and held withdrawals,
+    /// which are not held out of deposits. `None` if the sum overflows.
+    fn max_held(&self) -> Option<Amount<M>> {
+        let start = self.parked.checked_add(self.opening.held)?;
+        self.txs.values().try_fold(start, |max, tx| match tx {
+            TransactionRecord::Deposit { tx_id, amount, .. } => {
+                let charged_back = self.charged_back_txs.get(tx_id).copied();
+                max.checked_add(*amount)?
+                    .checked_sub(charged_back.unwrap_or(Amount::ZERO))
+            }
+            TransactionRecord::Withdrawal { tx_id, .. } => match self.disputed_txs.get(tx_id) {
+                Some(funds @ DisputedFunds::Withdrawal(_)) => max.checked_add(funds.amount()),
+                _ => Some(max),
+            },
+            TransactionRecord::Dispute { .. }
+            | TransactionRecord::Resolve { .. }
+            | TransactionRecord::Chargeback { .. }
+            | TransactionRecord::Correction { .. } => Some(max),
+        })
+    }
 }
 
-pub struct ClientSnapshot {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientSnapshot<M = Decimal> {
     pub client_id: ClientId,
-    pub available: Amount,
-    pub held: Amount,
+    pub available: Amount<M>,
+    pub held: Amount<M>,
+    /// Part of `held` parked above the available ceiling rather than disputed.
+    pub parked: Amount<M>,
     pub locked: bool,
+    pub lock_reason: Option<LockReason>,
+}
+
+/// Why an account is locked: the chargeback of `tx_id`, or `None` for an account
+/// seeded as locked, whose chargeback is not known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockReason {
+    pub tx_id: Option<TxID>,
 }
 
-impl ClientSnapshot {
-    pub fn total(&self) -> Amount {
+impl<M: Money> ClientSnapshot<M> {
+    pub fn total(&self) -> Amount<M> {
         self.available + self.held
     }
 }
 
+/// Amount held by a single open dispute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldEntry<M = Decimal> {
+    pub client_id: ClientId,
+    pub tx_id: TxID,
+    pub held: Amount<M>,
+    pub reason: Option<String>,
+}
+
+/// Balance change made by one applied transaction. For disputes, resolves and
+/// chargebacks `tx_id` is the disputed transaction and `reason` the row's reason code.
+/// An account opened outside any transaction, by `create_account`, `seed_client` or as
+/// the system account, gets an entry with no `op_type` or `tx_id` whose deltas are its
+/// opening balances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry<M = Decimal> {
+    pub client_id: ClientId,
+    pub op_type: Option<TransactionType>,
+    pub tx_id: Option<TxID>,
+    pub available_delta: Amount<M>,
+    pub held_delta: Amount<M>,
+    pub locked: bool,
+    pub reason: Option<String>,
+    /// Set for corrections, which an operator entered by hand outside the normal flow.
+    pub manual: bool,
+}
+
+/// One applied transaction in the history of a tx id, see `TxEngine::transaction_history`.
+/// `amount` is the deposit, withdrawal or signed correction amount, or the disputed
+/// portion of a partial dispute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry<M = Decimal> {
+    pub op_type: TransactionType,
+    pub client_id: ClientId,
+    pub tx_id: TxID,
+    pub amount: Option<Amount<M>>,
+    pub reason: Option<String>,
+}
+
+impl<M: Money> From<&TransactionRecord<M>> for HistoryEntry<M> {
+    fn from(record: &TransactionRecord<M>) -> Self {
+        let (op_type, client_id, tx_id, amount, reason) = match record {
+            TransactionRecord::Deposit {
+                client,
+                tx_id,
+                amount,
+            } => (TransactionType::Deposit, client, tx_id, Some(*amount), None),
+            TransactionRecord::Withdrawal {
+                client,
+                tx_id,
+                amount,
+            } => (
+                TransactionType::Withdrawal,
+                client,
+                tx_id,
+                Some(*amount),
+                None,
+            ),
+            TransactionRecord::Dispute {
+                client,
+                disputed_tx_id,
+                amount,
+                reason,
+            } => (
+                TransactionType::Dispute,
+                client,
+                disputed_tx_id,
+                *amount,
+                reason.clone(),
+            ),
+            TransactionRecord::Resolve {
+                client,
+                disputed_tx_id,
+                amount,
+                reason,
+            } => (
+                TransactionType::Resolve,
+                client,
+                disputed_tx_id,
+                *amount,
+                reason.clone(),
+            ),
+            TransactionRecord::Chargeback {
+                client,
+                disputed_tx_id,
+                amount,
+                reason,
+            } => (
+                TransactionType::Chargeback,
+                client,
+                disputed_tx_id,
+                *amount,
+                reason.clone(),
+            ),
+            TransactionRecord::Correction {
+                client,
+                tx_id,
+                amount,
+                reason,
+            } => (
+                TransactionType::Correction,
+                client,
+                tx_id,
+                Some(*amount),
+                Some(reason.clone()),
+            ),
+        };
+        HistoryEntry {
+            op_type,
+            client_id: *client_id,
+            tx_id: *tx_id,
+            amount,
+            reason,
+        }
+    }
+}
+
+/// Counts of successfully applied transactions per operation type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProcessingStats {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+    pub corrections: u64,
+}
+
+/// Balances summed across all clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals<M = Decimal> {
+    pub available: Amount<M>,
+    pub held: Amount<M>,
+    pub locked_accounts: usize,
+}
+
 trait ClientOwned {
     fn client_id(&self) -> &ClientId;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum TransactionRecord {
+/// State of one client before a transaction, limited to what applying it can change.
+/// The dispute entries are those of the transaction it references.
+struct UndoEntry<M> {
+    record: TransactionRecord<M>,
+    row: RowDigest,
+    stats: ProcessingStats,
+    audit_len: usize,
+    /// `None` if the transaction opened the account.
+    client: Option<ClientBefore<M>>,
+}
+
+struct ClientBefore<M> {
+    balances: Balances<M>,
+    parked: Amount<M>,
+    lock_reason: Option<LockReason>,
+    peaks: (Amount<M>, Amount<M>),
+    disputed: Option<DisputedFunds<M>>,
+    /// Where the tx id was in `dispute_order`.
+    dispute_position: Option<usize>,
+    dispute_portions: Option<Vec<Amount<M>>>,
+    dispute_reason: Option<String>,
+    charged_back: Option<Amount<M>>,
+}
+
+/// The key `dedup_identical` compares rows by: the digest the reader took of the row's
+/// raw fields, or for a transaction built in code, a digest of its fields as displayed.
+/// Either way `1.0` and `1.00` are different rows.
+fn row_key<M: Money>(tx: &Transaction<M>) -> RowDigest {
+    tx.row_digest.unwrap_or_else(|| {
+        let fields = [
+            tx.op_type.to_string(),
+            tx.client.to_string(),
+            tx.tx_id.to_string(),
+            tx.amount
+                .map(|amount| amount.to_string())
+                .unwrap_or_default(),
+            tx.reason.clone().unwrap_or_default(),
+        ];
+        row_digest(fields.iter().map(|field| field.as_bytes()))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TransactionRecord<M> {
     Deposit {
         client: ClientId,
         tx_id: TxID,
-        amount: Amount,
+        amount: Amount<M>,
     },
     Withdrawal {
         client: ClientId,
         tx_id: TxID,
-        amount: Amount,
+        amount: Amount<M>,
     },
     Dispute {
         client: ClientId,
         disputed_tx_id: TxID,
+        /// Portion of the disputed transaction to hold; the full amount when `None`.
+        amount: Option<Amount<M>>,
+        reason: Option<String>,
     },
     Resolve {
         client: ClientId,
         disputed_tx_id: TxID,
+        /// The row's amount, kept only under `LifecycleAmountPolicy::Strict`.
+        amount: Option<Amount<M>>,
+        reason: Option<String>,
     },
     Chargeback {
         client: ClientId,
         disputed_tx_id: TxID,
+        /// The row's amount, kept only under `LifecycleAmountPolicy::Strict`.
+        amount: Option<Amount<M>>,
+        reason: Option<String>,
+    },
+    Correction {
+        client: ClientId,
+        tx_id: TxID,
+        /// Signed change to available.
+        amount: Amount<M>,
+        reason: String,
     },
 }
 
-impl ClientOwned for TransactionRecord {
+impl<M> TransactionRecord<M> {
+    /// The id of a deposit or withdrawal, or of the transaction a lifecycle row refers to.
+    fn tx_id(&self) -> TxID {
+        match self {
+            TransactionRecord::Deposit { tx_id, .. }
+            | TransactionRecord::Withdrawal { tx_id, .. }
+            | TransactionRecord::Correction { tx_id, .. } => *tx_id,
+            TransactionRecord::Dispute { disputed_tx_id, .. }
+            | TransactionRecord::Resolve { disputed_tx_id, .. }
+            | TransactionRecord::Chargeback { disputed_tx_id, .. } => *disputed_tx_id,
+        }
+    }
+}
+
+impl<M> ClientOwned for TransactionRecord<M> {
     fn client_id(&self) -> &ClientId {
         match self {
             TransactionRecord::Deposit { client, .. } => client,
@@ -82,22 +657,76 @@ impl ClientOwned for TransactionRecord {
             TransactionRecord::Dispute { client, .. } => client,
             TransactionRecord::Resolve { client, .. } => client,
             TransactionRecord::Chargeback { client, .. } => client,
+            TransactionRecord::Correction { client, .. } => client,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DisputedFunds<M> {
+    Deposit(Amount<M>),
+    Withdrawal(Amount<M>),
+}
+
+impl<M: Money> DisputedFunds<M> {
+    fn amount(self) -> Amount<M> {
+        match self {
+            DisputedFunds::Deposit(amount) | DisputedFunds::Withdrawal(amount) => amount,
+        }
+    }
+
+    fn with_amount(self, amount: Amount<M>) -> Self {
+        match self {
+            DisputedFunds::Deposit(_) => DisputedFunds::Deposit(amount),
+            DisputedFunds::Withdrawal(_) => DisputedFunds::Withdrawal(amount),
+        }
+    }
+
+    /// (available, held) change when the dispute is opened.
+    fn on_dispute(self) -> (Amount<M>, Amount<M>) {
+        match self {
+            DisputedFunds::Deposit(amount) => (-amount, amount),
+            DisputedFunds::Withdrawal(amount) => (Amount::ZERO, amount),
+        }
+    }
+
+    /// (available, held) change when the dispute is resolved.
+    fn on_resolve(self) -> (Amount<M>, Amount<M>) {
+        match self {
+            DisputedFunds::Deposit(amount) => (amount, -amount),
+            DisputedFunds::Withdrawal(amount) => (Amount::ZERO, -amount),
+        }
+    }
+
+    /// (available, held) change when the dispute is charged back.
+    fn on_chargeback(self) -> (Amount<M>, Amount<M>) {
+        match self {
+            DisputedFunds::Deposit(amount) => (Amount::ZERO, -amount),
+            DisputedFunds::Withdrawal(amount) => (amount, -amount),
         }
     }
 }
 
-struct Balances {
-    available: Amount,
-    held: Amount,
+#[derive(Debug, Clone, Copy)]
+struct Balances<M> {
+    available: Amount<M>,
+    held: Amount<M>,
 }
 
-impl Balances {
+impl<M: Money> Balances<M> {
     fn init() -> Self {
         Balances {
             available: Amount::ZERO,
             held: Amount::ZERO,
         }
     }
+
+    fn checked_apply(self, (available, held): (Amount<M>, Amount<M>)) -> Option<Self> {
+        Some(Balances {
+            available: self.available.checked_add(available)?,
+            held: self.held.checked_add(held)?,
+        })
+    }
 }
 
 impl Default for TxEngine {
@@ -108,166 +737,1032 @@ impl Default for TxEngine {
 
 impl TxEngine {
     pub fn new() -> Self {
-        TxEngine {
-            users: std::collections::HashMap::new(),
-            processed_tx_ids: HashSet::new(),
-        }
+        TxEngineBuilder::new().build()
     }
 
-    pub fn clients_snapshot(&self) -> Vec<ClientSnapshot> {
-        let mut snapshots: Vec<ClientSnapshot> = self
-            .users
-            .iter()
-            .map(|(client_id, data)| ClientSnapshot {
-                client_id: *client_id,
-                available: data.balances.available,
-                held: data.balances.held,
-                locked: data.frozen,
-            })
-            .collect();
+    pub fn builder() -> TxEngineBuilder {
+        TxEngineBuilder::new()
+    }
 
-        snapshots.sort_by_key(|snapshot| snapshot.client_id.0);
-        snapshots
+    /// Rebuilds client balances and lock states by replaying audit entries in order,
+    /// including accounts opened or seeded outside any transaction. Transaction history
+    /// is not part of the log, so the result is only meant for comparing snapshots.
+    pub fn from_audit_log<'a, I>(entries: I) -> Result<Self, ProcessError>
+    where
+        I: IntoIterator<Item = &'a AuditEntry>,
+    {
+        let mut engine = TxEngine::new();
+        for entry in entries {
+            let user = engine
+                .users
+                .get_or_insert_with(entry.client_id, ClientData::init);
+            user.balances = checked_balance(
+                user.balances
+                    .checked_apply((entry.available_delta, entry.held_delta)),
+                entry.client_id,
+            )?;
+            if entry.op_type.is_none() {
+                user.opening = user.balances;
+            }
+            if !entry.locked {
+                user.lock_reason = None;
+            } else if user.lock_reason.is_none() {
+                user.lock_reason = Some(LockReason { tx_id: entry.tx_id });
+            }
+        }
+        Ok(engine)
     }
+}
 
-    pub fn process_transaction(&mut self, tx: &Transaction) -> Result<(), AppError> {
-        let record = Self::to_transaction_record(tx)?;
-        self.process_transaction_internal(&record)?;
-        self.record_processed_transaction(record);
-        Ok(())
+impl<S: BalanceStore> TxEngine<S> {
+    /// SHA-256 over every client's id, available, held and locked state, in client
+    /// order. Amounts are hashed by value, so `1.5` and `1.50` agree, and transaction
+    /// history is left out: engines that end in the same balances have the same digest.
+    pub fn state_digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for snapshot in self.clients_snapshot() {
+            hasher.update(snapshot.client_id.0.to_le_bytes());
+            hasher.update(snapshot.available.inner().normalize().serialize());
+            hasher.update(snapshot.held.inner().normalize().serialize());
+            hasher.update([u8::from(snapshot.locked)]);
+        }
+        hasher.finalize().into()
     }
+}
 
-    fn process_transaction_internal(&mut self, tx: &TransactionRecord) -> Result<(), AppError> {
-        self.check_duplicate_tx(tx)?;
-        self.check_frozen(tx.client_id())?;
+impl<S: BalanceStore<M>, M: Money> TxEngine<S, M> {
+    pub fn stats(&self) -> &ProcessingStats {
+        &self.stats
+    }
 
-        match tx {
-            TransactionRecord::Deposit {
-                client,
-                tx_id: _,
-                amount,
-            } => self.handle_deposit(*client, *amount)?,
+    /// Whether `client` has an account, i.e. made a successful deposit or was seeded
+    /// with `create_account`.
+    pub fn account_exists(&self, client: ClientId) -> bool {
+        self.users.get(&client).is_some()
+    }
 
-            TransactionRecord::Withdrawal {
-                client,
-                tx_id: _,
-                amount,
-            } => self.handle_withdrawal(*client, *amount)?,
+    /// Opens an empty, unlocked account for `client` ahead of its first deposit.
+    /// Returns `false` if the account already exists.
+    pub fn create_account(&mut self, client: ClientId) -> bool {
+        self.open_account(client, ClientData::init())
+    }
 
-            TransactionRecord::Dispute {
-                client,
-                disputed_tx_id,
-            } => self.handle_dispute(*client, *disputed_tx_id)?,
+    /// Opens `client`'s account with the given balances, as read back from an earlier
+    /// run's snapshot. Returns `false` if the account already exists.
+    ///
+    /// The disputes behind `held` are not known, so seeded held funds can never be
+    /// resolved or charged back, and a seeded lock names no chargeback.
+    pub fn seed_client(
+        &mut self,
+        client: ClientId,
+        available: Amount<M>,
+        held: Amount<M>,
+        locked: bool,
+    ) -> bool {
+        if self.account_exists(client) {
+            return false;
+        }
+        let mut data = ClientData::init();
+        data.balances = Balances { available, held };
+        data.opening = data.balances;
+        if locked {
+            data.lock_reason = Some(LockReason { tx_id: None });
+        }
+        data.record_peaks();
+        self.open_account(client, data)
+    }
 
-            TransactionRecord::Resolve {
-                client,
-                disputed_tx_id,
-            } => self.handle_resolve(*client, *disputed_tx_id)?,
+    /// Inserts `data` as `client`'s new account, audited with its balances as opening
+    /// deltas. Returns `false`, changing nothing, if the account already exists.
+    fn open_account(&mut self, client: ClientId, data: ClientData<M>) -> bool {
+        if self.account_exists(client) {
+            return false;
+        }
+        if self.options.record_audit {
+            self.audit_log.push(AuditEntry {
+                client_id: client,
+                op_type: None,
+                tx_id: None,
+                available_delta: data.balances.available,
+                held_delta: data.balances.held,
+                locked: data.lock_reason.is_some(),
+                reason: None,
+                manual: false,
+            });
+        }
+        self.users.insert(client, data);
+        true
+    }
 
-            TransactionRecord::Chargeback {
-                client,
-                disputed_tx_id,
-            } => self.handle_chargeback(*client, *disputed_tx_id)?,
+    /// The most recently skipped transactions with their rejections, oldest first. Only
+    /// the last `DEFAULT_RECENT_SKIPS` are kept, or as many as set with
+    /// `TxEngineBuilder::recent_skips`.
+    pub fn recent_skips(&self) -> &[SkipRecord] {
+        let capacity = self.options.recent_skips.unwrap_or(DEFAULT_RECENT_SKIPS);
+        &self.recent_skips[self.recent_skips.len().saturating_sub(capacity)..]
+    }
+
+    fn record_skip(&mut self, tx: &Transaction<M>, rejection: &Rejection) {
+        let capacity = self.options.recent_skips.unwrap_or(DEFAULT_RECENT_SKIPS);
+        if capacity == 0 {
+            return;
         }
+        self.recent_skips.push(SkipRecord {
+            op_type: tx.op_type,
+            client_id: tx.client,
+            tx_id: tx.tx_id,
+            rejection: rejection.clone(),
+        });
+        // Older skips are dropped in bulk once the buffer is twice the capacity, so a
+        // skip costs O(1) amortized rather than shifting the whole buffer each time.
+        if self.recent_skips.len() >= capacity * 2 {
+            self.recent_skips.drain(..capacity);
+        }
+    }
 
-        Ok(())
+    /// Entries recorded so far; empty unless enabled with `TxEngineBuilder::record_audit`.
+    pub fn audit_log(&self) -> &[AuditEntry<M>] {
+        &self.audit_log
     }
 
-    fn handle_deposit(&mut self, client: ClientId, amount: Amount) -> Result<(), AppError> {
-        let user = self.users.entry(client).or_insert_with(ClientData::init);
-        user.balances.available += amount;
-        Ok(())
+    pub fn totals(&self) -> Totals<M> {
+        self.users
+            .iter()
+            .fold(Totals::default(), |mut totals, (_, data)| {
+                totals.available += data.balances.available;
+                totals.held += data.balances.held;
+                if data.lock_reason.is_some() {
+                    totals.locked_accounts += 1;
+                }
+                totals
+            })
     }
 
-    fn handle_withdrawal(&mut self, client: ClientId, amount: Amount) -> Result<(), AppError> {
-        let available = self
+    pub fn clients_snapshot(&self) -> Vec<ClientSnapshot<M>> {
+        let mut snapshots: Vec<ClientSnapshot<M>> = self
             .users
-            .get(&client)
-            .map_or(Amount::ZERO, |user| user.balances.available);
-        if (available - amount) < Amount::ZERO {
-            return Err(AppError::TxProcessingNonCritical(format!(
-                "Insufficient funds for user {}: available {}, attempted withdrawal {}",
-                client, available, amount
-            )));
-        }
+            .iter()
+            .map(|(client_id, data)| Self::client_snapshot(*client_id, data))
+            .collect();
 
-        let user = self.users.entry(client).or_insert_with(ClientData::init);
-        user.balances.available -= amount;
-        Ok(())
+        snapshots.sort_by_key(|snapshot| snapshot.client_id.0);
+        snapshots
     }
 
-    fn handle_dispute(&mut self, client: ClientId, disputed_tx_id: TxID) -> Result<(), AppError> {
-        let user = match self.users.get_mut(&client) {
-            Some(user) => user,
-            None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot dispute transaction {} for user {}, client not found",
-                    disputed_tx_id, client
-                )));
-            }
-        };
-
-        if user.disputed_txs.contains_key(&disputed_tx_id) {
-            return Err(AppError::TxProcessingNonCritical(format!(
-                "Transaction {} for user {} is already disputed",
-                disputed_tx_id, client
-            )));
-        }
+    /// Consumes the engine and returns the final snapshots, sorted by client. For
+    /// pipelines that are done processing and only transform the result.
+    pub fn into_snapshots(self) -> Vec<ClientSnapshot<M>> {
+        self.clients_snapshot()
+    }
 
-        let disputed_tx = match user.txs.get(&disputed_tx_id) {
-            Some(tx) => tx,
-            None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Disputed transaction {} not found for user {}",
-                    disputed_tx_id, client
-                )));
-            }
+    /// `client`'s deposit, withdrawal or correction `tx` followed by every dispute,
+    /// resolve and chargeback applied to it, in the order they were processed. Empty if
+    /// `client` has no such transaction. Takes the client because under
+    /// `DedupScope::PerClient` several clients may own the same tx id.
+    pub fn transaction_history(&self, client: ClientId, tx: TxID) -> Vec<HistoryEntry<M>> {
+        let Some(user) = self.users.get(&client) else {
+            return Vec::new();
         };
+        let events = user.lifecycle_events.get(&tx).into_iter().flatten();
+        user.txs
+            .get(&tx)
+            .into_iter()
+            .chain(events)
+            .map(HistoryEntry::from)
+            .collect()
+    }
 
-        let balance_diff = match disputed_tx {
-            TransactionRecord::Deposit { amount, .. } => *amount,
+    /// The highest available balance and the highest total `client` has had after any
+    /// applied transaction, which may both be above the current ones. `None` if the
+    /// client is unknown.
+    pub fn client_peaks(&self, client: ClientId) -> Option<(Amount<M>, Amount<M>)> {
+        self.users
+            .get(&client)
+            .map(|user| (user.peak_available, user.peak_total))
+    }
 
-            TransactionRecord::Withdrawal { .. }
-            | TransactionRecord::Dispute { .. }
+    /// Open disputes that make up each client's held amount, sorted by client and tx id.
+    pub fn held_breakdown(&self) -> Vec<HeldEntry<M>> {
+        let mut entries: Vec<HeldEntry<M>> = self
+            .users
+            .iter()
+            .flat_map(|(client_id, data)| {
+                data.disputed_txs
+                    .iter()
+                    .map(|(tx_id, disputed_funds)| HeldEntry {
+                        client_id: *client_id,
+                        tx_id: *tx_id,
+                        held: disputed_funds.on_dispute().1,
+                        reason: data.dispute_reasons.get(tx_id).cloned(),
+                    })
+            })
+            .collect();
+
+        entries.sort_by_key(|entry| (entry.client_id.0, entry.tx_id));
+        entries
+    }
+
+    fn client_snapshot(client_id: ClientId, data: &ClientData<M>) -> ClientSnapshot<M> {
+        ClientSnapshot {
+            client_id,
+            available: data.balances.available,
+            held: data.balances.held,
+            parked: data.parked,
+            locked: data.lock_reason.is_some(),
+            lock_reason: data.lock_reason,
+        }
+    }
+
+    /// Returns the snapshot the transaction's client would have if `tx` were applied,
+    /// without mutating the engine. Only the affected client's data is cloned.
+    pub fn simulate(&self, tx: &Transaction<M>) -> Result<ClientSnapshot<M>, ProcessError> {
+        let record = self.to_transaction_record(tx)?;
+        let is_ignored_duplicate = self.check_duplicate_tx(&record)?;
+
+        let client = *record.client_id();
+        let mut scratch = TxEngineBuilder {
+            options: self.options.clone(),
+        }
+        .build_with_store(HashMap::<ClientId, ClientData<M>>::new());
+        if let Some(user) = self.users.get(&client) {
+            scratch.users.insert(client, user.clone());
+        }
+
+        if !is_ignored_duplicate {
+            scratch.process_transaction_internal(&record)?;
+        }
+
+        scratch
+            .users
+            .get(&client)
+            .map(|data| Self::client_snapshot(client, data))
+            .ok_or_else(|| {
+                ProcessError::rejected(
+                    RejectionCategory::UnknownClient,
+                    format!("Client {} not found", client),
+                )
+            })
+    }
+
+    pub fn process_transaction(&mut self, tx: &Transaction<M>) -> Result<(), ProcessError> {
+        let result = self.process_transaction_guarded(tx);
+        if let Err(ProcessError::Rejected(rejection)) = &result {
+            self.record_skip(tx, rejection);
+        }
+        result
+    }
+
+    fn process_transaction_guarded(&mut self, tx: &Transaction<M>) -> Result<(), ProcessError> {
+        if !self.options.panic_safe {
+            return self.process_transaction_unguarded(tx);
+        }
+        panic::catch_unwind(AssertUnwindSafe(|| self.process_transaction_unguarded(tx)))
+            .unwrap_or_else(|payload| {
+                // The panic may have left part of `tx` applied on top of the last entry.
+                self.last_applied = None;
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                Err(ProcessError::Critical(format!(
+                    "Panic while processing transaction {}: {}",
+                    tx.tx_id, message
+                )))
+            })
+    }
+
+    fn process_transaction_unguarded(&mut self, tx: &Transaction<M>) -> Result<(), ProcessError> {
+        self.check_identical_row(tx)?;
+        let record = self.to_transaction_record(tx)?;
+        if self.check_duplicate_tx(&record)? {
+            return Ok(());
+        }
+        let before = self.users.get(record.client_id()).map(|user| user.balances);
+        let undo = self.undo_entry(tx, &record);
+        self.process_transaction_internal(&record)?;
+        if self.options.record_audit {
+            self.audit_transaction(&record, before.unwrap_or_else(Balances::init))?;
+        }
+        self.record_processed_transaction(record);
+        self.last_applied = Some(undo);
+        Ok(())
+    }
+
+    fn undo_entry(&self, tx: &Transaction<M>, record: &TransactionRecord<M>) -> UndoEntry<M> {
+        let tx_id = record.tx_id();
+        let client = self.users.get(record.client_id()).map(|user| ClientBefore {
+            balances: user.balances,
+            parked: user.parked,
+            lock_reason: user.lock_reason,
+            peaks: (user.peak_available, user.peak_total),
+            disputed: user.disputed_txs.get(&tx_id).copied(),
+            dispute_position: user.dispute_order.iter().position(|open| *open == tx_id),
+            dispute_portions: user.dispute_portions.get(&tx_id).cloned(),
+            dispute_reason: user.dispute_reasons.get(&tx_id).cloned(),
+            charged_back: user.charged_back_txs.get(&tx_id).copied(),
+        });
+        UndoEntry {
+            record: record.clone(),
+            row: row_key(tx),
+            stats: self.stats,
+            audit_len: self.audit_log.len(),
+            client,
+        }
+    }
+
+    /// Reverts the most recently applied transaction, so balances, locks, open
+    /// disputes, history and stats are as they were before it. A deposit that opened an
+    /// account removes the account again, and undoing a chargeback unlocks the account
+    /// it locked. Only one level is kept: after an undo, or `resolve_all_disputes`,
+    /// there is nothing to undo until the next transaction is applied. Rejected and
+    /// ignored duplicate transactions are not undoable and do not replace the entry.
+    /// Under `dedup_identical`, the undone row no longer counts as seen.
+    pub fn undo_last(&mut self) -> Result<(), AppError> {
+        let Some(undo) = self.last_applied.take() else {
+            return Err(AppError::TxProcessing(
+                "No applied transaction to undo".to_string(),
+            ));
+        };
+        let client = *undo.record.client_id();
+        let tx_id = undo.record.tx_id();
+
+        match undo.client {
+            None => {
+                self.users.remove(&client);
+            }
+            Some(before) => {
+                let user = self
+                    .users
+                    .get_mut(&client)
+                    .expect("accounts are only removed by undo_last");
+                user.balances = before.balances;
+                user.parked = before.parked;
+                user.lock_reason = before.lock_reason;
+                (user.peak_available, user.peak_total) = before.peaks;
+                restore(&mut user.disputed_txs, tx_id, before.disputed);
+                user.dispute_order.retain(|open| *open != tx_id);
+                if let Some(position) = before.dispute_position {
+                    user.dispute_order.insert(position, tx_id);
+                }
+                restore(&mut user.dispute_portions, tx_id, before.dispute_portions);
+                restore(&mut user.dispute_reasons, tx_id, before.dispute_reason);
+                restore(&mut user.charged_back_txs, tx_id, before.charged_back);
+                match undo.record {
+                    TransactionRecord::Deposit { .. }
+                    | TransactionRecord::Withdrawal { .. }
+                    | TransactionRecord::Correction { .. } => {
+                        user.txs.remove(&tx_id);
+                    }
+                    TransactionRecord::Dispute { .. }
+                    | TransactionRecord::Resolve { .. }
+                    | TransactionRecord::Chargeback { .. } => {
+                        if let Some(events) = user.lifecycle_events.get_mut(&tx_id) {
+                            events.pop();
+                            if events.is_empty() {
+                                user.lifecycle_events.remove(&tx_id);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if matches!(
+            undo.record,
+            TransactionRecord::Deposit { .. }
+                | TransactionRecord::Withdrawal { .. }
+                | TransactionRecord::Correction { .. }
+        ) {
+            self.processed_tx_ids.remove(&tx_id);
+        }
+        self.stats = undo.stats;
+        self.audit_log.truncate(undo.audit_len);
+        self.seen_rows.remove(&undo.row);
+        Ok(())
+    }
+
+    /// Resolves every open dispute of `client`, oldest first, releasing held funds back
+    /// to available. Returns the number of disputes resolved.
+    pub fn resolve_all_disputes(&mut self, client: ClientId) -> Result<usize, ProcessError> {
+        self.check_frozen(&client)?;
+        self.last_applied = None;
+
+        let user = match self.users.get_mut(&client) {
+            Some(user) => user,
+            None => return Ok(0),
+        };
+
+        let mut new_balances = user.balances;
+        for tx_id in &user.dispute_order {
+            new_balances = checked_balance(
+                new_balances.checked_apply(user.disputed_txs[tx_id].on_resolve()),
+                client,
+            )?;
+        }
+
+        if self.options.record_audit {
+            for tx_id in &user.dispute_order {
+                let (available_delta, held_delta) = user.disputed_txs[tx_id].on_resolve();
+                self.audit_log.push(AuditEntry {
+                    client_id: client,
+                    op_type: Some(TransactionType::Resolve),
+                    tx_id: Some(*tx_id),
+                    available_delta,
+                    held_delta,
+                    locked: user.lock_reason.is_some(),
+                    reason: None,
+                    manual: false,
+                });
+            }
+        }
+
+        for tx_id in &user.dispute_order {
+            user.lifecycle_events
+                .entry(*tx_id)
+                .or_default()
+                .push(TransactionRecord::Resolve {
+                    client,
+                    disputed_tx_id: *tx_id,
+                    amount: None,
+                    reason: None,
+                });
+        }
+
+        let resolved = user.disputed_txs.len();
+        user.balances = new_balances;
+        user.disputed_txs.clear();
+        user.dispute_order.clear();
+        user.dispute_portions.clear();
+        user.dispute_reasons.clear();
+        self.stats.resolves += resolved as u64;
+        self.apply_available_ceiling(client)?;
+        self.record_peaks(client);
+        Ok(resolved)
+    }
+
+    /// Total deposits minus total withdrawals for `client`, ignoring disputes, holds,
+    /// chargebacks and corrections. `None` if the client is unknown.
+    pub fn net_flow(&self, client: ClientId) -> Option<Amount<M>> {
+        let user = self.users.get(&client)?;
+        let net = user.txs.values().fold(Amount::ZERO, |net, tx| match tx {
+            TransactionRecord::Deposit { amount, .. } => net + *amount,
+            TransactionRecord::Withdrawal { amount, .. } => net - *amount,
+            TransactionRecord::Dispute { .. }
             | TransactionRecord::Resolve { .. }
-            | TransactionRecord::Chargeback { .. } => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot dispute transaction {} for user {}, not a deposit",
-                    disputed_tx_id, client
-                )));
+            | TransactionRecord::Chargeback { .. }
+            | TransactionRecord::Correction { .. } => net,
+        });
+        Some(net)
+    }
+
+    /// Recomputes the client's total from its seeded balances and recorded deposits,
+    /// withdrawals and corrections, excluding charged-back amounts and withdrawn amounts
+    /// held by an open dispute, and compares it with `available + held`.
+    pub fn verify_client(&self, client: ClientId) -> Result<(), String> {
+        let user = self
+            .users
+            .get(&client)
+            .ok_or_else(|| format!("Client {} not found", client))?;
+
+        let charged_back = |tx_id: &TxID| {
+            user.charged_back_txs
+                .get(tx_id)
+                .copied()
+                .unwrap_or(Amount::ZERO)
+        };
+        let overflow = || format!("Balance overflow while verifying client {}", client);
+        let opening = user
+            .opening
+            .available
+            .checked_add(user.opening.held)
+            .ok_or_else(overflow)?;
+        let expected = user
+            .txs
+            .values()
+            .try_fold(opening, |expected, tx| match tx {
+                TransactionRecord::Deposit { tx_id, amount, .. } => expected
+                    .checked_add(*amount)?
+                    .checked_sub(charged_back(tx_id)),
+                TransactionRecord::Withdrawal { tx_id, amount, .. } => {
+                    let held = user
+                        .disputed_txs
+                        .get(tx_id)
+                        .map_or(Amount::ZERO, |funds| funds.amount());
+                    expected
+                        .checked_sub(*amount)?
+                        .checked_add(held)?
+                        .checked_add(charged_back(tx_id))
+                }
+                TransactionRecord::Correction { amount, .. } => expected.checked_add(*amount),
+                TransactionRecord::Dispute { .. }
+                | TransactionRecord::Resolve { .. }
+                | TransactionRecord::Chargeback { .. } => Some(expected),
+            });
+        let expected = expected.ok_or_else(overflow)?;
+        let actual = user
+            .balances
+            .available
+            .checked_add(user.balances.held)
+            .ok_or_else(overflow)?;
+
+        if !expected.eq_value(actual) {
+            return Err(format!(
+                "Balance drift for client {}: expected total {}, actual total {}",
+                client, expected, actual
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs every consistency check over all clients and returns the violations found:
+    /// `verify_client`, and non-negative held and parked amounts with held equal to the
+    /// open disputes plus parked and seeded held funds. Clients are checked in id order.
+    pub fn check_invariants(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        let mut clients: Vec<_> = self.users.iter().collect();
+        clients.sort_by_key(|(client_id, _)| client_id.0);
+        for (client_id, data) in clients {
+            if let Err(err) = self.verify_client(*client_id) {
+                violations.push(err);
+            }
+            if data.parked < Amount::ZERO {
+                violations.push(format!(
+                    "Negative parked amount {} for client {}",
+                    data.parked, client_id
+                ));
+            }
+            if data.balances.held < Amount::ZERO {
+                violations.push(format!(
+                    "Negative held amount {} for client {}",
+                    data.balances.held, client_id
+                ));
+            }
+            let expected_held = data
+                .parked
+                .checked_add(data.opening.held)
+                .and_then(|start| {
+                    data.disputed_txs
+                        .values()
+                        .try_fold(start, |sum, funds| sum.checked_add(funds.amount()))
+                });
+            let Some(expected_held) = expected_held else {
+                violations.push(format!(
+                    "Held amount overflow while checking client {}",
+                    client_id
+                ));
+                continue;
+            };
+            if !expected_held.eq_value(data.balances.held) {
+                violations.push(format!(
+                    "Held amount {} for client {} does not match open disputes and parked funds totalling {}",
+                    data.balances.held, client_id, expected_held
+                ));
+            }
+        }
+        violations
+    }
+
+    fn audit_transaction(
+        &mut self,
+        tx: &TransactionRecord<M>,
+        before: Balances<M>,
+    ) -> Result<(), ProcessError> {
+        let (op_type, client, tx_id, reason) = match tx {
+            TransactionRecord::Deposit { client, tx_id, .. } => {
+                (TransactionType::Deposit, *client, *tx_id, None)
+            }
+            TransactionRecord::Correction {
+                client,
+                tx_id,
+                reason,
+                ..
+            } => (
+                TransactionType::Correction,
+                *client,
+                *tx_id,
+                Some(reason.clone()),
+            ),
+            TransactionRecord::Withdrawal { client, tx_id, .. } => {
+                (TransactionType::Withdrawal, *client, *tx_id, None)
+            }
+            TransactionRecord::Dispute {
+                client,
+                disputed_tx_id,
+                reason,
+                ..
+            } => (
+                TransactionType::Dispute,
+                *client,
+                *disputed_tx_id,
+                reason.clone(),
+            ),
+            TransactionRecord::Resolve {
+                client,
+                disputed_tx_id,
+                reason,
+                ..
+            } => (
+                TransactionType::Resolve,
+                *client,
+                *disputed_tx_id,
+                reason.clone(),
+            ),
+            TransactionRecord::Chargeback {
+                client,
+                disputed_tx_id,
+                reason,
+                ..
+            } => (
+                TransactionType::Chargeback,
+                *client,
+                *disputed_tx_id,
+                reason.clone(),
+            ),
+        };
+        let Some(user) = self.users.get(&client) else {
+            return Ok(());
+        };
+
+        let entry = AuditEntry {
+            client_id: client,
+            op_type: Some(op_type),
+            tx_id: Some(tx_id),
+            available_delta: checked_balance(
+                user.balances.available.checked_sub(before.available),
+                client,
+            )?,
+            held_delta: checked_balance(user.balances.held.checked_sub(before.held), client)?,
+            locked: user.lock_reason.is_some(),
+            reason,
+            manual: op_type == TransactionType::Correction,
+        };
+        self.audit_log.push(entry);
+        Ok(())
+    }
+
+    /// Checks run in a fixed order: charged-back transaction, frozen account, then the
+    /// per-operation checks. A frozen account always exists, so a withdrawal on one
+    /// reports `FrozenAccount`, while a withdrawal on a never-seen client reports
+    /// `InsufficientFunds`, as it has nothing available, or `UnknownClient` with
+    /// `TxEngineBuilder::reject_withdrawals_for_unknown_clients`.
+    fn process_transaction_internal(
+        &mut self,
+        tx: &TransactionRecord<M>,
+    ) -> Result<(), ProcessError> {
+        self.check_charged_back(tx)?;
+        self.check_frozen(tx.client_id())?;
+
+        match tx {
+            TransactionRecord::Deposit {
+                client,
+                tx_id: _,
+                amount,
+            } => self.handle_deposit(*client, *amount)?,
+
+            TransactionRecord::Withdrawal {
+                client,
+                tx_id: _,
+                amount,
+            } => self.handle_withdrawal(*client, *amount)?,
+
+            TransactionRecord::Dispute {
+                client,
+                disputed_tx_id,
+                amount,
+                reason,
+            } => self.handle_dispute(*client, *disputed_tx_id, *amount, reason.as_deref())?,
+
+            TransactionRecord::Resolve {
+                client,
+                disputed_tx_id,
+                amount,
+                ..
+            } => self.handle_resolve(*client, *disputed_tx_id, *amount)?,
+
+            TransactionRecord::Chargeback {
+                client,
+                disputed_tx_id,
+                amount,
+                ..
+            } => self.handle_chargeback(*client, *disputed_tx_id, *amount)?,
+
+            TransactionRecord::Correction { client, amount, .. } => {
+                self.handle_correction(*client, *amount)?
             }
+        }
+
+        self.apply_available_ceiling(*tx.client_id())?;
+        self.record_peaks(*tx.client_id());
+        Ok(())
+    }
+
+    fn record_peaks(&mut self, client: ClientId) {
+        if let Some(user) = self.users.get_mut(&client) {
+            user.record_peaks();
+        }
+    }
+
+    /// Parks available funds above the ceiling in held, or releases parked funds back
+    /// to available up to it.
+    fn apply_available_ceiling(&mut self, client: ClientId) -> Result<(), ProcessError> {
+        let Some(ceiling) = self.options.available_ceiling else {
+            return Ok(());
+        };
+        let Some(user) = self.users.get_mut(&client) else {
+            return Ok(());
+        };
+
+        let available = user.balances.available;
+        let to_park = if available > ceiling {
+            checked_balance(available.checked_sub(ceiling), client)?
+        } else {
+            let room = checked_balance(ceiling.checked_sub(available), client)?;
+            -room.min(user.parked)
+        };
+        if to_park.is_zero() {
+            return Ok(());
+        }
+        user.balances = checked_balance(user.balances.checked_apply((-to_park, to_park)), client)?;
+        user.parked = checked_balance(user.parked.checked_add(to_park), client)?;
+        Ok(())
+    }
+
+    /// The only place, besides `create_account` and `seed_client`, where accounts are
+    /// created: the amount is checked first so a rejected deposit leaves no empty account
+    /// behind. Unlike those, the opening is audited as part of the deposit.
+    fn handle_deposit(&mut self, client: ClientId, amount: Amount<M>) -> Result<(), ProcessError> {
+        let available = self
+            .users
+            .get(&client)
+            .map_or(Amount::ZERO, |user| user.balances.available);
+        let new_available = checked_balance(available.checked_add(amount), client)?;
+
+        let user = self.users.get_or_insert_with(client, ClientData::init);
+        user.balances.available = new_available;
+        Ok(())
+    }
+
+    fn handle_withdrawal(
+        &mut self,
+        client: ClientId,
+        amount: Amount<M>,
+    ) -> Result<(), ProcessError> {
+        if self.options.reject_unknown_client_withdrawals && !self.account_exists(client) {
+            return Err(ProcessError::rejected(
+                RejectionCategory::UnknownClient,
+                format!(
+                    "Cannot withdraw {} for user {}, client not found",
+                    amount, client
+                ),
+            ));
+        }
+
+        // A never-seen client has nothing available, so the funds check below rejects
+        // the withdrawal before an account would be needed.
+        let available = self
+            .users
+            .get(&client)
+            .map_or(Amount::ZERO, |user| user.balances.available);
+        let new_available = checked_balance(available.checked_sub(amount), client)?;
+        let insufficient_funds = || {
+            ProcessError::rejected(
+                RejectionCategory::InsufficientFunds,
+                format!(
+                    "Insufficient funds for user {}: available {}, attempted withdrawal {}",
+                    client, available, amount
+                ),
+            )
         };
+        if new_available < Amount::ZERO && self.options.system_account != Some(client) {
+            return Err(insufficient_funds());
+        }
+
+        let user = self.users.get_mut(&client).ok_or_else(insufficient_funds)?;
+        user.balances.available = new_available;
+        Ok(())
+    }
 
-        user.balances.available -= balance_diff;
-        user.balances.held += balance_diff;
-        user.disputed_txs.insert(disputed_tx_id, balance_diff);
+    /// Applies a correction to available as is: a negative one may take it below zero,
+    /// and the frozen check has already run.
+    fn handle_correction(
+        &mut self,
+        client: ClientId,
+        amount: Amount<M>,
+    ) -> Result<(), ProcessError> {
+        let user = self.users.get_mut(&client).ok_or_else(|| {
+            ProcessError::rejected(
+                RejectionCategory::UnknownClient,
+                format!(
+                    "Cannot correct balance by {} for user {}, client not found",
+                    amount, client
+                ),
+            )
+        })?;
+        user.balances.available =
+            checked_balance(user.balances.available.checked_add(amount), client)?;
         Ok(())
     }
 
-    fn handle_resolve(&mut self, client: ClientId, disputed_tx_id: TxID) -> Result<(), AppError> {
+    fn handle_dispute(
+        &mut self,
+        client: ClientId,
+        disputed_tx_id: TxID,
+        partial_amount: Option<Amount<M>>,
+        reason: Option<&str>,
+    ) -> Result<(), ProcessError> {
         let user = match self.users.get_mut(&client) {
             Some(user) => user,
             None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot resolve disputed transaction {} for user {}, client not found",
+                return Err(ProcessError::rejected(
+                    RejectionCategory::UnknownClient,
+                    format!(
+                        "Cannot dispute transaction {} for user {}, client not found",
+                        disputed_tx_id, client
+                    ),
+                ));
+            }
+        };
+
+        let open_dispute = user.disputed_txs.get(&disputed_tx_id).copied();
+        if open_dispute.is_some() && partial_amount.is_none() {
+            return Err(ProcessError::rejected(
+                RejectionCategory::InvalidDispute,
+                format!(
+                    "Transaction {} for user {} is already disputed",
                     disputed_tx_id, client
+                ),
+            ));
+        }
+
+        if let (Some(max_open_disputes), None) = (self.options.max_open_disputes, open_dispute) {
+            if user.disputed_txs.len() >= max_open_disputes {
+                return Err(ProcessError::rejected(
+                    RejectionCategory::DisputeLimit,
+                    format!(
+                        "Cannot dispute transaction {} for user {}, {} disputes already open",
+                        disputed_tx_id,
+                        client,
+                        user.disputed_txs.len()
+                    ),
+                ));
+            }
+        }
+
+        let disputed_tx = match user.txs.get(&disputed_tx_id) {
+            Some(tx) => tx,
+            None => {
+                return Err(ProcessError::rejected(
+                    RejectionCategory::UnknownTx,
+                    format!(
+                        "Disputed transaction {} not found for user {}",
+                        disputed_tx_id, client
+                    ),
+                ));
+            }
+        };
+
+        let full_funds = match disputed_tx {
+            TransactionRecord::Deposit { amount, .. } => DisputedFunds::Deposit(*amount),
+
+            TransactionRecord::Withdrawal { amount, .. }
+                if self.options.allow_withdrawal_disputes =>
+            {
+                DisputedFunds::Withdrawal(*amount)
+            }
+
+            TransactionRecord::Withdrawal { .. }
+            | TransactionRecord::Dispute { .. }
+            | TransactionRecord::Resolve { .. }
+            | TransactionRecord::Chargeback { .. }
+            | TransactionRecord::Correction { .. } => {
+                return Err(ProcessError::rejected(
+                    RejectionCategory::InvalidDispute,
+                    format!(
+                        "Cannot dispute transaction {} for user {}, not a deposit",
+                        disputed_tx_id, client
+                    ),
+                ));
+            }
+        };
+
+        let already_held = open_dispute.map_or(Amount::ZERO, |funds| funds.amount());
+        let added = full_funds.with_amount(partial_amount.unwrap_or(full_funds.amount()));
+        let total_held = checked_balance(already_held.checked_add(added.amount()), client)?;
+        if total_held > full_funds.amount() {
+            return Err(ProcessError::rejected(
+                RejectionCategory::InvalidDispute,
+                format!(
+                    "Cannot dispute {} of transaction {} for user {}, {} of {} already disputed",
+                    added.amount(),
+                    disputed_tx_id,
+                    client,
+                    already_held,
+                    full_funds.amount()
+                ),
+            ));
+        }
+
+        let new_balances =
+            checked_balance(user.balances.checked_apply(added.on_dispute()), client)?;
+
+        if cfg!(debug_assertions) || self.options.verify_holds {
+            // The dispute being opened is not in `disputed_txs` yet.
+            let max_held = match added {
+                DisputedFunds::Deposit(_) => user.max_held(),
+                DisputedFunds::Withdrawal(amount) => {
+                    user.max_held().and_then(|max| max.checked_add(amount))
+                }
+            };
+            let max_held = checked_balance(max_held, client)?;
+            if new_balances.held > max_held {
+                return Err(ProcessError::Critical(format!(
+                    "Dispute of transaction {} for user {} would hold {}, more than the {} deposited",
+                    disputed_tx_id, client, new_balances.held, max_held
                 )));
             }
+        }
+
+        user.balances = new_balances;
+        if open_dispute.is_none() {
+            user.dispute_order.push(disputed_tx_id);
+        }
+        user.disputed_txs
+            .insert(disputed_tx_id, full_funds.with_amount(total_held));
+        user.dispute_portions
+            .entry(disputed_tx_id)
+            .or_default()
+            .push(added.amount());
+        if let Some(reason) = reason {
+            user.dispute_reasons
+                .insert(disputed_tx_id, reason.to_owned());
+        }
+        Ok(())
+    }
+
+    fn handle_resolve(
+        &mut self,
+        client: ClientId,
+        disputed_tx_id: TxID,
+        given_amount: Option<Amount<M>>,
+    ) -> Result<(), ProcessError> {
+        let user = match self.users.get_mut(&client) {
+            Some(user) => user,
+            None => {
+                return Err(ProcessError::rejected(
+                    RejectionCategory::UnknownClient,
+                    format!(
+                        "Cannot resolve disputed transaction {} for user {}, client not found",
+                        disputed_tx_id, client
+                    ),
+                ));
+            }
         };
 
-        let disputed_tx_diff = match user.disputed_txs.get(&disputed_tx_id) {
-            Some(amount) => amount,
+        let disputed_funds = match user.disputed_txs.get(&disputed_tx_id) {
+            Some(funds) => *funds,
             None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot resolve disputed transaction {} for user {}, not in dispute",
-                    disputed_tx_id, client
-                )));
+                return Err(ProcessError::rejected(
+                    RejectionCategory::InvalidDispute,
+                    format!(
+                        "Cannot resolve disputed transaction {} for user {}, not in dispute",
+                        disputed_tx_id, client
+                    ),
+                ));
             }
         };
 
-        user.balances.available += *disputed_tx_diff;
-        user.balances.held -= *disputed_tx_diff;
-        user.disputed_txs.remove(&disputed_tx_id);
+        let portions = user.dispute_portions.entry(disputed_tx_id).or_default();
+        let one_portion = self.options.resolve_scope == ResolveScope::Dispute && portions.len() > 1;
+        let released = if one_portion {
+            portions[0]
+        } else {
+            disputed_funds.amount()
+        };
+        Self::check_lifecycle_amount("resolve", client, disputed_tx_id, given_amount, released)?;
+        if one_portion {
+            let released = disputed_funds.with_amount(portions[0]);
+            let new_balances =
+                checked_balance(user.balances.checked_apply(released.on_resolve()), client)?;
+            let still_held = checked_balance(
+                disputed_funds.amount().checked_sub(released.amount()),
+                client,
+            )?;
+
+            user.balances = new_balances;
+            portions.remove(0);
+            user.disputed_txs
+                .insert(disputed_tx_id, disputed_funds.with_amount(still_held));
+            return Ok(());
+        }
+
+        let new_balances = checked_balance(
+            user.balances.checked_apply(disputed_funds.on_resolve()),
+            client,
+        )?;
+
+        user.balances = new_balances;
+        user.close_dispute(disputed_tx_id);
         Ok(())
     }
 
@@ -275,70 +1770,214 @@ impl TxEngine {
         &mut self,
         client: ClientId,
         disputed_tx_id: TxID,
-    ) -> Result<(), AppError> {
+        given_amount: Option<Amount<M>>,
+    ) -> Result<(), ProcessError> {
         let user = match self.users.get_mut(&client) {
             Some(user) => user,
             None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot chargeback disputed transaction {} for user {}, client not found",
-                    disputed_tx_id, client
-                )));
+                return Err(ProcessError::rejected(
+                    RejectionCategory::UnknownClient,
+                    format!(
+                        "Cannot chargeback disputed transaction {} for user {}, client not found",
+                        disputed_tx_id, client
+                    ),
+                ));
             }
         };
 
-        let disputed_tx_diff = match user.disputed_txs.get(&disputed_tx_id) {
-            Some(amount) => amount,
+        let disputed_funds = match user.disputed_txs.get(&disputed_tx_id) {
+            Some(funds) => *funds,
             None => {
-                return Err(AppError::TxProcessingNonCritical(format!(
-                    "Cannot chargeback disputed transaction {} for user {}, not in dispute",
-                    disputed_tx_id, client
-                )));
+                return Err(ProcessError::rejected(
+                    RejectionCategory::InvalidDispute,
+                    format!(
+                        "Cannot chargeback disputed transaction {} for user {}, not in dispute",
+                        disputed_tx_id, client
+                    ),
+                ));
             }
         };
 
-        user.balances.held -= *disputed_tx_diff;
-        user.disputed_txs.remove(&disputed_tx_id);
-        user.frozen = true;
+        Self::check_lifecycle_amount(
+            "chargeback",
+            client,
+            disputed_tx_id,
+            given_amount,
+            disputed_funds.amount(),
+        )?;
+        let change = match self.options.chargeback_policy {
+            ChargebackPolicy::RemoveFunds => disputed_funds.on_chargeback(),
+            ChargebackPolicy::ReturnToAvailable => disputed_funds.on_resolve(),
+        };
+        let new_balances = checked_balance(user.balances.checked_apply(change), client)?;
+
+        user.balances = new_balances;
+        user.close_dispute(disputed_tx_id);
+        if self.options.chargeback_policy == ChargebackPolicy::RemoveFunds {
+            user.charged_back_txs
+                .insert(disputed_tx_id, disputed_funds.amount());
+            if self.options.system_account != Some(client) {
+                user.lock_reason = Some(LockReason {
+                    tx_id: Some(disputed_tx_id),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects `tx` if it repeats an earlier row under `dedup_identical`, and otherwise
+    /// remembers it for the rows that follow.
+    fn check_identical_row(&mut self, tx: &Transaction<M>) -> Result<(), ProcessError> {
+        let Some(scope) = self.options.dedup_identical else {
+            return Ok(());
+        };
+        let row = row_key(tx);
+        if self.seen_rows.contains(&row) {
+            return Err(ProcessError::rejected(
+                RejectionCategory::DuplicateRow,
+                format!(
+                    "Row identical to a previous row ({},{},{})",
+                    tx.op_type, tx.client, tx.tx_id
+                ),
+            ));
+        }
+        if scope == IdenticalRowScope::Previous {
+            self.seen_rows.clear();
+        }
+        self.seen_rows.insert(row);
         Ok(())
     }
 
-    fn check_duplicate_tx(&self, tx: &TransactionRecord) -> Result<(), AppError> {
+    /// Applies the duplicate tx policy. Returns `true` when the transaction is a
+    /// duplicate that must be ignored as a successful no-op.
+    fn check_duplicate_tx(&self, tx: &TransactionRecord<M>) -> Result<bool, ProcessError> {
         match tx {
-            TransactionRecord::Deposit { tx_id, .. }
-            | TransactionRecord::Withdrawal { tx_id, .. } => {
-                if self.processed_tx_ids.contains(tx_id) {
-                    return Err(AppError::TxProcessingNonCritical(format!(
+            TransactionRecord::Deposit { client, tx_id, .. }
+            | TransactionRecord::Withdrawal { client, tx_id, .. }
+            | TransactionRecord::Correction { client, tx_id, .. } => {
+                let processed = match self.options.dedup_scope {
+                    DedupScope::Global => self.processed_tx_ids.contains(tx_id),
+                    DedupScope::PerClient => self
+                        .users
+                        .get(client)
+                        .is_some_and(|user| user.txs.contains_key(tx_id)),
+                };
+                if !processed {
+                    return Ok(false);
+                }
+                match self.options.duplicate_tx_policy {
+                    DuplicateTxPolicy::Reject => Err(ProcessError::rejected(
+                        RejectionCategory::DuplicateTx,
+                        format!("Duplicate transaction ID {}", tx_id),
+                    )),
+                    DuplicateTxPolicy::IgnoreSecond => {
+                        let original = self.users.get(client).and_then(|user| user.txs.get(tx_id));
+                        if original == Some(tx) {
+                            Ok(true)
+                        } else {
+                            Err(ProcessError::rejected(
+                                RejectionCategory::DuplicateTx,
+                                format!(
+                                    "Duplicate transaction ID {} with conflicting amount",
+                                    tx_id
+                                ),
+                            ))
+                        }
+                    }
+                    DuplicateTxPolicy::Error => Err(ProcessError::Critical(format!(
                         "Duplicate transaction ID {}",
                         tx_id
-                    )));
+                    ))),
                 }
-                Ok(())
             }
             TransactionRecord::Dispute { .. }
             | TransactionRecord::Resolve { .. }
-            | TransactionRecord::Chargeback { .. } => Ok(()),
-        }
-    }
-
-    fn check_frozen(&self, client: &ClientId) -> Result<(), AppError> {
-        if self.users.get(client).is_some_and(|user| user.frozen) {
-            return Err(AppError::TxProcessingNonCritical(format!(
-                "Account {} is frozen",
-                client
-            )));
+            | TransactionRecord::Chargeback { .. } => Ok(false),
         }
-        Ok(())
     }
 
-    fn to_transaction_record(tx: &Transaction) -> Result<TransactionRecord, AppError> {
-        match tx.op_type {
+    /// Rejects any dispute, resolve or chargeback of an already charged-back transaction,
+    /// ahead of the generic frozen-account rejection it would otherwise hit. A chargeback
+    /// is final, so this holds even if the account is no longer locked.
+    fn check_charged_back(&self, tx: &TransactionRecord<M>) -> Result<(), ProcessError> {
+        let (action, client, disputed_tx_id) = match tx {
+            TransactionRecord::Dispute {
+                client,
+                disputed_tx_id,
+                ..
+            } => ("dispute", client, disputed_tx_id),
+            TransactionRecord::Resolve {
+                client,
+                disputed_tx_id,
+                ..
+            } => ("resolve", client, disputed_tx_id),
+            TransactionRecord::Chargeback {
+                client,
+                disputed_tx_id,
+                ..
+            } => ("chargeback", client, disputed_tx_id),
+            TransactionRecord::Deposit { .. }
+            | TransactionRecord::Withdrawal { .. }
+            | TransactionRecord::Correction { .. } => return Ok(()),
+        };
+
+        if self
+            .users
+            .get(client)
+            .is_some_and(|user| user.charged_back_txs.contains_key(disputed_tx_id))
+        {
+            return Err(ProcessError::rejected(
+                RejectionCategory::InvalidDispute,
+                format!(
+                    "Cannot {} transaction {} for user {}: already charged back",
+                    action, disputed_tx_id, client
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_frozen(&self, client: &ClientId) -> Result<(), ProcessError> {
+        if self
+            .users
+            .get(client)
+            .is_some_and(|user| user.lock_reason.is_some())
+        {
+            return Err(ProcessError::rejected(
+                RejectionCategory::FrozenAccount,
+                format!("Account {} is frozen", client),
+            ));
+        }
+        Ok(())
+    }
+
+    fn to_transaction_record(
+        &self,
+        tx: &Transaction<M>,
+    ) -> Result<TransactionRecord<M>, ProcessError> {
+        let amount = match self.options.amount_scale {
+            Some(scale) => tx.amount.map(|amount| amount.truncate_to(scale)),
+            None => tx.amount,
+        };
+        match tx.op_type {
             TransactionType::Deposit => {
-                let amount = tx.amount.ok_or_else(|| {
-                    AppError::TxProcessingNonCritical(format!(
-                        "Missing amount for deposit tx {} and client {}",
-                        tx.tx_id, tx.client
-                    ))
+                let amount = amount.ok_or_else(|| {
+                    ProcessError::rejected(
+                        RejectionCategory::InvalidAmount,
+                        format!(
+                            "Missing amount for deposit tx {} and client {}",
+                            tx.tx_id, tx.client
+                        ),
+                    )
                 })?;
+                if amount < Amount::ZERO && self.options.negatives_are_withdrawals {
+                    return Ok(TransactionRecord::Withdrawal {
+                        client: tx.client,
+                        tx_id: tx.tx_id,
+                        amount: amount.abs(),
+                    });
+                }
+                Self::check_non_negative(tx, amount)?;
                 Ok(TransactionRecord::Deposit {
                     client: tx.client,
                     tx_id: tx.tx_id,
@@ -346,132 +1985,1690 @@ impl TxEngine {
                 })
             }
             TransactionType::Withdrawal => {
-                let amount = tx.amount.ok_or_else(|| {
-                    AppError::TxProcessingNonCritical(format!(
-                        "Missing amount for withdrawal tx {} and client {}",
-                        tx.tx_id, tx.client
-                    ))
+                let amount = amount.ok_or_else(|| {
+                    ProcessError::rejected(
+                        RejectionCategory::InvalidAmount,
+                        format!(
+                            "Missing amount for withdrawal tx {} and client {}",
+                            tx.tx_id, tx.client
+                        ),
+                    )
                 })?;
+                Self::check_non_negative(tx, amount)?;
                 Ok(TransactionRecord::Withdrawal {
                     client: tx.client,
                     tx_id: tx.tx_id,
                     amount,
                 })
             }
-            TransactionType::Dispute => Ok(TransactionRecord::Dispute {
-                client: tx.client,
-                disputed_tx_id: tx.tx_id,
-            }),
+            TransactionType::Dispute => {
+                if let Some(amount) = amount {
+                    if amount <= Amount::ZERO {
+                        return Err(ProcessError::rejected(
+                            RejectionCategory::InvalidAmount,
+                            format!(
+                                "Non-positive dispute amount {} for tx {} and client {}",
+                                amount, tx.tx_id, tx.client
+                            ),
+                        ));
+                    }
+                }
+                Ok(TransactionRecord::Dispute {
+                    client: tx.client,
+                    disputed_tx_id: tx.tx_id,
+                    amount,
+                    reason: tx.reason.clone(),
+                })
+            }
+            TransactionType::Correction => {
+                let amount = amount.ok_or_else(|| {
+                    ProcessError::rejected(
+                        RejectionCategory::InvalidAmount,
+                        format!(
+                            "Missing amount for correction tx {} and client {}",
+                            tx.tx_id, tx.client
+                        ),
+                    )
+                })?;
+                let reason = tx
+                    .reason
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|reason| !reason.is_empty())
+                    .ok_or_else(|| {
+                        ProcessError::rejected(
+                            RejectionCategory::MissingReason,
+                            format!(
+                                "Missing reason for correction tx {} and client {}",
+                                tx.tx_id, tx.client
+                            ),
+                        )
+                    })?;
+                Ok(TransactionRecord::Correction {
+                    client: tx.client,
+                    tx_id: tx.tx_id,
+                    amount,
+                    reason: reason.to_owned(),
+                })
+            }
             TransactionType::Resolve => Ok(TransactionRecord::Resolve {
                 client: tx.client,
                 disputed_tx_id: tx.tx_id,
+                amount: self.lifecycle_amount(amount),
+                reason: tx.reason.clone(),
             }),
             TransactionType::Chargeback => Ok(TransactionRecord::Chargeback {
                 client: tx.client,
                 disputed_tx_id: tx.tx_id,
+                amount: self.lifecycle_amount(amount),
+                reason: tx.reason.clone(),
             }),
         }
     }
 
-    fn record_processed_transaction(&mut self, tx: TransactionRecord) {
-        match tx {
-            TransactionRecord::Deposit { client, tx_id, .. }
-            | TransactionRecord::Withdrawal { client, tx_id, .. } => {
-                self.processed_tx_ids.insert(tx_id);
-                if let Some(user) = self.users.get_mut(&client) {
-                    user.txs.insert(tx_id, tx);
-                }
-            }
-            TransactionRecord::Dispute { .. }
-            | TransactionRecord::Resolve { .. }
-            | TransactionRecord::Chargeback { .. } => {}
-        }
+    fn lifecycle_amount(&self, amount: Option<Amount<M>>) -> Option<Amount<M>> {
+        match self.options.lifecycle_amount_policy {
+            LifecycleAmountPolicy::Ignore => None,
+            LifecycleAmountPolicy::Strict => amount,
+        }
+    }
+
+    /// Rejects a resolve or chargeback whose `given` amount differs from the `moved`
+    /// amount it releases or charges back.
+    fn check_lifecycle_amount(
+        action: &str,
+        client: ClientId,
+        disputed_tx_id: TxID,
+        given: Option<Amount<M>>,
+        moved: Amount<M>,
+    ) -> Result<(), ProcessError> {
+        match given {
+            Some(given) if given != moved => Err(ProcessError::rejected(
+                RejectionCategory::InvalidAmount,
+                format!(
+                    "Cannot {} transaction {} for user {}: amount {} does not match the {} held",
+                    action, disputed_tx_id, client, given, moved
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_non_negative(tx: &Transaction<M>, amount: Amount<M>) -> Result<(), ProcessError> {
+        if amount < Amount::ZERO {
+            return Err(ProcessError::rejected(
+                RejectionCategory::InvalidAmount,
+                format!(
+                    "Negative amount {} for {} tx {} and client {}",
+                    amount, tx.op_type, tx.tx_id, tx.client
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn record_processed_transaction(&mut self, tx: TransactionRecord<M>) {
+        match tx {
+            TransactionRecord::Deposit { .. } => self.stats.deposits += 1,
+            TransactionRecord::Withdrawal { .. } => self.stats.withdrawals += 1,
+            TransactionRecord::Dispute { .. } => self.stats.disputes += 1,
+            TransactionRecord::Resolve { .. } => self.stats.resolves += 1,
+            TransactionRecord::Chargeback { .. } => self.stats.chargebacks += 1,
+            TransactionRecord::Correction { .. } => self.stats.corrections += 1,
+        }
+
+        match tx {
+            TransactionRecord::Deposit { client, tx_id, .. }
+            | TransactionRecord::Withdrawal { client, tx_id, .. }
+            | TransactionRecord::Correction { client, tx_id, .. } => {
+                self.processed_tx_ids.insert(tx_id);
+                if let Some(user) = self.users.get_mut(&client) {
+                    user.txs.insert(tx_id, tx);
+                }
+            }
+            TransactionRecord::Dispute {
+                client,
+                disputed_tx_id,
+                ..
+            }
+            | TransactionRecord::Resolve {
+                client,
+                disputed_tx_id,
+                ..
+            }
+            | TransactionRecord::Chargeback {
+                client,
+                disputed_tx_id,
+                ..
+            } => {
+                if let Some(user) = self.users.get_mut(&client) {
+                    user.lifecycle_events
+                        .entry(disputed_tx_id)
+                        .or_default()
+                        .push(tx);
+                }
+            }
+        }
+    }
+}
+
+/// Balance arithmetic is checked up front so a handler either fully applies or
+/// leaves the client untouched.
+fn checked_balance<T>(result: Option<T>, client: ClientId) -> Result<T, ProcessError> {
+    result.ok_or_else(|| ProcessError::Critical(format!("Balance overflow for user {}", client)))
+}
+
+/// Puts back the entry `key` had, or removes it if it had none.
+fn restore<V>(map: &mut HashMap<TxID, V>, key: TxID, value: Option<V>) {
+    match value {
+        Some(value) => {
+            map.insert(key, value);
+        }
+        None => {
+            map.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::errors::AppError;
+    use crate::io::input::parse_transactions_from_reader;
+    use crate::test_utils::make_tx;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn amount_equality_ignores_scale() {
+        assert_eq!(Amount::new(dec!(1.50)), Amount::new(dec!(1.5)));
+        assert!(Amount::new(dec!(1.50)).eq_value(Amount::new(dec!(1.5))));
+        assert!(Amount::new(dec!(0.0)).is_zero());
+    }
+
+    fn snapshot_for(engine: &TxEngine, client_id: u16) -> ClientSnapshot {
+        engine
+            .clients_snapshot()
+            .into_iter()
+            .find(|snapshot| snapshot.client_id == ClientId(client_id))
+            .expect("snapshot for client must exist")
+    }
+
+    #[test]
+    fn deposit_increases_available_and_total() {
+        let mut engine = TxEngine::new();
+        let tx = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.5))));
+
+        engine.process_transaction(&tx).unwrap();
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(5.5)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::new(dec!(5.5)));
+        assert!(!snapshot.locked);
+    }
+
+    #[test]
+    fn withdrawal_with_insufficient_funds_is_rejected_without_state_change() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(1.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Amount::new(dec!(2.0))),
+        ));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(1.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::new(dec!(1.0)));
+    }
+
+    #[test]
+    fn withdrawal_for_unknown_client_with_insufficient_funds_does_not_create_state() {
+        let mut engine = TxEngine::new();
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            42,
+            1,
+            Some(Amount::new(dec!(1.0))),
+        ));
+
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::InsufficientFunds)
+            }
+            other => panic!("expected insufficient funds rejection, got {other:?}"),
+        }
+        assert!(engine.clients_snapshot().is_empty());
+    }
+
+    #[test]
+    fn withdrawal_for_unknown_client_is_rejected_as_unknown_client_when_enabled() {
+        let mut engine = TxEngine::builder()
+            .reject_withdrawals_for_unknown_clients(true)
+            .build();
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            42,
+            1,
+            Some(Amount::new(dec!(1.0))),
+        ));
+
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::UnknownClient);
+                assert_eq!(
+                    rejection.message,
+                    "Cannot withdraw 1.0 for user 42, client not found"
+                );
+            }
+            other => panic!("expected unknown client rejection, got {other:?}"),
+        }
+        assert!(engine.clients_snapshot().is_empty());
+
+        // A known client short of funds is still rejected for its balance.
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                42,
+                2,
+                Some(Amount::new(dec!(0.5))),
+            ))
+            .unwrap();
+        match engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            42,
+            3,
+            Some(Amount::new(dec!(1.0))),
+        )) {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::InsufficientFunds)
+            }
+            other => panic!("expected insufficient funds rejection, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn withdrawal_on_frozen_account_is_rejected_as_frozen_before_funds_are_checked() {
+        let mut engine = TxEngine::new();
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Chargeback, 1, 1, None),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            2,
+            Some(Amount::new(dec!(5.0))),
+        ));
+
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::FrozenAccount);
+                assert_eq!(rejection.message, "Account 1 is frozen");
+            }
+            other => panic!("expected frozen account rejection, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn only_successful_deposits_or_explicit_seeding_create_accounts() {
+        let mut engine = TxEngine::new();
+        let rejected = [
+            make_tx(TransactionType::Withdrawal, 7, 1, Some(Amount::ZERO)),
+            make_tx(
+                TransactionType::Deposit,
+                7,
+                2,
+                Some(Amount::new(dec!(-1.0))),
+            ),
+            make_tx(TransactionType::Dispute, 7, 1, None),
+        ];
+        for tx in &rejected {
+            assert!(engine.process_transaction(tx).is_err());
+        }
+        assert!(!engine.account_exists(ClientId(7)));
+        assert!(engine.clients_snapshot().is_empty());
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                7,
+                3,
+                Some(Amount::new(dec!(1.0))),
+            ))
+            .unwrap();
+        assert!(engine.account_exists(ClientId(7)));
+        assert_eq!(engine.clients_snapshot().len(), 1);
+
+        assert!(engine.create_account(ClientId(8)));
+        assert!(!engine.create_account(ClientId(8)));
+        assert_eq!(snapshot_for(&engine, 8).total(), Amount::ZERO);
+    }
+
+    #[test]
+    fn snapshot_does_not_include_client_with_only_invalid_withdrawal() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            42,
+            2,
+            Some(Amount::new(dec!(1.0))),
+        ));
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+
+        let snapshots = engine.clients_snapshot();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].client_id, ClientId(1));
+    }
+
+    #[test]
+    fn withdrawal_successfully_reduces_available_and_total() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.5))),
+            ))
+            .unwrap();
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.5)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::new(dec!(3.5)));
+    }
+
+    #[test]
+    fn dispute_on_deposit_moves_funds_to_held_even_if_available_goes_negative() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.5))),
+            ))
+            .unwrap();
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(-1.5)));
+        assert_eq!(snapshot.held, Amount::new(dec!(2.0)));
+        assert_eq!(snapshot.total(), Amount::new(dec!(0.5)));
+    }
+
+    #[test]
+    fn dispute_on_unknown_tx_for_existing_client_is_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 99, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(2.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+    }
+
+    #[test]
+    fn duplicate_dispute_on_same_tx_is_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(0.0)));
+        assert_eq!(snapshot.held, Amount::new(dec!(2.0)));
+    }
+
+    #[test]
+    fn dispute_on_withdrawal_is_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(4.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::new(dec!(4.0)));
+    }
+
+    #[test]
+    fn resolve_releases_held_funds() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::new(dec!(3.0)));
+    }
+
+    #[test]
+    fn resolve_without_active_dispute_is_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+    }
+
+    #[test]
+    fn chargeback_locks_account_and_future_transactions_are_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let post_chargeback_tx =
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0))));
+        let result = engine.process_transaction(&post_chargeback_tx);
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::ZERO);
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(snapshot.total(), Amount::ZERO);
+        assert!(snapshot.locked);
+    }
+
+    #[test]
+    fn lock_reason_names_the_charged_back_transaction() {
+        let mut engine = TxEngine::new();
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+        assert_eq!(snapshot_for(&engine, 1).lock_reason, None);
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 2, None))
+            .unwrap();
+
+        assert_eq!(
+            snapshot_for(&engine, 1).lock_reason,
+            Some(LockReason {
+                tx_id: Some(TxID(2))
+            })
+        );
+    }
+
+    #[test]
+    fn chargeback_policy_chooses_between_removing_and_returning_funds() {
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Chargeback, 1, 1, None),
+        ];
+        let mut default_engine = TxEngine::new();
+        let mut returning_engine = TxEngine::builder()
+            .chargeback_policy(ChargebackPolicy::ReturnToAvailable)
+            .build();
+        for tx in &workload {
+            default_engine.process_transaction(tx).unwrap();
+            returning_engine.process_transaction(tx).unwrap();
+        }
+
+        let removed = snapshot_for(&default_engine, 1);
+        assert_eq!(removed.available, Amount::new(dec!(1.0)));
+        assert_eq!(removed.held, Amount::ZERO);
+        assert!(removed.locked);
+
+        let returned = snapshot_for(&returning_engine, 1);
+        assert_eq!(returned.available, Amount::new(dec!(4.0)));
+        assert_eq!(returned.held, Amount::ZERO);
+        assert!(!returned.locked);
+        assert_eq!(returning_engine.stats().chargebacks, 1);
+        assert!(returning_engine.verify_client(ClientId(1)).is_ok());
+    }
+
+    #[test]
+    fn partial_disputes_hold_and_release_only_their_amount() {
+        let mut engine = TxEngine::new();
+        let workload = [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(10.0))),
+            ),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(4.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(1.5)))),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(4.5)));
+        assert_eq!(snapshot.held, Amount::new(dec!(5.5)));
+        assert_eq!(engine.held_breakdown().len(), 1);
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(4.5)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert!(snapshot.locked);
+        assert!(engine.verify_client(ClientId(1)).is_ok());
+    }
+
+    #[test]
+    fn dispute_resolve_scope_releases_only_the_oldest_partial_dispute() {
+        let mut engine = TxEngine::builder()
+            .resolve_scope(ResolveScope::Dispute)
+            .build();
+        let workload = [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(10.0))),
+            ),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(4.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(1.5)))),
+            make_tx(TransactionType::Resolve, 1, 1, None),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(8.5)));
+        assert_eq!(snapshot.held, Amount::new(dec!(1.5)));
+        assert_eq!(engine.held_breakdown()[0].held, Amount::new(dec!(1.5)));
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(10.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert!(engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .is_err());
+        assert!(engine.check_invariants().is_empty());
+    }
+
+    #[test]
+    fn verify_holds_fails_a_dispute_that_would_hold_more_than_deposited() {
+        let mut engine = TxEngine::builder().verify_holds(true).build();
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(2.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(8.0)));
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 2, None))
+            .unwrap();
+        // Simulates an accounting bug that left extra funds held.
+        engine.users.get_mut(&ClientId(1)).unwrap().balances.held = Amount::new(dec!(6.0));
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
+        assert!(matches!(result, Err(ProcessError::Critical(_))));
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(6.0)));
+    }
+
+    #[test]
+    fn verify_holds_reports_overflowing_deposits_as_critical() {
+        let mut engine = TxEngine::builder().verify_holds(true).build();
+        let workload = [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(Decimal::MAX)),
+            ),
+            make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(Decimal::MAX)),
+            ),
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                3,
+                Some(Amount::new(Decimal::MAX)),
+            ),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 3, None));
+
+        assert!(matches!(result, Err(ProcessError::Critical(_))));
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::ZERO);
+    }
+
+    #[test]
+    fn amount_scale_keeps_stored_balances_at_the_printed_scale() {
+        let workload: Vec<_> = (1..=3)
+            .map(|tx_id| {
+                make_tx(
+                    TransactionType::Deposit,
+                    1,
+                    tx_id,
+                    Some(Amount::new(dec!(1.00005))),
+                )
+            })
+            .chain([
+                make_tx(
+                    TransactionType::Withdrawal,
+                    1,
+                    4,
+                    Some(Amount::new(dec!(0.50009))),
+                ),
+                make_tx(TransactionType::Dispute, 1, 1, None),
+            ])
+            .collect();
+        let run = |mut engine: TxEngine| {
+            for tx in &workload {
+                engine.process_transaction(tx).unwrap();
+            }
+            snapshot_for(&engine, 1)
+        };
+
+        let exact = run(TxEngine::new());
+        let rounded = run(TxEngine::builder().amount_scale(4).build());
+
+        // Exact mode keeps the sub-cent residue, which output then cuts away.
+        assert_eq!(exact.available, Amount::new(dec!(1.50001)));
+        assert_eq!(exact.held, Amount::new(dec!(1.00005)));
+        assert_eq!(rounded.available, Amount::new(dec!(1.5)));
+        assert_eq!(rounded.held, Amount::new(dec!(1.0)));
+        for amount in [rounded.available, rounded.held, rounded.total()] {
+            assert_eq!(amount, amount.truncate_to(4));
+        }
+        assert_ne!(exact.total(), exact.total().truncate_to(4));
+    }
+
+    #[test]
+    fn state_digest_depends_only_on_final_balances() {
+        let deposits = [(1, 1, dec!(1.5)), (2, 2, dec!(2.0)), (1, 3, dec!(0.25))];
+        let run = |order: &[usize]| {
+            let mut engine = TxEngine::new();
+            for &index in order {
+                let (client, tx_id, amount) = deposits[index];
+                engine
+                    .process_transaction(&make_tx(
+                        TransactionType::Deposit,
+                        client,
+                        tx_id,
+                        Some(Amount::new(amount)),
+                    ))
+                    .unwrap();
+            }
+            engine
+        };
+
+        let forward = run(&[0, 1, 2]);
+        let reversed = run(&[2, 1, 0]);
+        assert_eq!(forward.state_digest(), reversed.state_digest());
+
+        let mut changed = run(&[0, 1, 2]);
+        changed
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                2,
+                4,
+                Some(Amount::new(dec!(0.0001))),
+            ))
+            .unwrap();
+        assert_ne!(forward.state_digest(), changed.state_digest());
+    }
+
+    #[test]
+    fn system_account_may_go_negative_and_is_not_locked_by_a_chargeback() {
+        let mut engine = TxEngine::builder().system_account(ClientId(0)).build();
+        assert!(engine.account_exists(ClientId(0)));
+
+        let workload = [
+            make_tx(
+                TransactionType::Withdrawal,
+                0,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ),
+            make_tx(TransactionType::Deposit, 0, 2, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Dispute, 0, 2, None),
+            make_tx(TransactionType::Chargeback, 0, 2, None),
+            make_tx(TransactionType::Deposit, 0, 3, Some(Amount::new(dec!(1.0)))),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let system = snapshot_for(&engine, 0);
+        assert_eq!(system.available, Amount::new(dec!(-1.0)));
+        assert_eq!(system.held, Amount::ZERO);
+        assert!(!system.locked);
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                4,
+                Some(Amount::new(dec!(1.0))),
+            ))
+            .unwrap();
+        let overdraft = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            5,
+            Some(Amount::new(dec!(2.0))),
+        ));
+        assert!(matches!(overdraft, Err(ProcessError::Rejected(_))));
+    }
+
+    #[test]
+    fn snapshot_total_stays_at_the_amount_scale() {
+        let mut engine = TxEngine::builder().amount_scale(2).build();
+        let workload = [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(10.129))),
+            ),
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(Amount::new(dec!(0.3333))),
+            ),
+            make_tx(
+                TransactionType::Dispute,
+                1,
+                2,
+                Some(Amount::new(dec!(0.111))),
+            ),
+        ];
+        for tx in &workload {
+            engine.process_transaction(tx).unwrap();
+        }
+
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(10.34)));
+        assert_eq!(snapshot.held, Amount::new(dec!(0.11)));
+        let total = snapshot.total();
+        assert_eq!(total, snapshot.available + snapshot.held);
+        assert_eq!(total.inner().to_string(), "10.45");
+    }
+
+    #[test]
+    fn partial_disputes_cannot_exceed_the_disputed_amount() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(10.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Dispute,
+                1,
+                1,
+                Some(Amount::new(dec!(7.0))),
+            ))
+            .unwrap();
+
+        let over_dispute = engine.process_transaction(&make_tx(
+            TransactionType::Dispute,
+            1,
+            1,
+            Some(Amount::new(dec!(3.5))),
+        ));
+        let full_dispute =
+            engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
+
+        for result in [over_dispute, full_dispute] {
+            match result {
+                Err(ProcessError::Rejected(rejection)) => {
+                    assert_eq!(rejection.category, RejectionCategory::InvalidDispute)
+                }
+                other => panic!("expected invalid dispute, got {other:?}"),
+            }
+        }
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
+        assert_eq!(snapshot.held, Amount::new(dec!(7.0)));
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(10.0)));
+    }
+
+    #[test]
+    fn chargeback_without_active_dispute_is_rejected() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert!(!snapshot.locked);
+    }
+
+    #[test]
+    fn frozen_account_rejects_non_deposit_ops_too() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(4.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let resolve_result =
+            engine.process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None));
+        let dispute_result =
+            engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
+        let chargeback_result =
+            engine.process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None));
+
+        assert!(matches!(resolve_result, Err(ProcessError::Rejected(_))));
+        assert!(matches!(dispute_result, Err(ProcessError::Rejected(_))));
+        assert!(matches!(chargeback_result, Err(ProcessError::Rejected(_))));
+    }
+
+    #[test]
+    fn duplicate_tx_id_is_rejected_globally_across_clients() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                10,
+                Some(Amount::new(dec!(1.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            2,
+            10,
+            Some(Amount::new(dec!(2.0))),
+        ));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        assert_eq!(engine.clients_snapshot().len(), 1);
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(1.0)));
+    }
+
+    #[test]
+    fn per_client_dedup_scope_lets_clients_reuse_a_tx_id() {
+        let mut engine = TxEngine::builder()
+            .dedup_scope(DedupScope::PerClient)
+            .build();
+        for tx in [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                10,
+                Some(Amount::new(dec!(1.0))),
+            ),
+            make_tx(
+                TransactionType::Deposit,
+                2,
+                10,
+                Some(Amount::new(dec!(2.0))),
+            ),
+            make_tx(TransactionType::Dispute, 2, 10, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+
+        let repeat = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            1,
+            10,
+            Some(Amount::new(dec!(1.0))),
+        ));
+        assert!(matches!(
+            repeat,
+            Err(ProcessError::Rejected(Rejection {
+                category: RejectionCategory::DuplicateTx,
+                ..
+            }))
+        ));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(1.0)));
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::ZERO);
+        assert_eq!(snapshot_for(&engine, 2).available, Amount::ZERO);
+        assert_eq!(snapshot_for(&engine, 2).held, Amount::new(dec!(2.0)));
+
+        let op_types = |client| {
+            engine
+                .transaction_history(ClientId(client), TxID(10))
+                .into_iter()
+                .map(|entry| entry.op_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(op_types(1), [TransactionType::Deposit]);
+        assert_eq!(
+            op_types(2),
+            [TransactionType::Deposit, TransactionType::Dispute]
+        );
+    }
+
+    #[test]
+    fn resolved_tx_id_is_not_reusable_by_a_new_deposit() {
+        let mut engine = TxEngine::new();
+        let deposit = make_tx(TransactionType::Deposit, 1, 5, Some(Amount::new(dec!(5.0))));
+        engine.process_transaction(&deposit).unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 5, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 5, None))
+            .unwrap();
+
+        let result = engine.process_transaction(&deposit);
+
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::DuplicateTx)
+            }
+            other => panic!("expected a duplicate rejection, got {other:?}"),
+        }
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(5.0)));
+        assert_eq!(snapshot.held, Amount::new(dec!(0.0)));
+
+        // The original deposit is still the one a new dispute refers to.
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 5, None))
+            .unwrap();
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(0.0)));
+        assert_eq!(snapshot.held, Amount::new(dec!(5.0)));
+        assert!(engine.verify_client(ClientId(1)).is_ok());
+    }
+
+    #[test]
+    fn invalid_non_deposit_ops_for_unknown_client_do_not_create_state() {
+        let mut engine = TxEngine::new();
+
+        let dispute_result =
+            engine.process_transaction(&make_tx(TransactionType::Dispute, 9, 1, None));
+        let resolve_result =
+            engine.process_transaction(&make_tx(TransactionType::Resolve, 9, 1, None));
+        let chargeback_result =
+            engine.process_transaction(&make_tx(TransactionType::Chargeback, 9, 1, None));
+
+        assert!(matches!(dispute_result, Err(ProcessError::Rejected(_))));
+        assert!(matches!(resolve_result, Err(ProcessError::Rejected(_))));
+        assert!(matches!(chargeback_result, Err(ProcessError::Rejected(_))));
+        assert!(engine.clients_snapshot().is_empty());
+    }
+
+    #[test]
+    fn missing_amount_for_deposit_is_rejected() {
+        let mut engine = TxEngine::new();
+        let result = engine.process_transaction(&make_tx(TransactionType::Deposit, 1, 1, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        assert!(engine.clients_snapshot().is_empty());
+    }
+
+    #[test]
+    fn missing_amount_for_withdrawal_is_rejected() {
+        let mut engine = TxEngine::new();
+        let result = engine.process_transaction(&make_tx(TransactionType::Withdrawal, 1, 1, None));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        assert!(engine.clients_snapshot().is_empty());
+    }
+
+    #[test]
+    fn stats_and_totals_reflect_only_applied_transactions() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                2,
+                2,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 2, 2, None))
+            .unwrap();
+        let _ = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(Amount::new(dec!(10.0))),
+        ));
+
+        let stats = engine.stats();
+        assert_eq!(stats.deposits, 2);
+        assert_eq!(stats.withdrawals, 0);
+        assert_eq!(stats.disputes, 1);
+
+        let totals = engine.totals();
+        assert_eq!(totals.available, Amount::new(dec!(3.0)));
+        assert_eq!(totals.held, Amount::new(dec!(2.0)));
+        assert_eq!(totals.locked_accounts, 0);
+    }
+
+    #[test]
+    fn resolve_all_disputes_releases_every_held_amount() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(Amount::new(dec!(3.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+
+        let resolved = engine.resolve_all_disputes(ClientId(1)).unwrap();
+
+        assert_eq!(resolved, 2);
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(5.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(engine.resolve_all_disputes(ClientId(1)).unwrap(), 0);
+        assert_eq!(engine.resolve_all_disputes(ClientId(7)).unwrap(), 0);
+    }
+
+    #[test]
+    fn resolve_all_disputes_is_rejected_for_frozen_account() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let result = engine.resolve_all_disputes(ClientId(1));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+    }
+
+    #[test]
+    fn verify_client_accepts_consistent_balances() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.5))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        assert_eq!(engine.verify_client(ClientId(1)), Ok(()));
+        assert!(engine.verify_client(ClientId(2)).is_err());
+    }
+
+    #[test]
+    fn verify_client_excludes_charged_back_deposits() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+
+        assert_eq!(snapshot_for(&engine, 1).total(), Amount::new(dec!(2.0)));
+        assert_eq!(engine.verify_client(ClientId(1)), Ok(()));
+    }
+
+    #[test]
+    fn overflowing_deposit_is_critical_and_leaves_balances_unchanged() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(Decimal::MAX)),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            1,
+            2,
+            Some(Amount::new(dec!(1.0))),
+        ));
+
+        assert!(matches!(result, Err(ProcessError::Critical(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(Decimal::MAX));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(engine.stats().deposits, 1);
+    }
+
+    #[test]
+    fn overflowing_dispute_is_critical_and_leaves_balances_unchanged() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(Decimal::MAX)),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(Decimal::MAX)),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                3,
+                Some(Amount::new(Decimal::MAX)),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 3, None));
+
+        assert!(matches!(result, Err(ProcessError::Critical(_))));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(0.0)));
+        assert_eq!(snapshot.held, Amount::new(Decimal::MAX));
+    }
+
+    #[test]
+    fn withdrawal_dispute_holds_amount_and_chargeback_returns_it() {
+        let mut engine = TxEngine::builder().allow_withdrawal_disputes(true).build();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
+        assert_eq!(snapshot.held, Amount::new(dec!(2.0)));
+        assert_eq!(engine.verify_client(ClientId(1)), Ok(()));
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 2, None))
+            .unwrap();
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(5.0)));
+        assert_eq!(snapshot.held, Amount::ZERO);
+        assert!(snapshot.locked);
+        assert_eq!(engine.verify_client(ClientId(1)), Ok(()));
+    }
+
+    #[test]
+    fn simulate_withdrawal_does_not_mutate_engine() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+
+        let simulated = engine
+            .simulate(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.5))),
+            ))
+            .unwrap();
+
+        assert_eq!(simulated.available, Amount::new(dec!(3.5)));
+        assert_eq!(simulated.total(), Amount::new(dec!(3.5)));
+        let snapshot = snapshot_for(&engine, 1);
+        assert_eq!(snapshot.available, Amount::new(dec!(5.0)));
+        assert_eq!(engine.stats().withdrawals, 0);
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                2,
+                Some(Amount::new(dec!(1.5))),
+            ))
+            .unwrap();
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(3.5)));
+    }
+
+    #[test]
+    fn simulate_reports_rejections_without_creating_state() {
+        let engine = TxEngine::new();
+
+        let result = engine.simulate(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            1,
+            Some(Amount::new(dec!(1.0))),
+        ));
+
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        assert!(engine.clients_snapshot().is_empty());
+    }
+
+    #[test]
+    fn negative_amounts_are_rejected_by_default() {
+        let mut engine = TxEngine::new();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+
+        let deposit_result = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            1,
+            2,
+            Some(Amount::new(dec!(-2.0))),
+        ));
+        let withdrawal_result = engine.process_transaction(&make_tx(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(Amount::new(dec!(-2.0))),
+        ));
+
+        assert!(matches!(deposit_result, Err(ProcessError::Rejected(_))));
+        assert!(matches!(withdrawal_result, Err(ProcessError::Rejected(_))));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(5.0)));
+    }
+
+    #[test]
+    fn negative_deposit_is_withdrawal_when_enabled() {
+        let mut engine = TxEngine::builder().negatives_are_withdrawals(true).build();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(Amount::new(dec!(-2.0))),
+            ))
+            .unwrap();
+        let overdraft = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            1,
+            3,
+            Some(Amount::new(dec!(-4.0))),
+        ));
+        let dispute = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
+
+        assert!(matches!(overdraft, Err(ProcessError::Rejected(_))));
+        assert!(matches!(dispute, Err(ProcessError::Rejected(_))));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(3.0)));
+        assert_eq!(engine.stats().withdrawals, 1);
+    }
+
+    fn process_repeated_deposit(policy: DuplicateTxPolicy) -> (TxEngine, Result<(), ProcessError>) {
+        let mut engine = TxEngine::builder().duplicate_tx_policy(policy).build();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(&make_tx(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Amount::new(dec!(3.0))),
+        ));
+        (engine, result)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rust_decimal_macros::dec;
+    #[test]
+    fn duplicate_policy_reject_skips_repeated_deposit() {
+        let (engine, result) = process_repeated_deposit(DuplicateTxPolicy::Reject);
 
-    fn make_tx(
-        op_type: TransactionType,
-        client: u16,
-        tx_id: u32,
-        amount: Option<Amount>,
-    ) -> Transaction {
-        Transaction {
-            op_type,
-            client: ClientId(client),
-            tx_id: TxID(tx_id),
-            amount,
-        }
+        assert!(matches!(result, Err(ProcessError::Rejected(_))));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(2.0)));
     }
 
-    fn snapshot_for(engine: &TxEngine, client_id: u16) -> ClientSnapshot {
-        engine
-            .clients_snapshot()
-            .into_iter()
-            .find(|snapshot| snapshot.client_id == ClientId(client_id))
-            .expect("snapshot for client must exist")
+    #[test]
+    fn duplicate_policy_ignore_second_is_silent_no_op_for_identical_retry() {
+        let mut engine = TxEngine::builder()
+            .duplicate_tx_policy(DuplicateTxPolicy::IgnoreSecond)
+            .build();
+        let deposit = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0))));
+        engine.process_transaction(&deposit).unwrap();
+
+        let result = engine.process_transaction(&deposit);
+
+        assert!(result.is_ok());
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(2.0)));
+        assert_eq!(engine.stats().deposits, 1);
     }
 
     #[test]
-    fn deposit_increases_available_and_total() {
-        let mut engine = TxEngine::new();
-        let tx = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.5))));
+    fn duplicate_policy_ignore_second_rejects_conflicting_amount() {
+        let (engine, result) = process_repeated_deposit(DuplicateTxPolicy::IgnoreSecond);
 
-        engine.process_transaction(&tx).unwrap();
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::DuplicateTx);
+                assert!(
+                    rejection.message.contains("conflicting amount"),
+                    "{rejection}"
+                )
+            }
+            other => panic!("expected rejection, got {other:?}"),
+        }
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(2.0)));
+        assert_eq!(engine.stats().deposits, 1);
+    }
 
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(5.5)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::new(dec!(5.5)));
-        assert!(!snapshot.locked);
+    #[test]
+    fn duplicate_policy_error_is_critical() {
+        let (engine, result) = process_repeated_deposit(DuplicateTxPolicy::Error);
+
+        assert!(matches!(result, Err(ProcessError::Critical(_))));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(2.0)));
     }
 
     #[test]
-    fn withdrawal_with_insufficient_funds_is_rejected_without_state_change() {
+    fn net_flow_ignores_holds_and_chargebacks() {
         let mut engine = TxEngine::new();
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
+                Some(Amount::new(dec!(5.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                1,
+                2,
+                Some(Amount::new(dec!(2.0))),
+            ))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                3,
                 Some(Amount::new(dec!(1.0))),
             ))
             .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
 
-        let result = engine.process_transaction(&make_tx(
-            TransactionType::Withdrawal,
-            1,
-            2,
-            Some(Amount::new(dec!(2.0))),
-        ));
-
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
         let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(1.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::new(dec!(1.0)));
-    }
+        assert_eq!(engine.net_flow(ClientId(1)), Some(Amount::new(dec!(6.0))));
+        assert_eq!(snapshot.total(), Amount::new(dec!(6.0)));
+        assert_eq!(snapshot.available, Amount::new(dec!(4.0)));
 
-    #[test]
-    fn withdrawal_for_unknown_client_with_insufficient_funds_does_not_create_state() {
-        let mut engine = TxEngine::new();
-        let result = engine.process_transaction(&make_tx(
-            TransactionType::Withdrawal,
-            42,
-            1,
-            Some(Amount::new(dec!(1.0))),
-        ));
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 2, None))
+            .unwrap();
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        assert!(engine.clients_snapshot().is_empty());
+        assert_eq!(engine.net_flow(ClientId(1)), Some(Amount::new(dec!(6.0))));
+        assert_eq!(snapshot_for(&engine, 1).total(), Amount::new(dec!(4.0)));
+        assert_eq!(engine.net_flow(ClientId(2)), None);
     }
 
     #[test]
-    fn snapshot_does_not_include_client_with_only_invalid_withdrawal() {
+    fn resolve_after_chargeback_reports_charged_back_tx() {
         let mut engine = TxEngine::new();
         engine
             .process_transaction(&make_tx(
@@ -481,350 +3678,992 @@ mod tests {
                 Some(Amount::new(dec!(3.0))),
             ))
             .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
 
-        let result = engine.process_transaction(&make_tx(
-            TransactionType::Withdrawal,
-            42,
-            2,
-            Some(Amount::new(dec!(1.0))),
-        ));
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
+        let result = engine.process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None));
 
-        let snapshots = engine.clients_snapshot();
-        assert_eq!(snapshots.len(), 1);
-        assert_eq!(snapshots[0].client_id, ClientId(1));
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(
+                    rejection.message,
+                    "Cannot resolve transaction 1 for user 1: already charged back"
+                );
+            }
+            other => panic!("expected non-critical error, got {other:?}"),
+        }
     }
 
     #[test]
-    fn withdrawal_successfully_reduces_available_and_total() {
+    fn held_breakdown_lists_open_disputes_summing_to_held() {
+        let mut engine = TxEngine::new();
+        for (tx_id, amount) in [(1, dec!(2.0)), (2, dec!(3.5)), (3, dec!(1.0))] {
+            engine
+                .process_transaction(&make_tx(
+                    TransactionType::Deposit,
+                    1,
+                    tx_id,
+                    Some(Amount::new(amount)),
+                ))
+                .unwrap();
+        }
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let entries = engine.held_breakdown();
+
+        assert_eq!(
+            entries.iter().map(|entry| entry.tx_id).collect::<Vec<_>>(),
+            vec![TxID(1), TxID(2)]
+        );
+        let held_sum = entries
+            .iter()
+            .fold(Amount::ZERO, |sum, entry| sum + entry.held);
+        assert_eq!(held_sum, snapshot_for(&engine, 1).held);
+    }
+
+    #[test]
+    fn engine_errors_are_only_rejections_or_critical() {
         let mut engine = TxEngine::new();
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
-                Some(Amount::new(dec!(5.0))),
+                Some(Amount::new(Decimal::MAX)),
             ))
             .unwrap();
+        let bad_transactions = [
+            make_tx(TransactionType::Deposit, 2, 2, None),
+            make_tx(
+                TransactionType::Withdrawal,
+                3,
+                3,
+                Some(Amount::new(dec!(1.0))),
+            ),
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Resolve, 1, 1, None),
+            make_tx(TransactionType::Deposit, 1, 4, Some(Amount::new(dec!(1.0)))),
+        ];
+
+        let errors: Vec<ProcessError> = bad_transactions
+            .iter()
+            .filter_map(|tx| engine.process_transaction(tx).err())
+            .collect();
+
+        assert_eq!(errors.len(), bad_transactions.len());
+        for err in &errors[..4] {
+            assert!(matches!(err, ProcessError::Rejected(_)));
+        }
+        assert!(matches!(errors[4], ProcessError::Critical(_)));
+        assert!(matches!(
+            AppError::from(errors[4].clone()),
+            AppError::TxProcessing(_)
+        ));
+    }
+
+    #[test]
+    fn max_open_disputes_rejects_dispute_over_the_cap() {
+        let mut engine = TxEngine::builder().max_open_disputes(1).build();
+        for tx_id in [1, 2] {
+            engine
+                .process_transaction(&make_tx(
+                    TransactionType::Deposit,
+                    1,
+                    tx_id,
+                    Some(Amount::new(dec!(2.0))),
+                ))
+                .unwrap();
+        }
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+
+        let over_cap = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
+
+        assert!(matches!(over_cap, Err(ProcessError::Rejected(_))));
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(2.0)));
 
         engine
-            .process_transaction(&make_tx(
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        engine
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(2.0)));
+    }
+
+    #[test]
+    fn audit_log_replay_matches_original_snapshot() {
+        let mut engine = TxEngine::builder()
+            .record_audit(true)
+            .allow_withdrawal_disputes(true)
+            .build();
+        let workload = [
+            make_tx(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Amount::new(dec!(10.0))),
+            ),
+            make_tx(
                 TransactionType::Withdrawal,
                 1,
                 2,
-                Some(Amount::new(dec!(1.5))),
-            ))
+                Some(Amount::new(dec!(3.5))),
+            ),
+            make_tx(
+                TransactionType::Deposit,
+                2,
+                3,
+                Some(Amount::new(dec!(4.25))),
+            ),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(TransactionType::Dispute, 2, 3, None),
+            make_tx(TransactionType::Chargeback, 2, 3, None),
+            make_tx(
+                TransactionType::Withdrawal,
+                1,
+                4,
+                Some(Amount::new(dec!(100.0))),
+            ),
+            make_tx(TransactionType::Deposit, 3, 5, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 3, 5, None),
+            make_tx(TransactionType::Deposit, 4, 6, Some(Amount::new(dec!(0.5)))),
+        ];
+        assert!(engine.seed_client(
+            ClientId(4),
+            Amount::new(dec!(2.0)),
+            Amount::new(dec!(1.5)),
+            false
+        ));
+        assert!(engine.seed_client(ClientId(5), Amount::new(dec!(3.0)), Amount::ZERO, true));
+        assert!(engine.create_account(ClientId(6)));
+        for tx in &workload {
+            let _ = engine.process_transaction(tx);
+        }
+        engine.resolve_all_disputes(ClientId(3)).unwrap();
+
+        let rebuilt = TxEngine::from_audit_log(engine.audit_log()).unwrap();
+
+        assert_eq!(rebuilt.clients_snapshot(), engine.clients_snapshot());
+        assert_eq!(engine.audit_log().len(), 13);
+        assert!(TxEngine::new().audit_log().is_empty());
+    }
+
+    #[test]
+    fn dispute_reason_is_recorded_in_audit_and_held_breakdown() {
+        let mut engine = TxEngine::builder().record_audit(true).build();
+        let deposit = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0))));
+        let dispute = Transaction {
+            reason: Some("fraud".to_string()),
+            ..make_tx(TransactionType::Dispute, 1, 1, None)
+        };
+
+        engine.process_transaction(&deposit).unwrap();
+        engine.process_transaction(&dispute).unwrap();
+
+        let entry = engine.audit_log().last().expect("dispute must be audited");
+        assert_eq!(entry.op_type, Some(TransactionType::Dispute));
+        assert_eq!(entry.reason.as_deref(), Some("fraud"));
+        assert_eq!(engine.audit_log()[0].reason, None);
+        assert_eq!(engine.held_breakdown()[0].reason.as_deref(), Some("fraud"));
+
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
             .unwrap();
+        assert!(engine.held_breakdown().is_empty());
+    }
 
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(3.5)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::new(dec!(3.5)));
+    #[test]
+    fn integer_cents_backend_matches_decimal_results() {
+        fn workload<M: Money>(amount: impl Fn(i64) -> M) -> Vec<Transaction<M>> {
+            let tx = |op_type, client, tx_id, cents: Option<i64>| Transaction {
+                op_type,
+                client: ClientId(client),
+                tx_id: TxID(tx_id),
+                amount: cents.map(|cents| Amount::new(amount(cents))),
+                reason: None,
+                row_digest: None,
+            };
+            vec![
+                tx(TransactionType::Deposit, 1, 1, Some(1_050)),
+                tx(TransactionType::Withdrawal, 1, 2, Some(325)),
+                tx(TransactionType::Withdrawal, 1, 3, Some(10_000)),
+                tx(TransactionType::Deposit, 2, 4, Some(700)),
+                tx(TransactionType::Dispute, 1, 1, None),
+                tx(TransactionType::Dispute, 2, 4, None),
+                tx(TransactionType::Chargeback, 2, 4, None),
+            ]
+        }
+
+        let mut decimal_engine = TxEngine::new();
+        for tx in &workload(|cents| Decimal::new(cents, 2)) {
+            let _ = decimal_engine.process_transaction(tx);
+        }
+        let mut cents_engine =
+            TxEngineBuilder::new().build_with_store(HashMap::<ClientId, ClientData<i64>>::new());
+        for tx in &workload(|cents| cents) {
+            let _ = cents_engine.process_transaction(tx);
+        }
+
+        let as_cents = |amount: Amount| {
+            let scaled = amount.inner() * Decimal::ONE_HUNDRED;
+            assert!(scaled.fract().is_zero());
+            i64::try_from(scaled).unwrap()
+        };
+        let expected: Vec<(ClientId, i64, i64, bool)> = decimal_engine
+            .clients_snapshot()
+            .into_iter()
+            .map(|s| {
+                (
+                    s.client_id,
+                    as_cents(s.available),
+                    as_cents(s.held),
+                    s.locked,
+                )
+            })
+            .collect();
+        let actual: Vec<(ClientId, i64, i64, bool)> = cents_engine
+            .clients_snapshot()
+            .into_iter()
+            .map(|s| (s.client_id, s.available.inner(), s.held.inner(), s.locked))
+            .collect();
+
+        assert_eq!(actual, expected);
+        assert_eq!(
+            actual,
+            vec![
+                (ClientId(1), -1_050 + 725, 1_050, false),
+                (ClientId(2), 0, 0, true)
+            ]
+        );
+    }
+
+    // `i64::MIN.abs()` only panics with overflow checks, which follow debug assertions.
+    #[cfg(debug_assertions)]
+    #[test]
+    fn panic_safe_turns_a_panicking_transaction_into_a_critical_error() {
+        let cents = |tx_id, cents| Transaction {
+            op_type: TransactionType::Deposit,
+            client: ClientId(1),
+            tx_id: TxID(tx_id),
+            amount: Some(Amount::new(cents)),
+            reason: None,
+            row_digest: None,
+        };
+        let builder = || {
+            TxEngineBuilder::new()
+                .negatives_are_withdrawals(true)
+                .panic_safe(true)
+        };
+
+        let mut unguarded = builder()
+            .panic_safe(false)
+            .build_with_store(HashMap::<ClientId, ClientData<i64>>::new());
+        let unwound = panic::catch_unwind(AssertUnwindSafe(|| {
+            unguarded.process_transaction(&cents(1, i64::MIN))
+        }));
+        assert!(unwound.is_err());
+
+        let mut engine = builder().build_with_store(HashMap::<ClientId, ClientData<i64>>::new());
+        engine.process_transaction(&cents(1, 100)).unwrap();
+        let result = engine.process_transaction(&cents(2, i64::MIN));
+
+        match result {
+            Err(ProcessError::Critical(message)) => {
+                assert!(message.starts_with("Panic while processing transaction 2"))
+            }
+            other => panic!("expected a critical error, got {other:?}"),
+        }
+        assert!(engine.recent_skips().is_empty());
+    }
+
+    #[test]
+    fn transaction_history_lists_the_deposit_and_its_lifecycle_events() {
+        let mut engine = TxEngine::new();
+        let dispute = Transaction {
+            reason: Some("fraud".to_string()),
+            ..make_tx(TransactionType::Dispute, 1, 1, None)
+        };
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0)))),
+            dispute,
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(TransactionType::Chargeback, 1, 1, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        // Rejected rows are not part of the history.
+        assert!(engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .is_err());
+
+        let history = engine.transaction_history(ClientId(1), TxID(1));
+
+        let entry = |op_type, amount, reason: Option<&str>| HistoryEntry {
+            op_type,
+            client_id: ClientId(1),
+            tx_id: TxID(1),
+            amount,
+            reason: reason.map(str::to_string),
+        };
+        assert_eq!(
+            history,
+            vec![
+                entry(TransactionType::Deposit, Some(Amount::new(dec!(2.0))), None),
+                entry(TransactionType::Dispute, None, Some("fraud")),
+                entry(TransactionType::Chargeback, None, None),
+            ]
+        );
+        assert_eq!(engine.transaction_history(ClientId(1), TxID(2)).len(), 2);
+        assert!(engine.transaction_history(ClientId(1), TxID(3)).is_empty());
+        assert!(engine.transaction_history(ClientId(2), TxID(1)).is_empty());
+    }
+
+    #[test]
+    fn into_snapshots_consumes_the_engine() {
+        let mut engine = TxEngine::new();
+        for tx in [
+            make_tx(TransactionType::Deposit, 2, 1, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 2, 1, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        let expected = engine.clients_snapshot();
+
+        // `engine` is moved here, so any further use of it no longer compiles.
+        let snapshots = engine.into_snapshots();
+
+        assert_eq!(snapshots, expected);
+        assert_eq!(
+            snapshots
+                .iter()
+                .map(|snapshot| snapshot.client_id)
+                .collect::<Vec<_>>(),
+            vec![ClientId(1), ClientId(2)]
+        );
+        assert_eq!(snapshots[1].held, Amount::new(dec!(3.0)));
+    }
+
+    #[test]
+    fn dedup_identical_skips_a_repeated_row_before_the_duplicate_tx_policy() {
+        let deposit = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0))));
+        let repeat = make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0))));
+        let builder = || TxEngine::builder().duplicate_tx_policy(DuplicateTxPolicy::Error);
+
+        let mut plain = builder().build();
+        plain.process_transaction(&deposit).unwrap();
+        assert!(matches!(
+            plain.process_transaction(&repeat),
+            Err(ProcessError::Critical(_))
+        ));
+
+        let mut engine = builder()
+            .dedup_identical(IdenticalRowScope::Previous)
+            .build();
+        engine.process_transaction(&deposit).unwrap();
+        match engine.process_transaction(&repeat) {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::DuplicateRow)
+            }
+            other => panic!("expected a duplicate row rejection, got {other:?}"),
+        }
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(2.0)));
+    }
+
+    #[test]
+    fn dedup_identical_compares_rows_as_read() {
+        let csv = "\
+type,client,tx,amount
+deposit,1,5,1.0
+deposit,1,5,1.00
+deposit,1,5,1.00
+";
+        let mut engine = TxEngine::builder()
+            .dedup_identical(IdenticalRowScope::Any)
+            .build();
+        let categories: Vec<_> = parse_transactions_from_reader(csv.as_bytes())
+            .map(|tx| match engine.process_transaction(&tx.unwrap()) {
+                Ok(()) => None,
+                Err(ProcessError::Rejected(rejection)) => Some(rejection.category),
+                Err(err) => panic!("unexpected critical error {err:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            categories,
+            [
+                None,
+                Some(RejectionCategory::DuplicateTx),
+                Some(RejectionCategory::DuplicateRow)
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_identical_scope_controls_which_rows_are_compared() {
+        let rows = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(2.0)))),
+        ];
+        let last_rejection = |scope| {
+            let mut engine = TxEngine::builder().dedup_identical(scope).build();
+            let results: Vec<_> = rows
+                .iter()
+                .map(|tx| engine.process_transaction(tx))
+                .collect();
+            match results.last() {
+                Some(Err(ProcessError::Rejected(rejection))) => rejection.category,
+                other => panic!("expected a rejection, got {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            last_rejection(IdenticalRowScope::Previous),
+            RejectionCategory::DuplicateTx
+        );
+        assert_eq!(
+            last_rejection(IdenticalRowScope::Any),
+            RejectionCategory::DuplicateRow
+        );
     }
 
     #[test]
-    fn dispute_on_deposit_moves_funds_to_held_even_if_available_goes_negative() {
+    fn check_invariants_reports_overflowing_sums_as_violations() {
         let mut engine = TxEngine::new();
-        engine
-            .process_transaction(&make_tx(
+        for tx in [
+            make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
-                Some(Amount::new(dec!(2.0))),
-            ))
-            .unwrap();
-        engine
-            .process_transaction(&make_tx(
-                TransactionType::Withdrawal,
-                1,
-                2,
-                Some(Amount::new(dec!(1.5))),
-            ))
-            .unwrap();
+                Some(Amount::new(Decimal::MAX)),
+            ),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        assert!(engine.check_invariants().is_empty());
 
-        engine
-            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
-            .unwrap();
+        let user = engine.users.get_mut(&ClientId(1)).unwrap();
+        user.balances.available = Amount::new(Decimal::MAX);
+        user.parked = Amount::new(Decimal::MAX);
 
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(-1.5)));
-        assert_eq!(snapshot.held, Amount::new(dec!(2.0)));
-        assert_eq!(snapshot.total(), Amount::new(dec!(0.5)));
+        assert_eq!(
+            engine.check_invariants(),
+            vec![
+                "Balance overflow while verifying client 1".to_string(),
+                "Held amount overflow while checking client 1".to_string(),
+            ]
+        );
     }
 
     #[test]
-    fn dispute_on_unknown_tx_for_existing_client_is_rejected() {
-        let mut engine = TxEngine::new();
-        engine
-            .process_transaction(&make_tx(
-                TransactionType::Deposit,
-                1,
+    fn check_invariants_reports_inconsistent_held_amounts() {
+        let mut engine = TxEngine::builder().allow_withdrawal_disputes(true).build();
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(
+                TransactionType::Withdrawal,
                 1,
+                2,
                 Some(Amount::new(dec!(2.0))),
-            ))
-            .unwrap();
+            ),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(TransactionType::Deposit, 2, 3, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 2, 3, None),
+            make_tx(TransactionType::Chargeback, 2, 3, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        assert!(engine.check_invariants().is_empty());
 
-        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 99, None));
+        let user = engine.users.get_mut(&ClientId(1)).unwrap();
+        user.balances.held = Amount::new(dec!(-1.0));
+        user.balances.available += Amount::new(dec!(3.0));
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(2.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
+        assert_eq!(
+            engine.check_invariants(),
+            vec![
+                "Negative held amount -1.0 for client 1".to_string(),
+                "Held amount -1.0 for client 1 does not match open disputes and parked funds \
+                 totalling 2.0"
+                    .to_string(),
+            ]
+        );
     }
 
     #[test]
-    fn duplicate_dispute_on_same_tx_is_rejected() {
-        let mut engine = TxEngine::new();
+    fn available_ceiling_parks_the_excess_in_held() {
+        let mut engine = TxEngine::builder()
+            .available_ceiling(Amount::new(dec!(100)))
+            .build();
+        let balances = |engine: &TxEngine| {
+            let snapshot = snapshot_for(engine, 1);
+            (snapshot.available, snapshot.held, snapshot.parked)
+        };
+        let amounts = |available, held, parked| {
+            (
+                Amount::new(available),
+                Amount::new(held),
+                Amount::new(parked),
+            )
+        };
+
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
-                Some(Amount::new(dec!(2.0))),
+                Some(Amount::new(dec!(80))),
             ))
             .unwrap();
-        engine
-            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
-            .unwrap();
-
-        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
-
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(0.0)));
-        assert_eq!(snapshot.held, Amount::new(dec!(2.0)));
-    }
-
-    #[test]
-    fn dispute_on_withdrawal_is_rejected() {
-        let mut engine = TxEngine::new();
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
-                1,
-                Some(Amount::new(dec!(5.0))),
+                2,
+                Some(Amount::new(dec!(50))),
             ))
             .unwrap();
+        assert_eq!(balances(&engine), amounts(dec!(100), dec!(30), dec!(30)));
+
+        // Parked funds are not withdrawable, but refill available once it drops.
+        assert!(engine
+            .process_transaction(&make_tx(
+                TransactionType::Withdrawal,
+                1,
+                3,
+                Some(Amount::new(dec!(120)))
+            ))
+            .is_err());
         engine
             .process_transaction(&make_tx(
                 TransactionType::Withdrawal,
                 1,
-                2,
-                Some(Amount::new(dec!(1.0))),
+                4,
+                Some(Amount::new(dec!(40))),
             ))
             .unwrap();
+        assert_eq!(balances(&engine), amounts(dec!(90), dec!(0), dec!(0)));
 
-        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 2, None));
-
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(4.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::new(dec!(4.0)));
-    }
-
-    #[test]
-    fn resolve_releases_held_funds() {
-        let mut engine = TxEngine::new();
+        // Disputed funds are held separately from parked ones.
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
-                1,
-                Some(Amount::new(dec!(3.0))),
+                5,
+                Some(Amount::new(dec!(60))),
             ))
             .unwrap();
+        assert_eq!(balances(&engine), amounts(dec!(100), dec!(50), dec!(50)));
         engine
-            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .process_transaction(&make_tx(TransactionType::Dispute, 1, 5, None))
             .unwrap();
-
+        assert_eq!(balances(&engine), amounts(dec!(90), dec!(60), dec!(0)));
         engine
-            .process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None))
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 5, None))
             .unwrap();
-
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::new(dec!(3.0)));
+        assert_eq!(balances(&engine), amounts(dec!(100), dec!(50), dec!(50)));
+        assert!(engine.check_invariants().is_empty());
     }
 
     #[test]
-    fn resolve_without_active_dispute_is_rejected() {
+    fn charged_back_tx_cannot_be_disputed_again_once_the_account_is_usable() {
         let mut engine = TxEngine::new();
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Chargeback, 1, 1, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        assert!(snapshot_for(&engine, 1).locked);
+        // There is no unfreeze operation; lift the lock directly as one would.
+        engine.users.get_mut(&ClientId(1)).unwrap().lock_reason = None;
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
-                1,
-                Some(Amount::new(dec!(3.0))),
+                2,
+                Some(Amount::new(dec!(1.0))),
             ))
             .unwrap();
 
-        let result = engine.process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None));
+        let result = engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
+        match result {
+            Err(ProcessError::Rejected(rejection)) => {
+                assert_eq!(rejection.category, RejectionCategory::InvalidDispute);
+                assert!(rejection.message.contains("already charged back"));
+            }
+            other => panic!("expected a charged-back rejection, got {other:?}"),
+        }
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(0.0)));
     }
 
     #[test]
-    fn chargeback_locks_account_and_future_transactions_are_rejected() {
-        let mut engine = TxEngine::new();
+    fn undo_last_reverts_each_operation_type() {
+        type State = (
+            Vec<ClientSnapshot>,
+            ProcessingStats,
+            Vec<HeldEntry>,
+            Vec<HistoryEntry>,
+        );
+        let state = |engine: &TxEngine| -> State {
+            (
+                engine.clients_snapshot(),
+                *engine.stats(),
+                engine.held_breakdown(),
+                engine.transaction_history(ClientId(1), TxID(2)),
+            )
+        };
+        let mut engine = TxEngine::builder()
+            .resolve_scope(ResolveScope::Dispute)
+            .dedup_identical(IdenticalRowScope::Any)
+            .build();
+        let mut dispute = make_tx(TransactionType::Dispute, 1, 2, None);
+        dispute.reason = Some("fraud".to_string());
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(3.0)))),
+            make_tx(
+                TransactionType::Withdrawal,
+                1,
+                3,
+                Some(Amount::new(dec!(1.5))),
+            ),
+            dispute,
+            make_tx(TransactionType::Resolve, 1, 2, None),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(TransactionType::Chargeback, 1, 2, None),
+        ];
+
+        for tx in &workload {
+            let before = state(&engine);
+            engine.process_transaction(tx).unwrap();
+            assert_ne!(state(&engine), before, "{} changes the state", tx.op_type);
+
+            engine.undo_last().unwrap();
+            assert_eq!(state(&engine), before, "undoing {}", tx.op_type);
+            assert!(engine.check_invariants().is_empty());
+            assert!(engine.undo_last().is_err(), "only one level of undo");
+
+            // Undone rows are forgotten by the duplicate checks, so they apply again.
+            engine.process_transaction(tx).unwrap();
+        }
+        assert!(snapshot_for(&engine, 1).locked);
+    }
+
+    #[test]
+    fn undo_last_removes_account_opened_by_the_undone_deposit() {
+        let mut engine = TxEngine::builder().record_audit(true).build();
+        assert!(engine.undo_last().is_err());
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
-                Some(Amount::new(dec!(3.0))),
+                Some(Amount::new(dec!(5.0))),
             ))
             .unwrap();
         engine
-            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
-            .unwrap();
-        engine
-            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .process_transaction(&make_tx(
+                TransactionType::Deposit,
+                2,
+                2,
+                Some(Amount::new(dec!(1.0))),
+            ))
             .unwrap();
+        let rejected = make_tx(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(Amount::new(dec!(9.0))),
+        );
+        assert!(engine.process_transaction(&rejected).is_err());
 
-        let post_chargeback_tx =
-            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(1.0))));
-        let result = engine.process_transaction(&post_chargeback_tx);
+        engine.undo_last().unwrap();
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::ZERO);
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert_eq!(snapshot.total(), Amount::ZERO);
-        assert!(snapshot.locked);
+        assert!(!engine.account_exists(ClientId(2)));
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(5.0)));
+        assert_eq!(engine.audit_log().len(), 1);
+        assert_eq!(engine.stats().deposits, 1);
     }
 
     #[test]
-    fn chargeback_without_active_dispute_is_rejected() {
+    fn client_peaks_keep_the_high_water_mark() {
         let mut engine = TxEngine::new();
-        engine
-            .process_transaction(&make_tx(
-                TransactionType::Deposit,
-                1,
+        assert_eq!(engine.client_peaks(ClientId(1)), None);
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(3.0)))),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            make_tx(
+                TransactionType::Withdrawal,
                 1,
-                Some(Amount::new(dec!(3.0))),
-            ))
-            .unwrap();
-
-        let result = engine.process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None));
+                3,
+                Some(Amount::new(dec!(4.0))),
+            ),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
         let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(3.0)));
-        assert_eq!(snapshot.held, Amount::ZERO);
-        assert!(!snapshot.locked);
+        assert_eq!(snapshot.available, Amount::new(dec!(1.0)));
+        assert_eq!(snapshot.total(), Amount::new(dec!(4.0)));
+        assert_eq!(
+            engine.client_peaks(ClientId(1)),
+            Some((Amount::new(dec!(8.0)), Amount::new(dec!(8.0))))
+        );
     }
 
     #[test]
-    fn frozen_account_rejects_non_deposit_ops_too() {
-        let mut engine = TxEngine::new();
+    fn strict_lifecycle_amount_rejects_a_contradicting_resolve_or_chargeback() {
+        let lifecycle =
+            |op_type, tx_id, amount: Decimal| make_tx(op_type, 1, tx_id, Some(Amount::new(amount)));
+        let mut engine = TxEngine::builder()
+            .lifecycle_amount_policy(LifecycleAmountPolicy::Strict)
+            .build();
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(5.0)))),
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(2.5)))),
+            make_tx(TransactionType::Dispute, 1, 1, None),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+
+        engine
+            .process_transaction(&lifecycle(TransactionType::Resolve, 1, dec!(5.00)))
+            .unwrap();
+        let err = engine
+            .process_transaction(&lifecycle(TransactionType::Chargeback, 2, dec!(3.0)))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ProcessError::rejected(
+                RejectionCategory::InvalidAmount,
+                "Cannot chargeback transaction 2 for user 1: amount 3.0 does not match the 2.5 held",
+            )
+        );
+        assert_eq!(snapshot_for(&engine, 1).held, Amount::new(dec!(2.5)));
+        assert!(!snapshot_for(&engine, 1).locked);
+
+        // By default the column is not read at all.
+        let mut lenient = TxEngine::new();
+        for tx in [
+            make_tx(TransactionType::Deposit, 1, 2, Some(Amount::new(dec!(2.5)))),
+            make_tx(TransactionType::Dispute, 1, 2, None),
+            lifecycle(TransactionType::Chargeback, 2, dec!(3.0)),
+        ] {
+            lenient.process_transaction(&tx).unwrap();
+        }
+        assert!(snapshot_for(&lenient, 1).locked);
+    }
+
+    #[test]
+    fn correction_adjusts_available_and_is_audited_as_manual() {
+        let correction = |tx_id, amount: Decimal, reason: Option<&str>| Transaction {
+            reason: reason.map(str::to_string),
+            ..make_tx(
+                TransactionType::Correction,
+                1,
+                tx_id,
+                Some(Amount::new(amount)),
+            )
+        };
+        let mut engine = TxEngine::builder().record_audit(true).build();
         engine
             .process_transaction(&make_tx(
                 TransactionType::Deposit,
                 1,
                 1,
-                Some(Amount::new(dec!(4.0))),
+                Some(Amount::new(dec!(2.0))),
             ))
             .unwrap();
+
         engine
-            .process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None))
+            .process_transaction(&correction(2, dec!(1.5), Some("missed deposit")))
             .unwrap();
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(3.5)));
+        // A negative correction skips the insufficient funds check.
         engine
-            .process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None))
+            .process_transaction(&correction(3, dec!(-5.0), Some("reversed credit")))
             .unwrap();
+        assert_eq!(snapshot_for(&engine, 1).available, Amount::new(dec!(-1.5)));
+        assert_eq!(engine.stats().corrections, 2);
+        assert!(engine.verify_client(ClientId(1)).is_ok());
 
-        let resolve_result =
-            engine.process_transaction(&make_tx(TransactionType::Resolve, 1, 1, None));
-        let dispute_result =
-            engine.process_transaction(&make_tx(TransactionType::Dispute, 1, 1, None));
-        let chargeback_result =
-            engine.process_transaction(&make_tx(TransactionType::Chargeback, 1, 1, None));
+        let err = engine
+            .process_transaction(&correction(4, dec!(1.0), Some("  ")))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ProcessError::rejected(
+                RejectionCategory::MissingReason,
+                "Missing reason for correction tx 4 and client 1",
+            )
+        );
 
-        assert!(matches!(
-            resolve_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
-        assert!(matches!(
-            dispute_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
-        assert!(matches!(
-            chargeback_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
+        let manual: Vec<_> = engine
+            .audit_log()
+            .iter()
+            .map(|entry| (entry.op_type, entry.manual, entry.reason.as_deref()))
+            .collect();
+        assert_eq!(
+            manual,
+            [
+                (Some(TransactionType::Deposit), false, None),
+                (
+                    Some(TransactionType::Correction),
+                    true,
+                    Some("missed deposit")
+                ),
+                (
+                    Some(TransactionType::Correction),
+                    true,
+                    Some("reversed credit")
+                ),
+            ]
+        );
+        assert_eq!(
+            engine.audit_log()[2].available_delta,
+            Amount::new(dec!(-5.0))
+        );
+
+        for tx in [
+            make_tx(TransactionType::Deposit, 2, 5, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Dispute, 2, 5, None),
+            make_tx(TransactionType::Chargeback, 2, 5, None),
+        ] {
+            engine.process_transaction(&tx).unwrap();
+        }
+        let frozen = Transaction {
+            client: ClientId(2),
+            ..correction(6, dec!(1.0), Some("goodwill"))
+        };
+        let err = engine.process_transaction(&frozen).unwrap_err();
+        assert!(
+            matches!(err, ProcessError::Rejected(ref rejection) if rejection.category == RejectionCategory::FrozenAccount)
+        );
     }
 
     #[test]
-    fn duplicate_tx_id_is_rejected_globally_across_clients() {
-        let mut engine = TxEngine::new();
-        engine
-            .process_transaction(&make_tx(
-                TransactionType::Deposit,
+    fn recent_skips_keep_the_last_ones_in_order() {
+        let mut engine = TxEngine::builder().recent_skips(3).build();
+        let workload = [
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(1.0)))),
+            make_tx(
+                TransactionType::Withdrawal,
                 1,
-                10,
-                Some(Amount::new(dec!(1.0))),
-            ))
-            .unwrap();
-
-        let result = engine.process_transaction(&make_tx(
-            TransactionType::Deposit,
-            2,
-            10,
-            Some(Amount::new(dec!(2.0))),
-        ));
-
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        assert_eq!(engine.clients_snapshot().len(), 1);
-        let snapshot = snapshot_for(&engine, 1);
-        assert_eq!(snapshot.available, Amount::new(dec!(1.0)));
-    }
+                2,
+                Some(Amount::new(dec!(5.0))),
+            ),
+            make_tx(TransactionType::Dispute, 2, 1, None),
+            make_tx(TransactionType::Deposit, 1, 1, Some(Amount::new(dec!(1.0)))),
+            make_tx(TransactionType::Resolve, 1, 1, None),
+            make_tx(TransactionType::Deposit, 1, 3, Some(Amount::new(dec!(2.0)))),
+            make_tx(TransactionType::Withdrawal, 1, 4, None),
+        ];
+        for tx in &workload {
+            let _ = engine.process_transaction(tx);
+        }
 
-    #[test]
-    fn invalid_non_deposit_ops_for_unknown_client_do_not_create_state() {
-        let mut engine = TxEngine::new();
+        let skips: Vec<_> = engine
+            .recent_skips()
+            .iter()
+            .map(|skip| (skip.op_type, skip.tx_id, skip.rejection.category))
+            .collect();
+        assert_eq!(
+            skips,
+            [
+                (
+                    TransactionType::Deposit,
+                    TxID(1),
+                    RejectionCategory::DuplicateTx
+                ),
+                (
+                    TransactionType::Resolve,
+                    TxID(1),
+                    RejectionCategory::InvalidDispute
+                ),
+                (
+                    TransactionType::Withdrawal,
+                    TxID(4),
+                    RejectionCategory::InvalidAmount
+                ),
+            ]
+        );
+        assert_eq!(
+            engine.recent_skips()[2].rejection.message,
+            "Missing amount for withdrawal tx 4 and client 1"
+        );
 
-        let dispute_result =
-            engine.process_transaction(&make_tx(TransactionType::Dispute, 9, 1, None));
-        let resolve_result =
-            engine.process_transaction(&make_tx(TransactionType::Resolve, 9, 1, None));
-        let chargeback_result =
-            engine.process_transaction(&make_tx(TransactionType::Chargeback, 9, 1, None));
+        for tx_id in 5..=12 {
+            let _ =
+                engine.process_transaction(&make_tx(TransactionType::Withdrawal, 1, tx_id, None));
+        }
+        let tx_ids: Vec<_> = engine
+            .recent_skips()
+            .iter()
+            .map(|skip| skip.tx_id)
+            .collect();
+        assert_eq!(tx_ids, [TxID(10), TxID(11), TxID(12)]);
 
-        assert!(matches!(
-            dispute_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
-        assert!(matches!(
-            resolve_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
-        assert!(matches!(
-            chargeback_result,
-            Err(AppError::TxProcessingNonCritical(_))
-        ));
-        assert!(engine.clients_snapshot().is_empty());
+        let mut silent = TxEngine::builder().recent_skips(0).build();
+        for tx in &workload {
+            let _ = silent.process_transaction(tx);
+        }
+        assert!(silent.recent_skips().is_empty());
     }
 
     #[test]
-    fn missing_amount_for_deposit_is_rejected() {
-        let mut engine = TxEngine::new();
-        let result = engine.process_transaction(&make_tx(TransactionType::Deposit, 1, 1, None));
-
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        assert!(engine.clients_snapshot().is_empty());
-    }
+    fn resolve_all_disputes_goes_in_open_order() {
+        let mut engine = TxEngine::builder().record_audit(true).build();
+        for tx_id in 1..=4 {
+            engine
+                .process_transaction(&make_tx(
+                    TransactionType::Deposit,
+                    1,
+                    tx_id,
+                    Some(Amount::new(dec!(1.0))),
+                ))
+                .unwrap();
+        }
+        for tx_id in [3, 1, 4, 2] {
+            engine
+                .process_transaction(&make_tx(TransactionType::Dispute, 1, tx_id, None))
+                .unwrap();
+        }
+        engine
+            .process_transaction(&make_tx(TransactionType::Resolve, 1, 4, None))
+            .unwrap();
 
-    #[test]
-    fn missing_amount_for_withdrawal_is_rejected() {
-        let mut engine = TxEngine::new();
-        let result = engine.process_transaction(&make_tx(TransactionType::Withdrawal, 1, 1, None));
+        assert_eq!(engine.resolve_all_disputes(ClientId(1)).unwrap(), 3);
 
-        assert!(matches!(result, Err(AppError::TxProcessingNonCritical(_))));
-        assert!(engine.clients_snapshot().is_empty());
+        let resolved: Vec<_> = engine.audit_log()[9..]
+            .iter()
+            .map(|entry| (entry.op_type, entry.tx_id))
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                (Some(TransactionType::Resolve), Some(TxID(3))),
+                (Some(TransactionType::Resolve), Some(TxID(1))),
+                (Some(TransactionType::Resolve), Some(TxID(2))),
+            ]
+        );
     }
 }
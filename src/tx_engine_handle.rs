@@ -0,0 +1,112 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    balance_store::BalanceStore,
+    domain::errors::ProcessError,
+    io::input::Transaction,
+    tx_engine::{ClientSnapshot, TxEngine},
+};
+
+enum Command {
+    Process(Transaction, Sender<Result<(), ProcessError>>),
+    Snapshot(Sender<Vec<ClientSnapshot>>),
+}
+
+/// Sends transactions to an engine owned by a worker thread. Clones share the same
+/// worker, so producers can run on several threads while processing stays serialized
+/// in arrival order.
+#[derive(Debug, Clone)]
+pub struct TxEngineHandle {
+    commands: Sender<Command>,
+}
+
+impl TxEngineHandle {
+    /// Moves `engine` to a new worker thread. The worker stops once every handle is
+    /// dropped and returns the engine through the join handle.
+    pub fn spawn<S>(engine: TxEngine<S>) -> (TxEngineHandle, JoinHandle<TxEngine<S>>)
+    where
+        S: BalanceStore + Send + 'static,
+    {
+        let (commands, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || run_worker(engine, receiver));
+        (TxEngineHandle { commands }, worker)
+    }
+
+    pub fn process_transaction(&self, tx: Transaction) -> Result<(), ProcessError> {
+        let (reply, response) = mpsc::channel();
+        self.send(Command::Process(tx, reply))?;
+        response.recv().map_err(|_| worker_stopped())?
+    }
+
+    pub fn clients_snapshot(&self) -> Result<Vec<ClientSnapshot>, ProcessError> {
+        let (reply, response) = mpsc::channel();
+        self.send(Command::Snapshot(reply))?;
+        response.recv().map_err(|_| worker_stopped())
+    }
+
+    fn send(&self, command: Command) -> Result<(), ProcessError> {
+        self.commands.send(command).map_err(|_| worker_stopped())
+    }
+}
+
+fn run_worker<S: BalanceStore>(
+    mut engine: TxEngine<S>,
+    commands: Receiver<Command>,
+) -> TxEngine<S> {
+    for command in commands {
+        // A dropped reply receiver only means the caller stopped waiting.
+        match command {
+            Command::Process(tx, reply) => {
+                let _ = reply.send(engine.process_transaction(&tx));
+            }
+            Command::Snapshot(reply) => {
+                let _ = reply.send(engine.clients_snapshot());
+            }
+        }
+    }
+    engine
+}
+
+fn worker_stopped() -> ProcessError {
+    ProcessError::Critical("Transaction engine worker has stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{Amount, TransactionType};
+    use crate::test_utils::make_tx;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn worker_serializes_transactions_from_other_threads() {
+        let (handle, worker) = TxEngineHandle::spawn(TxEngine::new());
+
+        let producer = handle.clone();
+        thread::spawn(move || {
+            producer
+                .process_transaction(make_tx(
+                    TransactionType::Deposit,
+                    1,
+                    1,
+                    Some(Amount::new(dec!(5.0))),
+                ))
+                .unwrap();
+            producer
+                .process_transaction(make_tx(TransactionType::Dispute, 1, 1, None))
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+
+        let snapshots = handle.clients_snapshot().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].available, Amount::ZERO);
+        assert_eq!(snapshots[0].held, Amount::new(dec!(5.0)));
+
+        drop(handle);
+        let engine = worker.join().unwrap();
+        assert_eq!(engine.stats().disputes, 1);
+    }
+}
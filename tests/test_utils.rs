@@ -0,0 +1,26 @@
+//! Uses the `test_utils` helpers the way a downstream crate would, through the library.
+#![cfg(feature = "test-utils")]
+
+use rust_decimal_macros::dec;
+use tx_engine_example::{
+    domain::types::TransactionType,
+    test_utils::{assert_balances, TransactionBuilder},
+    tx_engine::TxEngine,
+};
+
+#[test]
+fn helpers_are_usable_from_outside_the_crate() {
+    let mut engine = TxEngine::new();
+    for tx in [
+        TransactionBuilder::new(TransactionType::Deposit, 3, 1)
+            .amount(dec!(4.0))
+            .build(),
+        TransactionBuilder::new(TransactionType::Withdrawal, 3, 2)
+            .amount(dec!(1.5))
+            .build(),
+    ] {
+        engine.process_transaction(&tx).unwrap();
+    }
+
+    assert_balances(&engine, 3, dec!(2.5), dec!(0), false);
+}
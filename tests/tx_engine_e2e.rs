@@ -1,6 +1,6 @@
 use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 fn unique_csv_path(test_name: &str) -> PathBuf {
@@ -12,10 +12,15 @@ fn unique_csv_path(test_name: &str) -> PathBuf {
 }
 
 fn run_engine_with_csv(test_name: &str, csv_input: &str) -> (String, String) {
+    run_engine_with_args(test_name, csv_input, &[])
+}
+
+fn run_engine_with_args(test_name: &str, csv_input: &str, args: &[&str]) -> (String, String) {
     let path = unique_csv_path(test_name);
     fs::write(&path, csv_input).expect("must write input csv");
 
     let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .args(args)
         .arg(&path)
         .output()
         .expect("must run tx-engine-example binary");
@@ -66,3 +71,423 @@ resolve,77,1,
     assert!(!stdout.contains("\n2,"));
     assert!(!stdout.contains("\n77,"));
 }
+
+#[test]
+fn e2e_no_total_flag_omits_total_column() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,5.0
+withdrawal,1,2,1.5
+";
+
+    let (stdout, _stderr) = run_engine_with_args("no_total", input, &["--no-total"]);
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(lines[0], "client,available,held,locked");
+    assert_eq!(lines[1], "1,3.5000,0.0000,false");
+    assert_eq!(lines.len(), 2);
+}
+
+#[test]
+fn e2e_strict_mode_fails_on_first_skipped_row() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,1,2,5.0
+deposit,1,3,1.0
+";
+    let path = unique_csv_path("strict");
+    fs::write(&path, input).expect("must write input csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("--strict")
+        .arg(&path)
+        .output()
+        .expect("must run tx-engine-example binary");
+
+    fs::remove_file(&path).expect("must remove temp csv");
+
+    assert!(
+        !output.status.success(),
+        "binary should fail under --strict"
+    );
+    assert!(output.stdout.is_empty(), "no snapshot should be printed");
+    let stderr = String::from_utf8(output.stderr).expect("stderr must be utf8");
+    assert!(stderr.contains("Row 2 (withdrawal,1,2)"));
+}
+
+#[test]
+fn e2e_warnings_json_lists_each_skipped_row() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,1,2,5.0
+deposit,1,1,2.0
+resolve,1,1,
+";
+    let warnings_path = unique_csv_path("warnings_json").with_extension("jsonl");
+    let warnings_arg = warnings_path.to_string_lossy().into_owned();
+
+    let (stdout, _) =
+        run_engine_with_args("warnings_json", input, &["--warnings-json", &warnings_arg]);
+
+    let warnings = fs::read_to_string(&warnings_path).expect("must read warnings file");
+    fs::remove_file(&warnings_path).expect("must remove warnings file");
+
+    assert!(stdout.starts_with("client,available,held,total,locked\n"));
+    let rows: Vec<serde_json::Value> = warnings
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line must be a JSON object"))
+        .collect();
+    let summary: Vec<(u64, &str)> = rows
+        .iter()
+        .map(|row| {
+            (
+                row["row"].as_u64().unwrap(),
+                row["category"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (2, "insufficient_funds"),
+            (3, "duplicate_tx"),
+            (4, "invalid_dispute"),
+        ]
+    );
+    assert_eq!(rows[0]["client"], 1);
+    assert_eq!(rows[0]["tx"], 2);
+    assert!(rows[0]["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("Insufficient funds"));
+}
+
+#[test]
+fn e2e_annotate_marks_each_row_applied_or_rejected() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,1.0
+withdrawal,1,2,5.0
+";
+    let annotated_path = unique_csv_path("annotate_out");
+    let annotated_arg = annotated_path.to_string_lossy().into_owned();
+
+    run_engine_with_args("annotate", input, &["--annotate", &annotated_arg]);
+
+    let annotated = fs::read_to_string(&annotated_path).expect("must read annotated csv");
+    fs::remove_file(&annotated_path).expect("must remove annotated csv");
+
+    let lines: Vec<&str> = annotated.lines().collect();
+    assert_eq!(lines[0], "type,client,tx,amount,status");
+    assert_eq!(lines[1], "deposit,1,1,1.0,applied");
+    assert!(lines[2].starts_with("withdrawal,1,2,5.0,\"rejected: Insufficient funds"));
+    assert_eq!(lines.len(), 3);
+}
+
+#[test]
+fn e2e_annotate_marks_the_row_a_parse_error_stopped_at() {
+    let input_path = unique_csv_path("annotate_parse_error_in");
+    fs::write(
+        &input_path,
+        "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,abc\ndeposit,1,3,2.0\n",
+    )
+    .expect("must write input csv");
+    let annotated_path = unique_csv_path("annotate_parse_error_out");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("--annotate")
+        .arg(&annotated_path)
+        .arg(&input_path)
+        .output()
+        .expect("must run tx-engine-example binary");
+    let annotated = fs::read_to_string(&annotated_path).expect("must read annotated csv");
+    fs::remove_file(&input_path).expect("must remove temp csv");
+    fs::remove_file(&annotated_path).expect("must remove annotated csv");
+
+    assert!(!output.status.success(), "a parse error fails the run");
+    let lines: Vec<&str> = annotated.lines().collect();
+    assert_eq!(lines[1], "deposit,1,1,1.0,applied");
+    assert!(lines[2].starts_with("deposit,1,2,abc,\"parse error: "));
+    assert_eq!(lines[3], "deposit,1,3,2.0,not_processed");
+    assert_eq!(lines.len(), 4);
+}
+
+#[test]
+fn e2e_out_writes_snapshot_to_file() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,5.0
+withdrawal,1,2,1.5
+deposit,2,3,2.0
+";
+    let out_path = unique_csv_path("out_file");
+    let out_arg = out_path.to_string_lossy().into_owned();
+
+    let (stdout, _) = run_engine_with_args("out", input, &["--out", &out_arg]);
+
+    let written = fs::read_to_string(&out_path).expect("must read output file");
+    fs::remove_file(&out_path).expect("must remove output file");
+
+    assert_eq!(stdout, "");
+    assert_eq!(
+        written,
+        "\
+client,available,held,total,locked
+1,3.5000,0.0000,3.5000,false
+2,2.0000,0.0000,2.0000,false
+"
+    );
+}
+
+#[test]
+fn e2e_self_check_passes_on_a_normal_feed() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,5.0
+withdrawal,1,2,1.5
+deposit,2,3,2.0
+dispute,2,3,
+deposit,1,4,1.0
+dispute,1,4,
+chargeback,1,4,
+";
+
+    // `run_engine_with_args` asserts a zero exit status.
+    let (stdout, stderr) = run_engine_with_args("self_check", input, &["--self-check"]);
+
+    assert_eq!(
+        stdout,
+        "\
+client,available,held,total,locked
+1,3.5000,0.0000,3.5000,true
+2,0.0000,2.0000,2.0000,false
+"
+    );
+    assert!(!stderr.contains("Self-check failed"));
+}
+
+fn run_compare(test_name: &str, first: &str, second: &str) -> (bool, String) {
+    let first_path = unique_csv_path(&format!("{test_name}_first"));
+    let second_path = unique_csv_path(&format!("{test_name}_second"));
+    fs::write(&first_path, first).expect("must write first csv");
+    fs::write(&second_path, second).expect("must write second csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("compare")
+        .arg(&first_path)
+        .arg(&second_path)
+        .output()
+        .expect("must run tx-engine-example binary");
+
+    fs::remove_file(&first_path).expect("must remove first csv");
+    fs::remove_file(&second_path).expect("must remove second csv");
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).expect("stdout must be utf8"),
+    )
+}
+
+#[test]
+fn e2e_compare_reports_equal_and_differing_final_states() {
+    let original = "\
+type,client,tx,amount
+deposit,1,1,5.0
+deposit,2,2,3.0
+withdrawal,1,3,1.0
+";
+    let reordered = "\
+type,client,tx,amount
+deposit,2,2,3.0
+deposit,1,1,5.0
+withdrawal,1,3,1.0
+";
+    let modified = "\
+type,client,tx,amount
+deposit,2,2,3.0
+deposit,1,1,5.0
+withdrawal,1,3,1.5
+";
+
+    let (success, stdout) = run_compare("compare_equal", original, reordered);
+    assert!(success);
+    assert_eq!(stdout, "Final states are identical\n");
+
+    let (success, stdout) = run_compare("compare_differ", original, modified);
+    assert!(!success);
+    assert_eq!(
+        stdout,
+        "\
+- 1,4.0000,0.0000,4.0000,false
++ 1,3.5000,0.0000,3.5000,false
+"
+    );
+}
+
+#[test]
+fn e2e_batches_reports_row_counts_per_input_file() {
+    let first_path = unique_csv_path("batches_first");
+    let second_path = unique_csv_path("batches_second");
+    fs::write(
+        &first_path,
+        "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,5.0\n",
+    )
+    .expect("must write first csv");
+    fs::write(
+        &second_path,
+        "type,client,tx,amount\ndeposit,1,3,1.0\ndeposit,abc,4,1.0\n",
+    )
+    .expect("must write second csv");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("batches")
+        .arg(&first_path)
+        .arg(&second_path)
+        .output()
+        .expect("must run tx-engine-example binary");
+
+    fs::remove_file(&first_path).expect("must remove first csv");
+    fs::remove_file(&second_path).expect("must remove second csv");
+    assert!(output.status.success(), "binary should exit successfully");
+    assert_eq!(
+        String::from_utf8(output.stdout).expect("stdout must be utf8"),
+        "client,available,held,total,locked\n1,3.0000,0.0000,3.0000,false\n"
+    );
+    let stderr = String::from_utf8(output.stderr).expect("stderr must be utf8");
+    assert!(stderr.contains("batch,applied,skipped,parse_errors\n"));
+    assert!(stderr.contains(&format!("{},1,1,0\n", first_path.display())));
+    assert!(stderr.contains(&format!("{},1,0,1\n", second_path.display())));
+}
+
+#[test]
+fn e2e_seed_snapshot_continues_from_an_earlier_run() {
+    let snapshot_path = unique_csv_path("seed_snapshot");
+    fs::write(
+        &snapshot_path,
+        "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,false\n",
+    )
+    .expect("must write snapshot csv");
+    let snapshot_arg = snapshot_path.to_str().expect("temp path must be utf8");
+
+    let csv = "\
+type,client,tx,amount
+withdrawal,1,1,2.0
+deposit,2,2,1.0
+";
+    let (stdout, _) = run_engine_with_args(
+        "seed_snapshot_feed",
+        csv,
+        &["--seed-snapshot", snapshot_arg],
+    );
+
+    fs::remove_file(&snapshot_path).expect("must remove snapshot csv");
+    assert_eq!(
+        stdout,
+        "\
+client,available,held,total,locked
+1,3.0000,0.0000,3.0000,false
+2,1.0000,0.0000,1.0000,false
+"
+    );
+}
+
+#[test]
+fn e2e_print_schema_lists_columns_and_version() {
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("--print-schema")
+        .output()
+        .expect("must run tx-engine-example binary");
+
+    assert!(output.status.success(), "binary should exit successfully");
+    let stdout = String::from_utf8(output.stdout).expect("stdout must be utf8");
+    assert_eq!(
+        stdout,
+        "\
+version: 5
+client: u16
+available: decimal(4)
+held: decimal(4)
+total: decimal(4)
+locked: bool
+"
+    );
+}
+
+#[test]
+fn e2e_auto_scale_prints_the_input_precision() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,5.25
+withdrawal,1,2,1.50
+deposit,2,3,0.10
+";
+
+    let (fixed, _stderr) = run_engine_with_csv("fixed_scale", input);
+    let (auto, _stderr) = run_engine_with_args("auto_scale", input, &["--auto-scale"]);
+
+    assert_eq!(
+        fixed,
+        "client,available,held,total,locked\n1,3.7500,0.0000,3.7500,false\n2,0.1000,0.0000,0.1000,false\n"
+    );
+    assert_eq!(
+        auto,
+        "client,available,held,total,locked\n1,3.75,0.00,3.75,false\n2,0.10,0.00,0.10,false\n"
+    );
+}
+
+#[test]
+fn e2e_integer_cents_prints_whole_cents_or_fails() {
+    let input = "\
+type,client,tx,amount
+deposit,1,1,5.00
+withdrawal,1,2,1.50
+";
+    let (stdout, _stderr) = run_engine_with_args("integer_cents", input, &["--integer-cents"]);
+    assert_eq!(
+        stdout,
+        "client,available,held,total,locked\n1,350,0,350,false\n"
+    );
+
+    let path = unique_csv_path("integer_cents_residue");
+    fs::write(&path, "type,client,tx,amount\ndeposit,1,1,3.505\n").expect("must write input csv");
+    let output = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg("--integer-cents")
+        .arg(&path)
+        .output()
+        .expect("must run tx-engine-example binary");
+    fs::remove_file(&path).expect("must remove temp csv");
+
+    assert!(!output.status.success(), "a sub-cent balance must fail");
+    assert!(output.stdout.is_empty(), "no snapshot should be printed");
+    let stderr = String::from_utf8(output.stderr).expect("stderr must be utf8");
+    assert!(stderr.contains("Balances of clients 1 are not whole minor units"));
+}
+
+#[test]
+fn e2e_closed_stdout_exits_quietly() {
+    // Enough clients that the snapshot overflows the pipe buffer.
+    let mut input = String::from("type,client,tx,amount\n");
+    for client in 1..=20_000u32 {
+        input.push_str(&format!("deposit,{client},{client},1.0\n"));
+    }
+    let path = unique_csv_path("broken_pipe");
+    fs::write(&path, input).expect("must write input csv");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tx-engine-example"))
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("must run tx-engine-example binary");
+    // Like `| head -0`: the reader closes before reading anything.
+    drop(child.stdout.take());
+    let output = child.wait_with_output().expect("must wait for the binary");
+
+    fs::remove_file(&path).expect("must remove temp csv");
+
+    let stderr = String::from_utf8(output.stderr).expect("stderr must be utf8");
+    assert!(output.status.success(), "stderr: {stderr}");
+    assert!(!stderr.contains("panicked"));
+    assert!(stderr.is_empty());
+}